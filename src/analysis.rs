@@ -0,0 +1,301 @@
+use anyhow::{bail, Context, Result};
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use std::fs::File;
+use std::path::Path;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::key::Notation;
+use crate::key_detection::{estimate_key, KeyEstimate};
+use crate::tempo::{estimate_tempo, TempoEstimate};
+
+/// Audio is analysed as mono at this rate; it keeps everything up to
+/// 5.5 kHz, which is plenty for onsets and pitch.
+pub(crate) const ANALYSIS_RATE: u32 = 11_025;
+
+/// Mono samples at [`ANALYSIS_RATE`].
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Audio {
+    pub(crate) samples: Vec<f32>,
+}
+
+impl Audio {
+    /// Downmixes interleaved samples and resamples them to
+    /// [`ANALYSIS_RATE`] by averaging each output period.
+    #[cfg(test)]
+    pub(crate) fn from_interleaved(interleaved: &[f32], channels: usize, sample_rate: u32) -> Self {
+        let mut resampler = Resampler::new(channels, sample_rate);
+        resampler.push(interleaved);
+        resampler.finish()
+    }
+
+    pub(crate) fn duration_ms(&self) -> u32 {
+        (self.samples.len() as u64 * 1_000 / u64::from(ANALYSIS_RATE)) as u32
+    }
+}
+
+/// Streaming downmix to mono at [`ANALYSIS_RATE`], so a long file never
+/// has to be held at its source rate.
+struct Resampler {
+    channels: usize,
+    step: f64,
+    position: u64,
+    sum: f32,
+    count: u32,
+    next_boundary: f64,
+    samples: Vec<f32>,
+}
+
+impl Resampler {
+    fn new(channels: usize, sample_rate: u32) -> Self {
+        let step = f64::from(sample_rate) / f64::from(ANALYSIS_RATE);
+        Self {
+            channels: channels.max(1),
+            step,
+            position: 0,
+            sum: 0.0,
+            count: 0,
+            next_boundary: step,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Adds a block of interleaved samples; output periods may span blocks.
+    fn push(&mut self, interleaved: &[f32]) {
+        for frame in interleaved.chunks(self.channels) {
+            self.sum += frame.iter().sum::<f32>() / frame.len() as f32;
+            self.count += 1;
+            self.position += 1;
+            if self.position as f64 >= self.next_boundary {
+                self.samples.push(self.sum / self.count as f32);
+                (self.sum, self.count) = (0.0, 0);
+                self.next_boundary += self.step;
+            }
+        }
+    }
+
+    fn finish(self) -> Audio {
+        Audio {
+            samples: self.samples,
+        }
+    }
+}
+
+/// Decodes the default track of a WAV, FLAC, MP3, Ogg Vorbis or M4A (AAC)
+/// file. Corrupt packets are skipped, as players do.
+pub(crate) fn decode_audio(path: &Path) -> Result<Audio> {
+    let file = File::open(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .context("unsupported audio format")?;
+    let mut format = probed.format;
+    let track = format.default_track().context("no audio track")?;
+    let track_id = track.id;
+    let sample_rate = track
+        .codec_params
+        .sample_rate
+        .context("unknown sample rate")?;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .context("unsupported audio codec")?;
+
+    let mut resampler: Option<Resampler> = None;
+    let mut buffer: Option<SampleBuffer<f32>> = None;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(err))
+                if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                break
+            }
+            Err(err) => return Err(err).context("failed to read audio"),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(err) => return Err(err).context("failed to decode audio"),
+        };
+        let resampler = resampler
+            .get_or_insert_with(|| Resampler::new(decoded.spec().channels.count(), sample_rate));
+        let buffer = buffer
+            .get_or_insert_with(|| SampleBuffer::new(decoded.capacity() as u64, *decoded.spec()));
+        buffer.copy_interleaved_ref(decoded);
+        resampler.push(buffer.samples());
+    }
+
+    match resampler {
+        Some(resampler) if resampler.position > 0 => Ok(resampler.finish()),
+        _ => bail!("no audio samples decoded"),
+    }
+}
+
+/// Calls `frame` with the magnitude spectrum (`size / 2 + 1` bins) of each
+/// Hann-windowed frame, `hop` samples apart.
+pub(crate) fn for_each_spectrum(
+    audio: &Audio,
+    size: usize,
+    hop: usize,
+    mut frame: impl FnMut(&[f32]),
+) {
+    let fft = FftPlanner::<f32>::new().plan_fft_forward(size);
+    let window: Vec<f32> = (0..size)
+        .map(|n| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * n as f32 / size as f32).cos())
+        .collect();
+    let mut buffer = vec![Complex::default(); size];
+    let mut magnitudes = vec![0.0; size / 2 + 1];
+
+    let mut start = 0;
+    while start + size <= audio.samples.len() {
+        for ((slot, sample), weight) in buffer
+            .iter_mut()
+            .zip(&audio.samples[start..start + size])
+            .zip(&window)
+        {
+            *slot = Complex::new(sample * weight, 0.0);
+        }
+        fft.process(&mut buffer);
+        for (magnitude, bin) in magnitudes.iter_mut().zip(&buffer) {
+            *magnitude = bin.norm();
+        }
+        frame(&magnitudes);
+        start += hop;
+    }
+}
+
+/// What `analyze` found in one file.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Analysis {
+    pub(crate) duration_ms: u32,
+    pub(crate) tempo: Option<TempoEstimate>,
+    pub(crate) key: Option<KeyEstimate>,
+}
+
+pub(crate) fn analyze_file(path: &Path) -> Result<Analysis> {
+    let audio = decode_audio(path)?;
+    Ok(Analysis {
+        duration_ms: audio.duration_ms(),
+        tempo: estimate_tempo(&audio),
+        key: estimate_key(&audio),
+    })
+}
+
+pub(crate) fn describe_tempo(tempo: Option<&TempoEstimate>) -> String {
+    match tempo {
+        Some(tempo) => format!(
+            "{:.1} BPM (confidence {:.2}; half-time {:.1} at {:.2}, double-time {:.1} at {:.2})",
+            tempo.bpm,
+            tempo.confidence,
+            tempo.half_time.bpm,
+            tempo.half_time.strength,
+            tempo.double_time.bpm,
+            tempo.double_time.strength
+        ),
+        None => "no steady tempo".to_string(),
+    }
+}
+
+pub(crate) fn describe_key(key: Option<&KeyEstimate>, notation: Notation) -> String {
+    match key {
+        Some(key) => format!(
+            "key {} (confidence {:.2}; runner-up {} at {:.2})",
+            key.key.format(notation),
+            key.confidence,
+            key.runner_up.key.format(notation),
+            key.runner_up.correlation
+        ),
+        None => "no clear key".to_string(),
+    }
+}
+
+/// `analyze`: prints the estimates for each file, or each audio file under
+/// each folder, without touching the database.
+pub(crate) fn run_analyze(paths: &[String], notation: Notation) -> Result<()> {
+    let mut files = Vec::new();
+    for path in paths {
+        if Path::new(path).is_dir() {
+            files.extend(crate::scan::audio_files(Path::new(path))?);
+        } else {
+            files.push(path.into());
+        }
+    }
+
+    let mut failed = 0;
+    for file in &files {
+        match analyze_file(file) {
+            Ok(analysis) => println!(
+                "{} [{}]: {}, {}",
+                file.display(),
+                crate::format_duration(analysis.duration_ms),
+                describe_tempo(analysis.tempo.as_ref()),
+                describe_key(analysis.key.as_ref(), notation)
+            ),
+            Err(err) => {
+                failed += 1;
+                println!("[UNREADABLE] {} ({err:#})", file.display());
+            }
+        }
+    }
+    println!(
+        "Analyzed {} files, {failed} unreadable.",
+        files.len() - failed
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_interleaved_downmixes_and_resamples() {
+        // One second of stereo at 44.1 kHz: left at 1.0, right at 0.0.
+        let interleaved: Vec<f32> = (0..44_100).flat_map(|_| [1.0, 0.0]).collect();
+        let audio = Audio::from_interleaved(&interleaved, 2, 44_100);
+
+        assert_eq!(audio.samples.len(), ANALYSIS_RATE as usize);
+        assert!(audio
+            .samples
+            .iter()
+            .all(|sample| (sample - 0.5).abs() < 1e-6));
+        assert_eq!(audio.duration_ms(), 1_000);
+    }
+
+    #[test]
+    fn resampler_carries_periods_across_blocks() {
+        // 44.1 kHz stereo ramp fed in MP3-packet-sized blocks, which end
+        // partway through output periods.
+        let interleaved: Vec<f32> = (0..44_100)
+            .flat_map(|n| [n as f32 / 44_100.0, 0.0])
+            .collect();
+        let mut resampler = Resampler::new(2, 44_100);
+        for block in interleaved.chunks(1_152 * 2) {
+            resampler.push(block);
+        }
+
+        assert_eq!(
+            resampler.finish(),
+            Audio::from_interleaved(&interleaved, 2, 44_100)
+        );
+    }
+}
@@ -0,0 +1,1023 @@
+use anyhow::{anyhow, bail, Result};
+
+use crate::conflicts::ConflictPolicy;
+use crate::harmonic::SortMode;
+use crate::key::Notation;
+use crate::pitch::PitchOptions;
+use crate::MATCH_CACHE_PATH;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct MetadataArgs {
+    pub(crate) exportify_csv_paths: Vec<String>,
+    pub(crate) rekordbox_xml_paths: Vec<String>,
+    pub(crate) traktor_nml_paths: Vec<String>,
+    /// Serato `database V2`, `.crate` or history CSV files.
+    pub(crate) serato_paths: Vec<String>,
+    pub(crate) engine_db_paths: Vec<String>,
+    pub(crate) virtualdj_xml_paths: Vec<String>,
+    pub(crate) csv_paths: Vec<String>,
+    /// Preset name or mapping file used to read every `--csv` file.
+    pub(crate) csv_mapping: Option<String>,
+    pub(crate) overrides_path: Option<String>,
+    pub(crate) local_db_path: Option<String>,
+    /// Sources file listing named metadata layers, `sources.json` by default.
+    pub(crate) sources_path: Option<String>,
+}
+
+/// Field values given to `db add` / `db edit`; `None` leaves a field unchanged.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct TrackFields {
+    pub(crate) name: Option<String>,
+    pub(crate) artist: Option<String>,
+    pub(crate) bpm: Option<f32>,
+    pub(crate) key_camelot: Option<String>,
+    pub(crate) duration_ms: Option<u32>,
+    pub(crate) clear_duration: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum EntrySelector {
+    /// 1-based position, as printed by `db search`.
+    Index(usize),
+    /// `Artist - Title`, compared after normalization.
+    Track(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PromoteMode {
+    /// Append rows without a curated counterpart; never touch curated values.
+    AddMissing,
+    /// Report what would be added and every conflict without writing.
+    ShowConflicts,
+    /// Append missing rows and replace conflicting curated BPM/key values.
+    Overwrite,
+}
+
+impl PromoteMode {
+    fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "add-missing" => Some(Self::AddMissing),
+            "show-conflicts" => Some(Self::ShowConflicts),
+            "overwrite" => Some(Self::Overwrite),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum DbCommand {
+    Add(TrackFields),
+    Edit {
+        selector: EntrySelector,
+        fields: TrackFields,
+    },
+    Remove {
+        selector: EntrySelector,
+    },
+    Search {
+        query: String,
+    },
+    Promote {
+        mode: PromoteMode,
+    },
+    ImportTemplate {
+        template_path: String,
+    },
+    Check,
+    Migrate,
+    /// Copies the database into a new file of the other format.
+    Convert {
+        target_path: String,
+    },
+}
+
+/// Options of the default command, which sorts a playlist.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SortArgs {
+    pub(crate) playlist_input: String,
+    pub(crate) interactive: bool,
+    /// `None` when `--no-match-cache` disables the cache.
+    pub(crate) match_cache_path: Option<String>,
+    pub(crate) missing_template_path: Option<String>,
+    pub(crate) conflict_policy: ConflictPolicy,
+    /// Where to write every source conflict as JSON.
+    pub(crate) conflict_report_path: Option<String>,
+    pub(crate) sort_mode: SortMode,
+    /// Allowed harmonic moves; `harmonic_rules.json` is used when it exists.
+    pub(crate) harmonic_rules_path: Option<String>,
+    pub(crate) pitch: PitchOptions,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Command {
+    Sort(SortArgs),
+    Explain {
+        track_input: String,
+        duration_ms: Option<u32>,
+        conflict_policy: ConflictPolicy,
+    },
+    Db(DbCommand),
+    /// Read BPM and key tags from the audio files under `folder`.
+    Scan {
+        folder: String,
+        mode: PromoteMode,
+        /// Estimate the BPM and key of files missing those tags from their audio.
+        analyze: bool,
+    },
+    /// Estimate the BPM and key of audio files without touching the database.
+    Analyze {
+        paths: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CliArgs {
+    pub(crate) binary_name: String,
+    pub(crate) metadata: MetadataArgs,
+    /// How reports and prompts write keys.
+    pub(crate) key_notation: Notation,
+    pub(crate) command: Command,
+}
+
+pub(crate) fn usage(binary_name: &str) -> String {
+    format!(
+        "Usage: {binary_name} [--local-db <path> | --sources <path>] [--exportify-csv <path>]... [--rekordbox-xml <path>]... [--traktor-nml <path>]... [--serato <path>]... [--engine-db <path>]... [--virtualdj-xml <path>]... [--csv <path>]... [--csv-mapping <preset|mapping.json>] [--overrides <path>] [--interactive] [--match-cache <path> | --no-match-cache] [--missing-template <path.csv|path.json>] [--conflict-policy priority|majority|most-recent|unresolved] [--conflict-report <path.json>] [--sort-mode wheel|harmonic] [--harmonic-rules <path.json>] [--pitch-shift [--target-bpm <bpm>]] [--suggest-pitch] <playlist_id_or_url>\n       {binary_name} explain [--local-db <path> | --sources <path>] [--exportify-csv <path>]... [--rekordbox-xml <path>]... [--traktor-nml <path>]... [--serato <path>]... [--engine-db <path>]... [--virtualdj-xml <path>]... [--csv <path>]... [--csv-mapping <preset|mapping.json>] [--overrides <path>] [--conflict-policy priority|majority|most-recent|unresolved] [--duration <m:ss>] <track_id_or_url | \"Artist - Title\">\n       {binary_name} db add [--local-db <path>] --name <title> --artist <artist> --bpm <bpm> --key <key> [--duration <m:ss>]\n       {binary_name} db edit [--local-db <path>] (--index <n> | \"Artist - Title\") [--name <title>] [--artist <artist>] [--bpm <bpm>] [--key <key>] [--duration <m:ss> | --clear-duration]\n       {binary_name} db rm [--local-db <path>] (--index <n> | \"Artist - Title\")\n       {binary_name} db search [--local-db <path>] <query>\n       {binary_name} db promote [--local-db <path>] (--exportify-csv <path> | --rekordbox-xml <path> | --traktor-nml <path> | --serato <path> | --engine-db <path> | --virtualdj-xml <path> | --csv <path>)... [--csv-mapping <preset|mapping.json>] [--mode add-missing|show-conflicts|overwrite]\n       {binary_name} db import-template [--local-db <path>] <path.csv|path.json>\n       {binary_name} db check [--local-db <path>]\n       {binary_name} db migrate [--local-db <path>]\n       {binary_name} db convert [--local-db <path>] --to <path.json|path.sqlite>\n       {binary_name} scan [--local-db <path>] [--mode add-missing|show-conflicts|overwrite] [--analyze] <music_folder>\n       {binary_name} analyze <audio_file_or_folder>...\n\nEvery command also takes --key-notation camelot|open-key|musical|spotify for the keys it prints.\n\nExamples:\n  {binary_name} 2nOsiUa2nlXBGuDMjDIbDb\n  {binary_name} https://open.spotify.com/playlist/2nOsiUa2nlXBGuDMjDIbDb\n  {binary_name} --exportify-csv exports/playlist.csv spotify:playlist:2nOsiUa2nlXBGuDMjDIbDb\n  {binary_name} explain --duration 4:08 \"FISHER - Losing It\"\n  {binary_name} db edit \"FISHER - Losing It\" --bpm 126"
+    )
+}
+
+/// Returns the value of `arg` when it is `name <value>` or `name=<value>`.
+fn flag_value<I>(name: &str, arg: &str, args: &mut I, binary_name: &str) -> Result<Option<String>>
+where
+    I: Iterator<Item = String>,
+{
+    let value = if arg == name {
+        args.next()
+    } else if let Some(value) = arg
+        .strip_prefix(name)
+        .and_then(|rest| rest.strip_prefix('='))
+    {
+        Some(value.to_string())
+    } else {
+        return Ok(None);
+    };
+
+    match value {
+        Some(value) if !value.is_empty() => Ok(Some(value)),
+        _ => bail!("missing value for {name}\n\n{}", usage(binary_name)),
+    }
+}
+
+/// `--key-notation`, accepted by every command.
+fn parse_notation_flag<I>(
+    arg: &str,
+    args: &mut I,
+    notation: &mut Notation,
+    binary_name: &str,
+) -> Result<bool>
+where
+    I: Iterator<Item = String>,
+{
+    let Some(raw) = flag_value("--key-notation", arg, args, binary_name)? else {
+        return Ok(false);
+    };
+    *notation = Notation::parse(&raw).ok_or_else(|| {
+        anyhow!(
+            "invalid --key-notation value: {raw}\n\n{}",
+            usage(binary_name)
+        )
+    })?;
+    Ok(true)
+}
+
+fn parse_metadata_flag<I>(
+    arg: &str,
+    args: &mut I,
+    metadata: &mut MetadataArgs,
+    binary_name: &str,
+) -> Result<bool>
+where
+    I: Iterator<Item = String>,
+{
+    if let Some(path) = flag_value("--exportify-csv", arg, args, binary_name)? {
+        metadata.exportify_csv_paths.push(path);
+    } else if let Some(path) = flag_value("--rekordbox-xml", arg, args, binary_name)? {
+        metadata.rekordbox_xml_paths.push(path);
+    } else if let Some(path) = flag_value("--traktor-nml", arg, args, binary_name)? {
+        metadata.traktor_nml_paths.push(path);
+    } else if let Some(path) = flag_value("--serato", arg, args, binary_name)? {
+        metadata.serato_paths.push(path);
+    } else if let Some(path) = flag_value("--engine-db", arg, args, binary_name)? {
+        metadata.engine_db_paths.push(path);
+    } else if let Some(path) = flag_value("--virtualdj-xml", arg, args, binary_name)? {
+        metadata.virtualdj_xml_paths.push(path);
+    } else if let Some(path) = flag_value("--csv", arg, args, binary_name)? {
+        metadata.csv_paths.push(path);
+    } else if let Some(mapping) = flag_value("--csv-mapping", arg, args, binary_name)? {
+        metadata.csv_mapping = Some(mapping);
+    } else if let Some(path) = flag_value("--overrides", arg, args, binary_name)? {
+        metadata.overrides_path = Some(path);
+    } else if let Some(path) = flag_value("--local-db", arg, args, binary_name)? {
+        metadata.local_db_path = Some(path);
+    } else if let Some(path) = flag_value("--sources", arg, args, binary_name)? {
+        metadata.sources_path = Some(path);
+    } else {
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Parses `m:ss` or a plain millisecond count.
+pub(crate) fn parse_duration(raw: &str) -> Option<u32> {
+    let raw = raw.trim();
+    match raw.split_once(':') {
+        Some((minutes, seconds)) => {
+            let minutes: u32 = minutes.parse().ok()?;
+            let seconds: u32 = seconds.parse().ok()?;
+            if seconds >= 60 {
+                return None;
+            }
+            minutes
+                .checked_mul(60)?
+                .checked_add(seconds)?
+                .checked_mul(1_000)
+        }
+        None => raw.parse().ok(),
+    }
+}
+
+fn parse_mode_flag(raw: &str) -> Result<PromoteMode> {
+    PromoteMode::parse(raw).ok_or_else(|| anyhow!("invalid --mode value: {raw}"))
+}
+
+fn parse_duration_flag(raw: &str) -> Result<u32> {
+    parse_duration(raw).ok_or_else(|| anyhow!("invalid --duration value: {raw}"))
+}
+
+/// Parses everything after `db`: the action, its flags and its positional.
+fn parse_db_args<I>(
+    args: &mut I,
+    metadata: &mut MetadataArgs,
+    notation: &mut Notation,
+    binary_name: &str,
+) -> Result<Command>
+where
+    I: Iterator<Item = String>,
+{
+    let action = args.next().unwrap_or_default();
+    if !matches!(
+        action.as_str(),
+        "add"
+            | "edit"
+            | "rm"
+            | "search"
+            | "promote"
+            | "import-template"
+            | "check"
+            | "migrate"
+            | "convert"
+    ) {
+        bail!("unknown db action '{action}'\n\n{}", usage(binary_name));
+    }
+
+    let mut fields = TrackFields::default();
+    let mut index = None;
+    let mut positional = None;
+    let mut mode = None;
+    let mut target_path = None;
+
+    while let Some(arg) = args.next() {
+        if parse_metadata_flag(&arg, args, metadata, binary_name)?
+            || parse_notation_flag(&arg, args, notation, binary_name)?
+        {
+            continue;
+        }
+        if arg == "-h" || arg == "--help" {
+            bail!("{}", usage(binary_name));
+        } else if arg == "--clear-duration" && action == "edit" {
+            fields.clear_duration = true;
+        } else if let Some(value) = flag_value("--name", &arg, args, binary_name)? {
+            fields.name = Some(value);
+        } else if let Some(value) = flag_value("--artist", &arg, args, binary_name)? {
+            fields.artist = Some(value);
+        } else if let Some(value) = flag_value("--key", &arg, args, binary_name)? {
+            fields.key_camelot = Some(value);
+        } else if let Some(raw) = flag_value("--bpm", &arg, args, binary_name)? {
+            let bpm = raw
+                .parse()
+                .map_err(|_| anyhow!("invalid --bpm value: {raw}"))?;
+            fields.bpm = Some(bpm);
+        } else if let Some(raw) = flag_value("--duration", &arg, args, binary_name)? {
+            fields.duration_ms = Some(parse_duration_flag(&raw)?);
+        } else if let Some(raw) = flag_value("--index", &arg, args, binary_name)? {
+            let number = raw
+                .parse()
+                .map_err(|_| anyhow!("invalid --index value: {raw}"))?;
+            index = Some(number);
+        } else if let Some(path) = flag_value("--to", &arg, args, binary_name)? {
+            if action != "convert" {
+                bail!("--to only applies to db convert\n\n{}", usage(binary_name));
+            }
+            target_path = Some(path);
+        } else if let Some(raw) = flag_value("--mode", &arg, args, binary_name)? {
+            if action != "promote" {
+                bail!(
+                    "--mode only applies to db promote\n\n{}",
+                    usage(binary_name)
+                );
+            }
+            mode = Some(parse_mode_flag(&raw)?);
+        } else if arg.starts_with('-') {
+            bail!("unknown option: {arg}\n\n{}", usage(binary_name));
+        } else if positional.replace(arg).is_some() {
+            bail!("{}", usage(binary_name));
+        }
+    }
+
+    if matches!(
+        action.as_str(),
+        "rm" | "search" | "promote" | "import-template" | "check" | "migrate" | "convert"
+    ) && fields != TrackFields::default()
+    {
+        bail!(
+            "db {action} does not take track fields\n\n{}",
+            usage(binary_name)
+        );
+    }
+
+    let selector = match (index, positional.clone()) {
+        (Some(number), None) => Some(EntrySelector::Index(number)),
+        (None, Some(track)) => Some(EntrySelector::Track(track)),
+        (None, None) => None,
+        (Some(_), Some(_)) => bail!("pass either --index or 'Artist - Title', not both"),
+    };
+
+    let command = match action.as_str() {
+        "add" if selector.is_none() => DbCommand::Add(fields),
+        "edit" => DbCommand::Edit {
+            selector: selector.ok_or_else(|| anyhow!(usage(binary_name)))?,
+            fields,
+        },
+        "rm" => DbCommand::Remove {
+            selector: selector.ok_or_else(|| anyhow!(usage(binary_name)))?,
+        },
+        "search" if index.is_none() => DbCommand::Search {
+            query: positional.ok_or_else(|| anyhow!(usage(binary_name)))?,
+        },
+        "import-template" if index.is_none() => DbCommand::ImportTemplate {
+            template_path: positional.ok_or_else(|| anyhow!(usage(binary_name)))?,
+        },
+        "check" if selector.is_none() => DbCommand::Check,
+        "migrate" if selector.is_none() => DbCommand::Migrate,
+        "convert" if selector.is_none() => DbCommand::Convert {
+            target_path: target_path.ok_or_else(|| anyhow!("db convert needs --to <path>"))?,
+        },
+        "promote" if selector.is_none() => DbCommand::Promote {
+            mode: mode.unwrap_or(PromoteMode::AddMissing),
+        },
+        _ => bail!("{}", usage(binary_name)),
+    };
+    Ok(Command::Db(command))
+}
+
+/// Parses everything after `scan`: the music folder, `--local-db`, `--mode`
+/// and `--analyze`.
+fn parse_scan_args<I>(
+    args: &mut I,
+    metadata: &mut MetadataArgs,
+    notation: &mut Notation,
+    binary_name: &str,
+) -> Result<Command>
+where
+    I: Iterator<Item = String>,
+{
+    let mut folder = None;
+    let mut mode = None;
+    let mut analyze = false;
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            bail!("{}", usage(binary_name));
+        } else if arg == "--analyze" {
+            analyze = true;
+        } else if parse_notation_flag(&arg, args, notation, binary_name)? {
+            continue;
+        } else if let Some(path) = flag_value("--local-db", &arg, args, binary_name)? {
+            metadata.local_db_path = Some(path);
+        } else if let Some(raw) = flag_value("--mode", &arg, args, binary_name)? {
+            mode = Some(parse_mode_flag(&raw)?);
+        } else if arg.starts_with('-') {
+            bail!("unknown option: {arg}\n\n{}", usage(binary_name));
+        } else if folder.replace(arg).is_some() {
+            bail!("{}", usage(binary_name));
+        }
+    }
+
+    Ok(Command::Scan {
+        folder: folder.ok_or_else(|| anyhow!(usage(binary_name)))?,
+        mode: mode.unwrap_or(PromoteMode::AddMissing),
+        analyze,
+    })
+}
+
+/// Parses everything after `analyze`: one or more files or folders.
+fn parse_analyze_args<I>(
+    args: &mut I,
+    notation: &mut Notation,
+    binary_name: &str,
+) -> Result<Command>
+where
+    I: Iterator<Item = String>,
+{
+    let mut paths = Vec::new();
+    while let Some(arg) = args.next() {
+        if parse_notation_flag(&arg, args, notation, binary_name)? {
+            continue;
+        } else if arg == "-h" || arg == "--help" {
+            bail!("{}", usage(binary_name));
+        } else if arg.starts_with('-') {
+            bail!("unknown option: {arg}\n\n{}", usage(binary_name));
+        }
+        paths.push(arg);
+    }
+    if paths.is_empty() {
+        bail!("{}", usage(binary_name));
+    }
+    Ok(Command::Analyze { paths })
+}
+
+pub(crate) fn parse_args_from<I>(args: I) -> Result<CliArgs>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter().peekable();
+    let binary_name = args
+        .next()
+        .unwrap_or_else(|| "spotify-key-bpm-sorter".to_string());
+    let mut metadata = MetadataArgs::default();
+    let mut key_notation = Notation::default();
+
+    if args.next_if(|arg| arg == "db").is_some() {
+        let command = parse_db_args(&mut args, &mut metadata, &mut key_notation, &binary_name)?;
+        return Ok(CliArgs {
+            binary_name,
+            metadata,
+            key_notation,
+            command,
+        });
+    }
+
+    if args.next_if(|arg| arg == "scan").is_some() {
+        let command = parse_scan_args(&mut args, &mut metadata, &mut key_notation, &binary_name)?;
+        return Ok(CliArgs {
+            binary_name,
+            metadata,
+            key_notation,
+            command,
+        });
+    }
+
+    if args.next_if(|arg| arg == "analyze").is_some() {
+        let command = parse_analyze_args(&mut args, &mut key_notation, &binary_name)?;
+        return Ok(CliArgs {
+            binary_name,
+            metadata,
+            key_notation,
+            command,
+        });
+    }
+
+    let subcommand = args.next_if(|arg| arg == "explain");
+
+    let mut positional = None;
+    let mut interactive = false;
+    let mut match_cache_path = Some(MATCH_CACHE_PATH.to_string());
+    let mut missing_template_path = None;
+    let mut conflict_policy = ConflictPolicy::default();
+    let mut conflict_report_path = None;
+    let mut sort_mode = SortMode::default();
+    let mut harmonic_rules_path = None;
+    let mut pitch = PitchOptions::default();
+    let mut duration_ms = None;
+
+    while let Some(arg) = args.next() {
+        if parse_metadata_flag(&arg, &mut args, &mut metadata, &binary_name)?
+            || parse_notation_flag(&arg, &mut args, &mut key_notation, &binary_name)?
+        {
+            continue;
+        }
+
+        match arg.as_str() {
+            "-h" | "--help" => bail!("{}", usage(&binary_name)),
+            "--interactive" if subcommand.is_none() => interactive = true,
+            "--no-match-cache" if subcommand.is_none() => match_cache_path = None,
+            "--pitch-shift" if subcommand.is_none() => pitch.shift = true,
+            "--suggest-pitch" if subcommand.is_none() => pitch.suggest = true,
+            _ => {
+                if let Some(path) = flag_value("--match-cache", &arg, &mut args, &binary_name)? {
+                    if subcommand.is_some() {
+                        bail!(
+                            "--match-cache only applies to sorting\n\n{}",
+                            usage(&binary_name)
+                        );
+                    }
+                    match_cache_path = Some(path);
+                } else if let Some(path) =
+                    flag_value("--missing-template", &arg, &mut args, &binary_name)?
+                {
+                    if subcommand.is_some() {
+                        bail!(
+                            "--missing-template only applies to sorting\n\n{}",
+                            usage(&binary_name)
+                        );
+                    }
+                    missing_template_path = Some(path);
+                } else if let Some(raw) =
+                    flag_value("--conflict-policy", &arg, &mut args, &binary_name)?
+                {
+                    conflict_policy = ConflictPolicy::parse(&raw).ok_or_else(|| {
+                        anyhow!(
+                            "invalid --conflict-policy value: {raw}\n\n{}",
+                            usage(&binary_name)
+                        )
+                    })?;
+                } else if let Some(path) =
+                    flag_value("--conflict-report", &arg, &mut args, &binary_name)?
+                {
+                    if subcommand.is_some() {
+                        bail!(
+                            "--conflict-report only applies to sorting\n\n{}",
+                            usage(&binary_name)
+                        );
+                    }
+                    conflict_report_path = Some(path);
+                } else if let Some(raw) = flag_value("--sort-mode", &arg, &mut args, &binary_name)?
+                {
+                    if subcommand.is_some() {
+                        bail!(
+                            "--sort-mode only applies to sorting\n\n{}",
+                            usage(&binary_name)
+                        );
+                    }
+                    sort_mode = SortMode::parse(&raw).ok_or_else(|| {
+                        anyhow!(
+                            "invalid --sort-mode value: {raw}\n\n{}",
+                            usage(&binary_name)
+                        )
+                    })?;
+                } else if let Some(path) =
+                    flag_value("--harmonic-rules", &arg, &mut args, &binary_name)?
+                {
+                    if subcommand.is_some() {
+                        bail!(
+                            "--harmonic-rules only applies to sorting\n\n{}",
+                            usage(&binary_name)
+                        );
+                    }
+                    harmonic_rules_path = Some(path);
+                } else if let Some(raw) = flag_value("--target-bpm", &arg, &mut args, &binary_name)?
+                {
+                    if subcommand.is_some() {
+                        bail!(
+                            "--target-bpm only applies to sorting\n\n{}",
+                            usage(&binary_name)
+                        );
+                    }
+                    let bpm = raw
+                        .parse()
+                        .ok()
+                        .filter(|bpm: &f32| bpm.is_finite() && *bpm > 0.0)
+                        .ok_or_else(|| anyhow!("invalid --target-bpm value: {raw}"))?;
+                    pitch.target_bpm = Some(bpm);
+                } else if let Some(raw) = flag_value("--duration", &arg, &mut args, &binary_name)? {
+                    if subcommand.is_none() {
+                        bail!(
+                            "--duration only applies to explain\n\n{}",
+                            usage(&binary_name)
+                        );
+                    }
+                    duration_ms = Some(parse_duration_flag(&raw)?);
+                } else if arg.starts_with('-') {
+                    bail!("unknown option: {arg}\n\n{}", usage(&binary_name));
+                } else if positional.replace(arg).is_some() {
+                    bail!("{}", usage(&binary_name));
+                }
+            }
+        }
+    }
+
+    if pitch.target_bpm.is_some() && !pitch.shift {
+        bail!(
+            "--target-bpm needs --pitch-shift\n\n{}",
+            usage(&binary_name)
+        );
+    }
+
+    let positional = positional.ok_or_else(|| anyhow!(usage(&binary_name)))?;
+    let command = match subcommand {
+        Some(_) => Command::Explain {
+            track_input: positional,
+            duration_ms,
+            conflict_policy,
+        },
+        None => Command::Sort(SortArgs {
+            playlist_input: positional,
+            interactive,
+            match_cache_path,
+            missing_template_path,
+            conflict_policy,
+            conflict_report_path,
+            sort_mode,
+            harmonic_rules_path,
+            pitch,
+        }),
+    };
+
+    Ok(CliArgs {
+        binary_name,
+        metadata,
+        key_notation,
+        command,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(raw: &[&str]) -> Vec<String> {
+        raw.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_args_accepts_repeated_exportify_flags() {
+        let cli = parse_args_from(args(&[
+            "mix_sorter",
+            "--exportify-csv",
+            "one.csv",
+            "--exportify-csv=two.csv",
+            "spotify:playlist:2nOsiUa2nlXBGuDMjDIbDb",
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            cli,
+            CliArgs {
+                binary_name: "mix_sorter".to_string(),
+                metadata: MetadataArgs {
+                    exportify_csv_paths: vec!["one.csv".to_string(), "two.csv".to_string()],
+                    rekordbox_xml_paths: Vec::new(),
+                    traktor_nml_paths: Vec::new(),
+                    serato_paths: Vec::new(),
+                    engine_db_paths: Vec::new(),
+                    virtualdj_xml_paths: Vec::new(),
+                    csv_paths: Vec::new(),
+                    csv_mapping: None,
+                    overrides_path: None,
+                    local_db_path: None,
+                    sources_path: None,
+                },
+                key_notation: Notation::Camelot,
+                command: Command::Sort(SortArgs {
+                    playlist_input: "spotify:playlist:2nOsiUa2nlXBGuDMjDIbDb".to_string(),
+                    interactive: false,
+                    match_cache_path: Some(MATCH_CACHE_PATH.to_string()),
+                    missing_template_path: None,
+                    conflict_policy: ConflictPolicy::Priority,
+                    conflict_report_path: None,
+                    sort_mode: SortMode::Wheel,
+                    harmonic_rules_path: None,
+                    pitch: PitchOptions::default(),
+                }),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_args_reads_explain_subcommand() {
+        let cli = parse_args_from(args(&[
+            "mix_sorter",
+            "explain",
+            "--overrides=mine.json",
+            "--duration",
+            "4:08",
+            "FISHER - Losing It",
+        ]))
+        .unwrap();
+
+        assert_eq!(cli.metadata.overrides_path.as_deref(), Some("mine.json"));
+        assert_eq!(
+            cli.command,
+            Command::Explain {
+                track_input: "FISHER - Losing It".to_string(),
+                duration_ms: Some(248_000),
+                conflict_policy: ConflictPolicy::Priority,
+            }
+        );
+
+        let err = parse_args_from(args(&["mix_sorter", "--duration", "1:00", "id"])).unwrap_err();
+        assert!(err.to_string().contains("only applies to explain"));
+        let err =
+            parse_args_from(args(&["mix_sorter", "explain", "--interactive", "id"])).unwrap_err();
+        assert!(err.to_string().contains("unknown option"));
+    }
+
+    #[test]
+    fn parse_args_reads_match_cache_flags() {
+        let cli = parse_args_from(args(&["mix_sorter", "--no-match-cache", "id"])).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Sort(SortArgs {
+                match_cache_path: None,
+                ..
+            })
+        ));
+
+        let cli = parse_args_from(args(&["mix_sorter", "--match-cache=team.json", "id"])).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Sort(SortArgs {
+                match_cache_path: Some(path),
+                ..
+            }) if path == "team.json"
+        ));
+    }
+
+    #[test]
+    fn parse_args_reads_missing_template_flag() {
+        let cli = parse_args_from(args(&[
+            "mix_sorter",
+            "--missing-template",
+            "todo.csv",
+            "id",
+        ]))
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Sort(SortArgs {
+                missing_template_path: Some(path),
+                ..
+            }) if path == "todo.csv"
+        ));
+    }
+
+    #[test]
+    fn parse_args_reads_conflict_flags() {
+        let cli = parse_args_from(args(&[
+            "mix_sorter",
+            "--conflict-policy=majority",
+            "--conflict-report",
+            "conflicts.json",
+            "id",
+        ]))
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Sort(SortArgs {
+                conflict_policy: ConflictPolicy::Majority,
+                conflict_report_path: Some(path),
+                ..
+            }) if path == "conflicts.json"
+        ));
+        let err = parse_args_from(args(&["mix_sorter", "--conflict-policy", "newest", "id"]))
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("invalid --conflict-policy value: newest"));
+
+        let cli = parse_args_from(args(&[
+            "mix_sorter",
+            "explain",
+            "--conflict-policy",
+            "unresolved",
+            "--duration=4:08",
+            "A - B",
+        ]))
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Explain {
+                conflict_policy: ConflictPolicy::Unresolved,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn parse_args_reads_sort_mode_and_rules() {
+        let cli = parse_args_from(args(&[
+            "mix_sorter",
+            "--sort-mode=harmonic",
+            "--harmonic-rules",
+            "rules.json",
+            "id",
+        ]))
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Sort(SortArgs {
+                sort_mode: SortMode::Harmonic,
+                harmonic_rules_path: Some(path),
+                ..
+            }) if path == "rules.json"
+        ));
+        assert!(parse_args_from(args(&[
+            "mix_sorter",
+            "explain",
+            "--sort-mode=harmonic",
+            "A - B"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn parse_args_reads_pitch_flags() {
+        let cli = parse_args_from(args(&[
+            "mix_sorter",
+            "--pitch-shift",
+            "--target-bpm",
+            "126",
+            "--suggest-pitch",
+            "id",
+        ]))
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Sort(SortArgs {
+                pitch: PitchOptions {
+                    shift: true,
+                    target_bpm: Some(bpm),
+                    suggest: true,
+                },
+                ..
+            }) if bpm == 126.0
+        ));
+        assert!(parse_args_from(args(&["mix_sorter", "--target-bpm=126", "id"])).is_err());
+        assert!(parse_args_from(args(&[
+            "mix_sorter",
+            "--pitch-shift",
+            "--target-bpm=0",
+            "id"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn parse_args_reads_db_subcommands() {
+        let cli = parse_args_from(args(&[
+            "mix_sorter",
+            "db",
+            "add",
+            "--local-db=crates.json",
+            "--name",
+            "Losing It",
+            "--artist=FISHER",
+            "--bpm",
+            "125",
+            "--key",
+            "10b",
+            "--duration",
+            "4:08",
+        ]))
+        .unwrap();
+        assert_eq!(cli.metadata.local_db_path.as_deref(), Some("crates.json"));
+        assert_eq!(
+            cli.command,
+            Command::Db(DbCommand::Add(TrackFields {
+                name: Some("Losing It".to_string()),
+                artist: Some("FISHER".to_string()),
+                bpm: Some(125.0),
+                key_camelot: Some("10b".to_string()),
+                duration_ms: Some(248_000),
+                clear_duration: false,
+            }))
+        );
+
+        let cli = parse_args_from(args(&["mix_sorter", "db", "rm", "--index", "12"])).unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Db(DbCommand::Remove {
+                selector: EntrySelector::Index(12)
+            })
+        );
+
+        let err = parse_args_from(args(&[
+            "mix_sorter",
+            "db",
+            "rm",
+            "--bpm",
+            "120",
+            "--index",
+            "1",
+        ]))
+        .unwrap_err();
+        assert!(err.to_string().contains("does not take track fields"));
+        let cli = parse_args_from(args(&[
+            "mix_sorter",
+            "db",
+            "promote",
+            "--exportify-csv",
+            "a.csv",
+            "--mode=show-conflicts",
+        ]))
+        .unwrap();
+        assert_eq!(cli.metadata.exportify_csv_paths, vec!["a.csv".to_string()]);
+        assert_eq!(
+            cli.command,
+            Command::Db(DbCommand::Promote {
+                mode: PromoteMode::ShowConflicts
+            })
+        );
+
+        let cli = parse_args_from(args(&[
+            "mix_sorter",
+            "db",
+            "convert",
+            "--to",
+            "team.sqlite",
+        ]))
+        .unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Db(DbCommand::Convert {
+                target_path: "team.sqlite".to_string()
+            })
+        );
+        let err =
+            parse_args_from(args(&["mix_sorter", "db", "check", "--to", "x.db"])).unwrap_err();
+        assert!(err.to_string().contains("--to only applies to db convert"));
+
+        let err = parse_args_from(args(&["mix_sorter", "db", "drop"])).unwrap_err();
+        assert!(err.to_string().contains("unknown db action"));
+    }
+
+    #[test]
+    fn parse_args_reads_scan_subcommand() {
+        let cli = parse_args_from(args(&[
+            "mix_sorter",
+            "scan",
+            "--local-db",
+            "mine.json",
+            "~/Music",
+            "--mode",
+            "overwrite",
+            "--analyze",
+        ]))
+        .unwrap();
+        assert_eq!(cli.metadata.local_db_path.as_deref(), Some("mine.json"));
+        assert_eq!(
+            cli.command,
+            Command::Scan {
+                folder: "~/Music".to_string(),
+                mode: PromoteMode::Overwrite,
+                analyze: true,
+            }
+        );
+
+        let cli = parse_args_from(args(&["mix_sorter", "analyze", "a.flac", "Sets"])).unwrap();
+        assert_eq!(
+            cli.command,
+            Command::Analyze {
+                paths: vec!["a.flac".to_string(), "Sets".to_string()]
+            }
+        );
+
+        let err =
+            parse_args_from(args(&["mix_sorter", "scan", "--exportify-csv", "a.csv"])).unwrap_err();
+        assert!(err.to_string().contains("unknown option: --exportify-csv"));
+    }
+
+    #[test]
+    fn parse_args_reads_key_notation() {
+        let cli = parse_args_from(args(&[
+            "mix_sorter",
+            "analyze",
+            "a.flac",
+            "--key-notation",
+            "open-key",
+            "Sets",
+        ]))
+        .unwrap();
+        assert_eq!(cli.key_notation, Notation::OpenKey);
+        assert_eq!(
+            cli.command,
+            Command::Analyze {
+                paths: vec!["a.flac".to_string(), "Sets".to_string()]
+            }
+        );
+        let err =
+            parse_args_from(args(&["mix_sorter", "--key-notation", "sharps", "id"])).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("invalid --key-notation value: sharps"));
+    }
+
+    #[test]
+    fn parse_duration_accepts_minutes_or_milliseconds() {
+        assert_eq!(parse_duration("4:08"), Some(248_000));
+        assert_eq!(parse_duration("248000"), Some(248_000));
+        assert_eq!(parse_duration("4:75"), None);
+        assert_eq!(parse_duration("soon"), None);
+        assert_eq!(parse_duration("99999:00"), None);
+        assert_eq!(parse_duration("71582:48"), None);
+    }
+}
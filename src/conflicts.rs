@@ -0,0 +1,320 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+use crate::db::f32_value;
+use crate::key::Notation;
+use crate::{
+    ambiguous_candidates, MetadataEntry, ScoredCandidate, TrackInfo, BPM_CONFLICT_TOLERANCE,
+};
+
+/// How to pick between sources that disagree on a track's BPM or key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum ConflictPolicy {
+    /// The candidate from the highest-priority layer.
+    #[default]
+    Priority,
+    /// The BPM/key pair backed by the most layers, then priority.
+    Majority,
+    /// The candidate from the most recently modified source file, then priority.
+    MostRecent,
+    /// No match; the track stays in the unsorted block.
+    Unresolved,
+}
+
+impl ConflictPolicy {
+    pub(crate) fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "priority" => Some(Self::Priority),
+            "majority" => Some(Self::Majority),
+            "most-recent" => Some(Self::MostRecent),
+            "unresolved" => Some(Self::Unresolved),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Self::Priority => "priority",
+            Self::Majority => "majority",
+            Self::MostRecent => "most-recent",
+            Self::Unresolved => "unresolved",
+        }
+    }
+
+    /// Picks from `candidates`, which are in rank order.
+    fn choose<'a>(self, candidates: &[ScoredCandidate<'a>]) -> Option<&'a MetadataEntry> {
+        let support = |entry: &MetadataEntry| {
+            candidates
+                .iter()
+                .filter(|other| same_values(other.entry, entry))
+                .map(|other| other.entry.source.name.as_str())
+                .collect::<HashSet<_>>()
+                .len()
+        };
+
+        match self {
+            Self::Priority => first_max_by_key(candidates, |entry| entry.source.priority()),
+            Self::Majority => first_max_by_key(candidates, |entry| {
+                (support(entry), entry.source.priority())
+            }),
+            Self::MostRecent => first_max_by_key(candidates, |entry| {
+                (entry.source.modified, entry.source.priority())
+            }),
+            Self::Unresolved => None,
+        }
+    }
+}
+
+/// Like `max_by_key`, but the earliest candidate wins ties.
+fn first_max_by_key<'a, K: Ord>(
+    candidates: &[ScoredCandidate<'a>],
+    key: impl Fn(&MetadataEntry) -> K,
+) -> Option<&'a MetadataEntry> {
+    candidates
+        .iter()
+        .map(|candidate| candidate.entry)
+        .reduce(|best, entry| if key(entry) > key(best) { entry } else { best })
+}
+
+fn same_values(left: &MetadataEntry, right: &MetadataEntry) -> bool {
+    (left.bpm - right.bpm).abs() <= BPM_CONFLICT_TOLERANCE && left.key == right.key
+}
+
+/// A track that more than one source described with different BPM or key.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Conflict {
+    pub(crate) track: TrackInfo,
+    /// Every close candidate with its score, in rank order.
+    pub(crate) candidates: Vec<(MetadataEntry, i32)>,
+    pub(crate) policy: ConflictPolicy,
+    pub(crate) chosen: Option<MetadataEntry>,
+}
+
+impl Conflict {
+    pub(crate) fn describe(&self, notation: Notation) -> String {
+        let candidates: Vec<String> = self
+            .candidates
+            .iter()
+            .map(|(entry, score)| {
+                format!(
+                    "{} {} BPM {} (score {score})",
+                    entry.source.label(),
+                    entry.bpm,
+                    entry.key.format(notation)
+                )
+            })
+            .collect();
+        let outcome = match &self.chosen {
+            Some(entry) => format!("chose {} by {}", entry.source.label(), self.policy.label()),
+            None => "left unsorted".to_string(),
+        };
+        format!(
+            "{} - {}: {} -> {outcome}",
+            self.track.artist,
+            self.track.name,
+            candidates.join(" | ")
+        )
+    }
+
+    fn to_json(&self) -> Value {
+        let candidates: Vec<Value> = self
+            .candidates
+            .iter()
+            .map(|(entry, score)| {
+                json!({
+                    "source": entry.source.label(),
+                    "priority": entry.source.priority(),
+                    "name": entry.name,
+                    "artist": entry.artist,
+                    "bpm": f32_value(entry.bpm),
+                    "key_camelot": entry.key,
+                    "duration_ms": entry.duration_ms,
+                    "score": score,
+                })
+            })
+            .collect();
+        json!({
+            "spotify_id": self.track.id,
+            "artist": self.track.artist,
+            "name": self.track.name,
+            "policy": self.policy.label(),
+            "chosen": self.chosen.as_ref().map(|entry| entry.source.label()),
+            "candidates": candidates,
+        })
+    }
+}
+
+/// True when two layers among `close` share no BPM/key pair. Rows of one
+/// layer that disagree with each other are not a conflict between sources,
+/// nor is a layer that holds one of another layer's values.
+fn layers_disagree(close: &[ScoredCandidate<'_>]) -> bool {
+    let mut groups: Vec<&MetadataEntry> = Vec::new();
+    let mut layer_groups: HashMap<&str, HashSet<usize>> = HashMap::new();
+    for candidate in close {
+        let group = match groups
+            .iter()
+            .position(|entry| same_values(entry, candidate.entry))
+        {
+            Some(group) => group,
+            None => {
+                groups.push(candidate.entry);
+                groups.len() - 1
+            }
+        };
+        layer_groups
+            .entry(candidate.entry.source.name.as_str())
+            .or_default()
+            .insert(group);
+    }
+
+    let layers: Vec<&HashSet<usize>> = layer_groups.values().collect();
+    layers.iter().enumerate().any(|(index, left)| {
+        layers[index + 1..]
+            .iter()
+            .any(|right| left.is_disjoint(right))
+    })
+}
+
+/// Resolves `ranked` under `policy`. Returns the match and, when layers
+/// among the close candidates disagree on BPM or key, the conflict that was
+/// settled.
+pub(crate) fn resolve_match(
+    track: &TrackInfo,
+    ranked: &[ScoredCandidate<'_>],
+    policy: ConflictPolicy,
+) -> (Option<MetadataEntry>, Option<Conflict>) {
+    let close = ambiguous_candidates(ranked);
+    if !layers_disagree(close) {
+        return (
+            ranked.first().map(|candidate| candidate.entry.clone()),
+            None,
+        );
+    }
+
+    let chosen = policy.choose(close).cloned();
+    let conflict = Conflict {
+        track: track.clone(),
+        candidates: close
+            .iter()
+            .map(|candidate| (candidate.entry.clone(), candidate.score))
+            .collect(),
+        policy,
+        chosen: chosen.clone(),
+    };
+    (chosen, Some(conflict))
+}
+
+pub(crate) fn write_conflict_report(path: &str, conflicts: &[Conflict]) -> Result<()> {
+    let report: Vec<Value> = conflicts.iter().map(Conflict::to_json).collect();
+    fs::write(path, serde_json::to_string_pretty(&report)? + "\n")
+        .with_context(|| format!("failed to write {path}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::Key;
+    use crate::{rank_candidates, MetadataSource, SourceLayer};
+    use std::time::{Duration, SystemTime};
+
+    fn entry(layer: &str, priority: i32, bpm: f32, key: &str, age_secs: u64) -> MetadataEntry {
+        MetadataEntry {
+            name: "Losing It".to_string(),
+            artist: "FISHER".to_string(),
+            bpm,
+            bpm_confidence: None,
+            key: Key::parse(key).unwrap(),
+            duration_ms: Some(248_000),
+            genre: None,
+            source: SourceLayer {
+                kind: MetadataSource::LocalDb,
+                name: layer.to_string(),
+                priority,
+                modified: Some(SystemTime::now() - Duration::from_secs(age_secs)),
+            },
+        }
+    }
+
+    fn track() -> TrackInfo {
+        TrackInfo {
+            id: "spotify-id".to_string(),
+            name: "Losing It".to_string(),
+            artist: "FISHER".to_string(),
+            key: None,
+            tempo: None,
+            duration_ms: 248_000,
+        }
+    }
+
+    fn chosen_layer(entries: &[MetadataEntry], policy: ConflictPolicy) -> Option<String> {
+        let ranked = rank_candidates(&track(), entries);
+        let (chosen, conflict) = resolve_match(&track(), &ranked, policy);
+        assert_eq!(conflict.unwrap().candidates.len(), entries.len());
+        chosen.map(|entry| entry.source.name)
+    }
+
+    #[test]
+    fn policies_pick_by_priority_majority_and_recency() {
+        let entries = vec![
+            entry("personal", 30, 125.0, "8A", 100),
+            entry("team", 20, 125.0, "9A", 50),
+            entry("export", 10, 125.0, "9a", 5),
+        ];
+
+        let chosen = |policy| chosen_layer(&entries, policy);
+        assert_eq!(
+            chosen(ConflictPolicy::Priority).as_deref(),
+            Some("personal")
+        );
+        assert_eq!(chosen(ConflictPolicy::Majority).as_deref(), Some("team"));
+        assert_eq!(
+            chosen(ConflictPolicy::MostRecent).as_deref(),
+            Some("export")
+        );
+        assert_eq!(chosen(ConflictPolicy::Unresolved), None);
+    }
+
+    #[test]
+    fn resolve_match_ignores_agreement_and_single_layer_ties() {
+        let agreeing = vec![
+            entry("personal", 30, 125.0, "8A", 0),
+            entry("team", 20, 125.0, "8a", 0),
+        ];
+        let ranked = rank_candidates(&track(), &agreeing);
+        let (chosen, conflict) = resolve_match(&track(), &ranked, ConflictPolicy::Unresolved);
+        assert_eq!(chosen.unwrap().source.name, "personal");
+        assert!(conflict.is_none());
+
+        // An Exportify tempo and a curated round number agree.
+        let rounded = vec![
+            entry("personal", 30, 125.0, "8A", 0),
+            entry("export", 10, 124.987, "8A", 0),
+        ];
+        let ranked = rank_candidates(&track(), &rounded);
+        assert!(resolve_match(&track(), &ranked, ConflictPolicy::Unresolved)
+            .1
+            .is_none());
+
+        let one_layer = vec![
+            entry("team", 20, 125.0, "8A", 0),
+            entry("team", 20, 126.0, "8A", 0),
+        ];
+        let ranked = rank_candidates(&track(), &one_layer);
+        assert!(resolve_match(&track(), &ranked, ConflictPolicy::Unresolved)
+            .1
+            .is_none());
+
+        // Only `team` disagrees with itself; `personal` backs one of its rows.
+        let inside_one_layer = vec![
+            entry("team", 20, 125.0, "8A", 0),
+            entry("team", 20, 126.0, "8A", 0),
+            entry("personal", 30, 125.0, "8A", 0),
+        ];
+        let ranked = rank_candidates(&track(), &inside_one_layer);
+        let (chosen, conflict) = resolve_match(&track(), &ranked, ConflictPolicy::Unresolved);
+        assert_eq!(chosen.unwrap().source.name, "personal");
+        assert!(conflict.is_none());
+    }
+}
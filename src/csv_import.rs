@@ -0,0 +1,459 @@
+use anyhow::{anyhow, bail, Context, Result};
+use csv::StringRecord;
+use rspotify::model::Modality;
+use std::fs;
+use std::io;
+
+use crate::cli::parse_duration;
+use crate::key::Key;
+use crate::library::{LibraryImport, LibraryKey, LibraryTrack};
+use crate::MetadataSource;
+
+/// Presets tried, in order, when a CSV is loaded without a mapping.
+pub(crate) const PRESET_NAMES: [&str; 3] = ["exportify", "mixed-in-key", "tunebat"];
+
+/// How the key column is written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum KeyNotation {
+    /// Any notation `Key::parse` reads: Camelot (`8A`), Open Key (`1m`)
+    /// or musical (`Am`, `F# minor`).
+    #[default]
+    Auto,
+    /// Open Key (`1m`, `1d`), as used by Traktor and Mixed In Key.
+    OpenKey,
+    /// A Spotify pitch class 0-11, with the mode in its own column.
+    Spotify,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+pub(crate) enum DurationUnit {
+    #[default]
+    #[serde(rename = "ms")]
+    Milliseconds,
+    #[serde(rename = "seconds")]
+    Seconds,
+    #[serde(rename = "m:ss")]
+    Clock,
+}
+
+/// Which columns of a CSV hold each field. Each field lists header names to
+/// try in order, compared case-insensitively.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CsvMapping {
+    pub(crate) name: String,
+    title: Vec<String>,
+    artist: Vec<String>,
+    bpm: Vec<String>,
+    key: Vec<String>,
+    key_notation: KeyNotation,
+    /// Required for `KeyNotation::Spotify`, ignored otherwise.
+    mode: Vec<String>,
+    duration: Vec<String>,
+    duration_unit: DurationUnit,
+    genre: Vec<String>,
+    /// Only the first of several artists is kept when set.
+    artist_separator: Option<String>,
+}
+
+/// A user mapping file. Columns are single header names.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MappingFile {
+    title: String,
+    artist: String,
+    bpm: String,
+    key: String,
+    #[serde(default)]
+    key_notation: KeyNotation,
+    #[serde(default)]
+    mode: Option<String>,
+    #[serde(default)]
+    duration: Option<String>,
+    #[serde(default)]
+    duration_unit: DurationUnit,
+    #[serde(default)]
+    genre: Option<String>,
+    #[serde(default)]
+    artist_separator: Option<String>,
+}
+
+fn names(columns: &[&str]) -> Vec<String> {
+    columns.iter().map(|column| column.to_string()).collect()
+}
+
+impl CsvMapping {
+    pub(crate) fn preset(name: &str) -> Option<Self> {
+        match name {
+            "exportify" => Some(Self {
+                name: name.to_string(),
+                title: names(&["Track Name"]),
+                artist: names(&["Artist Name(s)"]),
+                bpm: names(&["Tempo"]),
+                key: names(&["Key"]),
+                key_notation: KeyNotation::Spotify,
+                mode: names(&["Mode", "Mode (major or minor)"]),
+                duration: names(&["Duration (ms)"]),
+                duration_unit: DurationUnit::Milliseconds,
+                genre: Vec::new(),
+                artist_separator: Some(";".to_string()),
+            }),
+            "mixed-in-key" => Some(Self {
+                name: name.to_string(),
+                title: names(&["Title", "Track Name"]),
+                artist: names(&["Artist"]),
+                bpm: names(&["BPM", "Tempo"]),
+                key: names(&["Key result", "Key"]),
+                key_notation: KeyNotation::Auto,
+                mode: Vec::new(),
+                duration: names(&["Duration", "Length"]),
+                duration_unit: DurationUnit::Clock,
+                genre: names(&["Genre"]),
+                artist_separator: None,
+            }),
+            "tunebat" => Some(Self {
+                name: name.to_string(),
+                title: names(&["Song", "Track", "Title"]),
+                artist: names(&["Artist", "Artists"]),
+                bpm: names(&["BPM"]),
+                key: names(&["Camelot", "Key"]),
+                key_notation: KeyNotation::Auto,
+                mode: Vec::new(),
+                duration: names(&["Duration"]),
+                duration_unit: DurationUnit::Clock,
+                genre: names(&["Genre"]),
+                artist_separator: None,
+            }),
+            _ => None,
+        }
+    }
+
+    fn from_file(name: &str, file: MappingFile) -> Result<Self> {
+        if file.key_notation == KeyNotation::Spotify && file.mode.is_none() {
+            bail!("key_notation spotify needs a mode column");
+        }
+        let optional = |column: Option<String>| column.into_iter().collect();
+        Ok(Self {
+            name: name.to_string(),
+            title: vec![file.title],
+            artist: vec![file.artist],
+            bpm: vec![file.bpm],
+            key: vec![file.key],
+            key_notation: file.key_notation,
+            mode: optional(file.mode),
+            duration: optional(file.duration),
+            duration_unit: file.duration_unit,
+            genre: optional(file.genre),
+            artist_separator: file
+                .artist_separator
+                .filter(|separator| !separator.is_empty()),
+        })
+    }
+
+    /// A preset name, or the path of a JSON mapping file.
+    pub(crate) fn resolve(spec: &str) -> Result<Self> {
+        if let Some(preset) = Self::preset(spec) {
+            return Ok(preset);
+        }
+        let content = fs::read_to_string(spec).with_context(|| {
+            format!(
+                "'{spec}' is neither a CSV preset ({}) nor a readable mapping file",
+                PRESET_NAMES.join(", ")
+            )
+        })?;
+        let file: MappingFile = serde_json::from_str(&content)
+            .with_context(|| format!("failed to parse CSV mapping {spec}"))?;
+        Self::from_file(spec, file).with_context(|| format!("invalid CSV mapping {spec}"))
+    }
+
+    /// The first preset whose required columns are all present.
+    fn detect(headers: &StringRecord) -> Option<Self> {
+        PRESET_NAMES
+            .iter()
+            .filter_map(|name| Self::preset(name))
+            .find(|mapping| mapping.columns(headers).is_ok())
+    }
+
+    fn columns(&self, headers: &StringRecord) -> Result<Columns> {
+        let required = |columns: &[String]| {
+            find_column(headers, columns).ok_or_else(|| {
+                anyhow!(
+                    "{} CSV is missing the '{}' column",
+                    self.name,
+                    columns.first().map(String::as_str).unwrap_or_default()
+                )
+            })
+        };
+        Ok(Columns {
+            title: required(&self.title)?,
+            artist: required(&self.artist)?,
+            bpm: required(&self.bpm)?,
+            key: required(&self.key)?,
+            mode: match self.key_notation {
+                KeyNotation::Spotify => Some(required(&self.mode)?),
+                _ => None,
+            },
+            duration: find_column(headers, &self.duration),
+            genre: find_column(headers, &self.genre),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Columns {
+    title: usize,
+    artist: usize,
+    bpm: usize,
+    key: usize,
+    mode: Option<usize>,
+    duration: Option<usize>,
+    genre: Option<usize>,
+}
+
+fn find_column(headers: &StringRecord, names: &[String]) -> Option<usize> {
+    names.iter().find_map(|name| {
+        headers.iter().position(|header| {
+            header
+                .trim()
+                .trim_start_matches('\u{feff}')
+                .eq_ignore_ascii_case(name.trim())
+        })
+    })
+}
+
+fn parse_spotify_mode(raw_mode: &str) -> Option<Modality> {
+    match raw_mode.trim().to_ascii_lowercase().as_str() {
+        "1" | "major" => Some(Modality::Major),
+        "0" | "minor" => Some(Modality::Minor),
+        _ => None,
+    }
+}
+
+fn key_field(raw_key: &str, raw_mode: Option<&str>, notation: KeyNotation) -> LibraryKey {
+    match notation {
+        KeyNotation::Auto => LibraryKey::read(raw_key, Key::parse),
+        KeyNotation::OpenKey => LibraryKey::read(raw_key, Key::from_open_key),
+        KeyNotation::Spotify => {
+            let raw_mode = raw_mode.unwrap_or_default().trim();
+            if raw_mode.is_empty() {
+                return LibraryKey::Missing;
+            }
+            LibraryKey::read(raw_key, |raw_key| {
+                let pitch = raw_key.parse::<i32>().ok()?;
+                Key::from_spotify(pitch, parse_spotify_mode(raw_mode)?)
+            })
+        }
+    }
+}
+
+fn parse_duration_field(raw: &str, unit: DurationUnit) -> Option<u32> {
+    let raw = raw.trim();
+    match unit {
+        DurationUnit::Milliseconds => raw.parse().ok(),
+        DurationUnit::Seconds => raw
+            .parse::<f32>()
+            .ok()
+            .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+            .map(|seconds| (seconds * 1_000.0).round() as u32),
+        DurationUnit::Clock => parse_duration(raw),
+    }
+}
+
+fn track_from_record(
+    record: &StringRecord,
+    columns: Columns,
+    mapping: &CsvMapping,
+) -> LibraryTrack {
+    let field = |index: Option<usize>| {
+        index
+            .and_then(|index| record.get(index))
+            .unwrap_or_default()
+    };
+    let artist = field(Some(columns.artist));
+    let artist = match &mapping.artist_separator {
+        Some(separator) => artist.split(separator.as_str()).next().unwrap_or(artist),
+        None => artist,
+    };
+
+    LibraryTrack {
+        name: field(Some(columns.title)).to_string(),
+        artist: artist.to_string(),
+        bpm: field(Some(columns.bpm)).to_string(),
+        bpm_confidence: None,
+        key: key_field(
+            field(Some(columns.key)),
+            columns.mode.map(|mode| field(Some(mode))),
+            mapping.key_notation,
+        ),
+        duration_ms: parse_duration_field(field(columns.duration), mapping.duration_unit),
+        genre: field(columns.genre).to_string(),
+    }
+}
+
+/// Rows read from a CSV and the mapping that read them.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CsvImport {
+    pub(crate) mapping: String,
+    pub(crate) library: LibraryImport,
+}
+
+/// Reads a CSV with `mapping`, or with the first preset that fits its
+/// headers when `mapping` is `None`.
+pub(crate) fn load_csv_reader<R: io::Read>(
+    reader: R,
+    mapping: Option<&CsvMapping>,
+    kind: MetadataSource,
+) -> Result<CsvImport> {
+    let mut csv_reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+    let headers = csv_reader.headers()?.clone();
+    let detected;
+    let mapping = match mapping {
+        Some(mapping) => mapping,
+        None => {
+            detected = CsvMapping::detect(&headers).ok_or_else(|| {
+                anyhow!(
+                    "the columns match no CSV preset ({}); pass --csv-mapping <preset|mapping.json>",
+                    PRESET_NAMES.join(", ")
+                )
+            })?;
+            &detected
+        }
+    };
+    let columns = mapping.columns(&headers)?;
+
+    let mut library = LibraryImport::default();
+    for record in csv_reader.records() {
+        library.push(track_from_record(&record?, columns, mapping), kind);
+    }
+    Ok(CsvImport {
+        mapping: mapping.name.clone(),
+        library,
+    })
+}
+
+pub(crate) fn load_csv(
+    path: &str,
+    mapping: Option<&CsvMapping>,
+    kind: MetadataSource,
+) -> Result<CsvImport> {
+    let file = fs::File::open(path).with_context(|| format!("failed to read {path}"))?;
+    load_csv_reader(file, mapping, kind).with_context(|| format!("failed to parse CSV at {path}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exportify() -> CsvMapping {
+        CsvMapping::preset("exportify").unwrap()
+    }
+
+    #[test]
+    fn load_csv_reader_imports_exportify_numeric_mode_with_bom_header() {
+        let csv = "\u{feff}Track Name,Artist Name(s),Duration (ms),Key,Mode,Tempo\nLosing It,FISHER;Chris Lake,248000,2,1,125.0\n";
+        let import = load_csv_reader(
+            csv.as_bytes(),
+            Some(&exportify()),
+            MetadataSource::Exportify,
+        )
+        .unwrap()
+        .library;
+
+        assert_eq!(import.skipped(), 0);
+        assert_eq!(import.entries.len(), 1);
+        assert_eq!(import.entries[0].name, "Losing It");
+        assert_eq!(import.entries[0].artist, "FISHER");
+        assert_eq!(import.entries[0].duration_ms, Some(248_000));
+        assert_eq!(import.entries[0].key.camelot(), "10B");
+        assert_eq!(import.entries[0].source.kind, MetadataSource::Exportify);
+    }
+
+    #[test]
+    fn load_csv_reader_tracks_skipped_exportify_rows() {
+        let csv = "Track Name,Artist Name(s),Duration (ms),Key,Mode,Tempo\n\
+                   Missing Tempo,FISHER,248000,2,1,\n\
+                   Invalid Mode,FISHER,248000,2,weird,125.0\n\
+                   Good Track,FISHER,248000,2,1,125.0\n";
+        let import = load_csv_reader(
+            csv.as_bytes(),
+            Some(&exportify()),
+            MetadataSource::Exportify,
+        )
+        .unwrap()
+        .library;
+
+        assert_eq!(import.entries.len(), 1);
+        assert_eq!(import.missing_bpm, 1);
+        assert_eq!(import.invalid_key, 1);
+        assert_eq!(import.skipped(), 2);
+    }
+
+    #[test]
+    fn load_csv_reader_detects_presets_and_applies_custom_mappings() {
+        let detected = |csv: &str| {
+            let import = load_csv_reader(csv.as_bytes(), None, MetadataSource::Csv).unwrap();
+            let rows: Vec<String> = import
+                .library
+                .entries
+                .iter()
+                .map(|entry| {
+                    format!(
+                        "{} - {} | {} | {} | {:?}",
+                        entry.artist,
+                        entry.name,
+                        entry.bpm,
+                        entry.key.camelot(),
+                        entry.duration_ms
+                    )
+                })
+                .collect();
+            (import.mapping, rows)
+        };
+
+        assert_eq!(
+            detected("Artist,Title,Key result,BPM,Energy\nFISHER,Losing It,10B,125,7\n"),
+            (
+                "mixed-in-key".to_string(),
+                vec!["FISHER - Losing It | 125 | 10B | None".to_string()]
+            )
+        );
+        assert_eq!(
+            detected("Song,Artist,Key,Camelot,BPM,Duration\nGlue,Bicep,A Minor,8A,130,4:29\n"),
+            (
+                "tunebat".to_string(),
+                vec!["Bicep - Glue | 130 | 8A | Some(269000)".to_string()]
+            )
+        );
+        assert!(load_csv_reader("a,b\n1,2\n".as_bytes(), None, MetadataSource::Csv).is_err());
+
+        let mapping = CsvMapping::from_file(
+            "mine.json",
+            serde_json::from_str(
+                r#"{"title": "Track", "artist": "By", "bpm": "Tempo", "key": "Open Key",
+                    "key_notation": "open-key", "duration": "Secs", "duration_unit": "seconds"}"#,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let import = load_csv_reader(
+            "Track,By,Tempo,Open Key,Secs\nLosing It,FISHER,125,3d,248\nGlue,Bicep,130,1m,269.4\n"
+                .as_bytes(),
+            Some(&mapping),
+            MetadataSource::Csv,
+        )
+        .unwrap()
+        .library;
+        let keys: Vec<(String, Option<u32>)> = import
+            .entries
+            .iter()
+            .map(|entry| (entry.key.camelot(), entry.duration_ms))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                ("10B".to_string(), Some(248_000)),
+                ("8A".to_string(), Some(269_400))
+            ]
+        );
+    }
+}
@@ -0,0 +1,1102 @@
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{Map, Value};
+use std::fs;
+
+use crate::cli::{DbCommand, EntrySelector, MetadataArgs, PromoteMode, TrackFields};
+use crate::key::{display_key, Key, Notation};
+use crate::sources::overlay_sources;
+use crate::sqlite_store::{is_sqlite_path, SqliteStore};
+use crate::{
+    extract_track_id, format_duration, key_to_camelot, load_source_entries, local_db_path,
+    normalize, LocalTrackData, MetadataEntry, MetadataSource, BPM_CONFLICT_TOLERANCE,
+    DURATION_MATCH_TOLERANCE_MS,
+};
+
+pub(crate) const MIN_BPM: f32 = 40.0;
+pub(crate) const MAX_BPM: f32 = 250.0;
+
+/// A promoted row whose BPM or key disagrees with a curated entry.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PromoteConflict {
+    pub(crate) number: usize,
+    pub(crate) curated: LocalTrackData,
+    pub(crate) incoming: LocalTrackData,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct PromoteReport {
+    pub(crate) added: Vec<LocalTrackData>,
+    pub(crate) conflicts: Vec<PromoteConflict>,
+    pub(crate) unchanged: usize,
+    pub(crate) invalid: Vec<(LocalTrackData, String)>,
+    /// Rows that repeat an earlier row of the same import; they are skipped.
+    pub(crate) duplicates: Vec<LocalTrackData>,
+}
+
+/// Current schema version: `{"version": 2, "tracks": [...]}`. Version 1 is
+/// the original bare array of tracks, which is still read and written as-is.
+pub(crate) const LOCAL_DB_VERSION: u64 = 2;
+
+/// Serialized track fields in README order.
+pub(crate) const TRACK_FIELDS: [&str; 14] = [
+    "name",
+    "artist",
+    "bpm",
+    "key_camelot",
+    "duration_ms",
+    "genre",
+    "energy",
+    "tags",
+    "rating",
+    "isrc",
+    "spotify_id",
+    "notes",
+    "bpm_confidence",
+    "verified",
+];
+
+/// `local_db.json` kept as raw JSON objects, so rewriting the file preserves
+/// entry order, key order, and any fields MIX_SORTER does not know about.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LocalDbFile {
+    /// File the database was loaded from, where [`TrackStore::save`] writes.
+    path: Option<String>,
+    /// Top-level object of a versioned file, `None` for a bare array.
+    header: Option<Map<String, Value>>,
+    entries: Vec<Map<String, Value>>,
+}
+
+impl LocalDbFile {
+    pub(crate) fn from_json(content: &str) -> Result<Self> {
+        let (header, values) = match serde_json::from_str(content)? {
+            Value::Array(values) => (None, values),
+            Value::Object(mut header) => {
+                let version = header
+                    .get("version")
+                    .and_then(Value::as_u64)
+                    .ok_or_else(|| anyhow!("missing numeric 'version'"))?;
+                if version > LOCAL_DB_VERSION {
+                    bail!(
+                        "database version {version} is newer than this build supports ({LOCAL_DB_VERSION})"
+                    );
+                }
+                let Some(Value::Array(values)) = header.insert("tracks".to_string(), Value::Null)
+                else {
+                    bail!("missing 'tracks' array");
+                };
+                (Some(header), values)
+            }
+            _ => bail!("expected a JSON array of tracks or an object with 'version' and 'tracks'"),
+        };
+
+        let entries = values
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| match value {
+                Value::Object(entry) => Ok(entry),
+                _ => bail!("entry #{} is not a JSON object", index + 1),
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            path: None,
+            header,
+            entries,
+        })
+    }
+
+    pub(crate) fn to_json(&self) -> Result<String> {
+        let entries = Value::from(
+            self.entries
+                .iter()
+                .cloned()
+                .map(Value::Object)
+                .collect::<Vec<_>>(),
+        );
+        let document = match &self.header {
+            Some(header) => {
+                let mut header = header.clone();
+                header.insert("tracks".to_string(), entries);
+                Value::Object(header)
+            }
+            None => entries,
+        };
+        Ok(serde_json::to_string_pretty(&document)? + "\n")
+    }
+
+    pub(crate) fn version(&self) -> u64 {
+        self.header
+            .as_ref()
+            .and_then(|header| header.get("version"))
+            .and_then(Value::as_u64)
+            .unwrap_or(1)
+    }
+
+    /// Moves the file to [`LOCAL_DB_VERSION`]; returns whether anything changed.
+    pub(crate) fn migrate(&mut self) -> Result<bool> {
+        if self.version() == LOCAL_DB_VERSION {
+            return Ok(false);
+        }
+        TrackStore::tracks(self)?;
+
+        let header = self.header.get_or_insert_with(Map::new);
+        header.insert("version".to_string(), Value::from(LOCAL_DB_VERSION));
+        header.insert("tracks".to_string(), Value::Null);
+        Ok(true)
+    }
+
+    /// Parsed tracks paired with the keys MIX_SORTER does not know about.
+    pub(crate) fn tracks_with_extra(&self) -> Result<Vec<(LocalTrackData, Map<String, Value>)>> {
+        self.tracks()?
+            .into_iter()
+            .zip(&self.entries)
+            .map(|(track, entry)| {
+                let mut extra = entry.clone();
+                extra.retain(|key, _| !TRACK_FIELDS.contains(&key.as_str()));
+                Ok((track, extra))
+            })
+            .collect()
+    }
+
+    /// A current-version file holding `tracks`, each followed by its extra keys.
+    pub(crate) fn from_tracks(tracks: Vec<(LocalTrackData, Map<String, Value>)>) -> Result<Self> {
+        let mut db = Self::from_json("[]")?;
+        db.migrate()?;
+        for (track, extra) in tracks {
+            let mut entry = Map::new();
+            write_track_fields(&mut entry, &track)?;
+            entry.extend(extra);
+            db.entries.push(entry);
+        }
+        Ok(db)
+    }
+}
+
+impl TrackStore for LocalDbFile {
+    fn parsed_tracks(&self) -> Result<Vec<Result<LocalTrackData>>> {
+        Ok(self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                serde_json::from_value(Value::Object(entry.clone()))
+                    .with_context(|| format!("entry #{} is not a valid track", index + 1))
+            })
+            .collect())
+    }
+
+    fn len(&self) -> Result<usize> {
+        Ok(self.entries.len())
+    }
+
+    fn track_at(&self, position: usize) -> Result<LocalTrackData> {
+        let entry = self
+            .entries
+            .get(position)
+            .ok_or_else(|| anyhow!("no entry #{}", position + 1))?;
+        serde_json::from_value(Value::Object(entry.clone()))
+            .with_context(|| format!("entry #{} is not a valid track", position + 1))
+    }
+
+    fn insert(&mut self, track: &LocalTrackData) -> Result<()> {
+        let mut entry = Map::new();
+        write_track_fields(&mut entry, track)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    fn update(&mut self, position: usize, track: &LocalTrackData) -> Result<()> {
+        write_track_fields(&mut self.entries[position], track)
+    }
+
+    fn delete(&mut self, position: usize) -> Result<()> {
+        self.entries.remove(position);
+        Ok(())
+    }
+
+    fn save(&mut self) -> Result<()> {
+        let path = self
+            .path
+            .as_deref()
+            .ok_or_else(|| anyhow!("database was not loaded from a file"))?;
+        save_local_db_file(path, self)
+    }
+}
+
+/// Lookups a store may answer from an index instead of scanning every track.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Lookup<'a> {
+    /// Normalized title and artist.
+    Track {
+        title: &'a str,
+        artist: &'a str,
+    },
+    /// ISRC as returned by [`normalize_isrc`].
+    Isrc(&'a str),
+    SpotifyId(&'a str),
+}
+
+impl Lookup<'_> {
+    fn matches(&self, track: &LocalTrackData) -> bool {
+        match *self {
+            Self::Track { title, artist } => {
+                normalize(&track.name) == title && normalize(&track.artist) == artist
+            }
+            Self::Isrc(isrc) => track.isrc.as_deref().map(normalize_isrc).as_deref() == Some(isrc),
+            Self::SpotifyId(id) => track.spotify_id.as_deref() == Some(id),
+        }
+    }
+}
+
+/// The curated track list, stored as `local_db.json` or as a SQLite file.
+/// Positions are 0-based and follow file order; users see them 1-based.
+pub(crate) trait TrackStore {
+    /// Every track parsed on its own, so one malformed entry does not hide
+    /// problems in the others.
+    fn parsed_tracks(&self) -> Result<Vec<Result<LocalTrackData>>>;
+    fn len(&self) -> Result<usize>;
+    fn track_at(&self, position: usize) -> Result<LocalTrackData>;
+    fn insert(&mut self, track: &LocalTrackData) -> Result<()>;
+    fn update(&mut self, position: usize, track: &LocalTrackData) -> Result<()>;
+    fn delete(&mut self, position: usize) -> Result<()>;
+    /// Persists every change made since the store was opened.
+    fn save(&mut self) -> Result<()>;
+
+    fn lookup(&self, lookup: Lookup<'_>) -> Result<Vec<usize>> {
+        Ok(self
+            .tracks()?
+            .iter()
+            .enumerate()
+            .filter(|(_, track)| lookup.matches(track))
+            .map(|(position, _)| position)
+            .collect())
+    }
+
+    fn tracks(&self) -> Result<Vec<LocalTrackData>> {
+        self.parsed_tracks()?.into_iter().collect()
+    }
+
+    fn position_of(&self, selector: &EntrySelector) -> Result<usize> {
+        match selector {
+            EntrySelector::Index(number) => {
+                let len = self.len()?;
+                if *number == 0 || *number > len {
+                    bail!("no entry #{number}; the database has {len} entries");
+                }
+                Ok(number - 1)
+            }
+            EntrySelector::Track(raw) => {
+                let (artist, title) = raw
+                    .split_once(" - ")
+                    .ok_or_else(|| anyhow!("expected 'Artist - Title', got '{raw}'"))?;
+                let positions = self.lookup(Lookup::Track {
+                    title: &normalize(title),
+                    artist: &normalize(artist),
+                })?;
+
+                match positions.as_slice() {
+                    [position] => Ok(*position),
+                    [] => bail!("no entry matches '{raw}'"),
+                    many => bail!(
+                        "'{raw}' matches entries {}; pick one with --index",
+                        many.iter()
+                            .map(|position| format!("#{}", position + 1))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Validates `track` as entry `position` against the rest of the database.
+    fn check_write(&self, track: &LocalTrackData, position: Option<usize>) -> Result<()> {
+        validate_track(track)?;
+
+        let same_track = self.lookup(Lookup::Track {
+            title: &normalize(&track.name),
+            artist: &normalize(&track.artist),
+        })?;
+        for other_position in same_track {
+            if Some(other_position) == position {
+                continue;
+            }
+            let other = self.track_at(other_position)?;
+            if other.duration_ms == track.duration_ms {
+                bail!(
+                    "duplicate of #{}: {}; give versions distinct durations or edit the existing entry",
+                    other_position + 1,
+                    describe_track(&other, Notation::Camelot)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, mut track: LocalTrackData) -> Result<usize> {
+        store_key_as_camelot(&mut track);
+        self.check_write(&track, None)?;
+        self.insert(&track)?;
+        self.len()
+    }
+
+    fn edit(&mut self, selector: &EntrySelector, fields: &TrackFields) -> Result<usize> {
+        let position = self.position_of(selector)?;
+        let mut track = self.track_at(position)?;
+        apply_fields(&mut track, fields);
+        store_key_as_camelot(&mut track);
+        self.check_write(&track, Some(position))?;
+        self.update(position, &track)?;
+        Ok(position + 1)
+    }
+
+    fn remove(&mut self, selector: &EntrySelector) -> Result<LocalTrackData> {
+        let position = self.position_of(selector)?;
+        let track = self.track_at(position)?;
+        self.delete(position)?;
+        Ok(track)
+    }
+
+    /// Merges imported rows: rows without a curated counterpart are appended,
+    /// and rows that disagree on BPM or key are reported as conflicts and
+    /// only applied in [`PromoteMode::Overwrite`]. Rows are compared with
+    /// the database as it was before the import; a row that repeats an
+    /// earlier one of the same import is reported as a duplicate.
+    fn promote(
+        &mut self,
+        incoming: Vec<MetadataEntry>,
+        mode: PromoteMode,
+    ) -> Result<PromoteReport> {
+        let curated = self.tracks()?;
+        let mut imported: Vec<LocalTrackData> = Vec::new();
+        let mut report = PromoteReport::default();
+
+        for entry in incoming {
+            let track = LocalTrackData {
+                name: entry.name,
+                artist: entry.artist,
+                bpm: (entry.bpm * 10.0).round() / 10.0,
+                bpm_confidence: entry.bpm_confidence,
+                key_camelot: entry.key.camelot(),
+                duration_ms: entry.duration_ms,
+                genre: entry.genre,
+                ..LocalTrackData::default()
+            };
+            if let Err(err) = validate_track(&track) {
+                report.invalid.push((track, err.to_string()));
+                continue;
+            }
+            if imported
+                .iter()
+                .any(|earlier| is_same_recording(earlier, &track))
+            {
+                report.duplicates.push(track);
+                continue;
+            }
+            imported.push(track.clone());
+
+            let Some(position) = curated
+                .iter()
+                .position(|existing| is_same_recording(existing, &track))
+            else {
+                self.insert(&track)?;
+                report.added.push(track);
+                continue;
+            };
+
+            let existing = &curated[position];
+            let bpm_differs = (existing.bpm - track.bpm).abs() >= BPM_CONFLICT_TOLERANCE;
+            // Curated rows may be hand-edited in any notation.
+            let key_differs = Key::parse(&existing.key_camelot) != Key::parse(&track.key_camelot);
+            if !bpm_differs && !key_differs {
+                report.unchanged += 1;
+                continue;
+            }
+
+            report.conflicts.push(PromoteConflict {
+                number: position + 1,
+                curated: existing.clone(),
+                incoming: track.clone(),
+            });
+            if mode == PromoteMode::Overwrite {
+                let updated = LocalTrackData {
+                    bpm: track.bpm,
+                    bpm_confidence: track.bpm_confidence,
+                    key_camelot: track.key_camelot,
+                    duration_ms: existing.duration_ms.or(track.duration_ms),
+                    genre: existing.genre.clone().or(track.genre),
+                    ..existing.clone()
+                };
+                self.update(position, &updated)?;
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Entries with the Spotify ID or ISRC in `query`, otherwise entries whose
+    /// `artist - title` contains every word of `query`.
+    fn search(&self, query: &str) -> Result<Vec<(usize, LocalTrackData)>> {
+        let indexed = if let Some(id) = extract_track_id(query).filter(|id| id.len() == 22) {
+            Some(self.lookup(Lookup::SpotifyId(&id))?)
+        } else if is_isrc(query.trim()) {
+            Some(self.lookup(Lookup::Isrc(&normalize_isrc(query)))?)
+        } else {
+            None
+        };
+        if let Some(positions) = indexed {
+            return positions
+                .into_iter()
+                .map(|position| Ok((position + 1, self.track_at(position)?)))
+                .collect();
+        }
+
+        let words: Vec<String> = normalize(query)
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+        Ok(self
+            .tracks()?
+            .into_iter()
+            .enumerate()
+            .filter(|(_, track)| {
+                let haystack = format!("{} {}", normalize(&track.artist), normalize(&track.name));
+                words.iter().all(|word| haystack.contains(word.as_str()))
+            })
+            .map(|(position, track)| (position + 1, track))
+            .collect())
+    }
+}
+
+/// Same normalized title and artist, and no duration disagreement beyond the
+/// matcher's tolerance.
+fn is_same_recording(curated: &LocalTrackData, incoming: &LocalTrackData) -> bool {
+    normalize(&curated.name) == normalize(&incoming.name)
+        && normalize(&curated.artist) == normalize(&incoming.artist)
+        && match (curated.duration_ms, incoming.duration_ms) {
+            (Some(left), Some(right)) => {
+                (i64::from(left) - i64::from(right)).abs() <= DURATION_MATCH_TOLERANCE_MS
+            }
+            _ => true,
+        }
+}
+
+/// Rewrites a parseable key in Camelot, the notation `key_camelot` is
+/// stored in; an unparseable one is left for [`validate_track`] to reject.
+fn store_key_as_camelot(track: &mut LocalTrackData) {
+    if let Some(camelot) = key_to_camelot(&track.key_camelot) {
+        track.key_camelot = camelot;
+    }
+}
+
+pub(crate) fn validate_track(track: &LocalTrackData) -> Result<()> {
+    if track.name.trim().is_empty() {
+        bail!("track name is empty");
+    }
+    if track.artist.trim().is_empty() {
+        bail!("artist is empty");
+    }
+    if !track.bpm.is_finite() || !(MIN_BPM..=MAX_BPM).contains(&track.bpm) {
+        bail!("BPM {} is outside {MIN_BPM}-{MAX_BPM}", track.bpm);
+    }
+    if Key::parse(&track.key_camelot).is_none() {
+        bail!(
+            "'{}' is not a Camelot, Open Key or musical key",
+            track.key_camelot
+        );
+    }
+    if let Some(problem) = optional_field_problems(track).into_iter().next() {
+        bail!(problem);
+    }
+    Ok(())
+}
+
+/// Problems with the optional schema fields, one message per field.
+pub(crate) fn optional_field_problems(track: &LocalTrackData) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if let Some(energy) = track.energy.filter(|energy| !(1..=10).contains(energy)) {
+        problems.push(format!("energy {energy} is outside 1-10"));
+    }
+    if let Some(rating) = track.rating.filter(|rating| *rating > 5) {
+        problems.push(format!("rating {rating} is outside 0-5"));
+    }
+    if let Some(confidence) = track
+        .bpm_confidence
+        .filter(|confidence| !(0.0..=1.0).contains(confidence))
+    {
+        problems.push(format!("bpm_confidence {confidence} is outside 0-1"));
+    }
+    if let Some(isrc) = track.isrc.as_deref().filter(|isrc| !is_isrc(isrc)) {
+        problems.push(format!("'{isrc}' is not a 12-character ISRC"));
+    }
+    if let Some(id) = track
+        .spotify_id
+        .as_deref()
+        .filter(|id| id.len() != 22 || !id.chars().all(|c| c.is_ascii_alphanumeric()))
+    {
+        problems.push(format!("'{id}' is not a 22-character Spotify track ID"));
+    }
+    if track.tags.iter().any(|tag| tag.trim().is_empty()) {
+        problems.push("tags contain an empty tag".to_string());
+    }
+
+    problems
+}
+
+/// Uppercase ISRC without hyphens, the form ISRC lookups compare.
+pub(crate) fn normalize_isrc(raw: &str) -> String {
+    raw.trim().replace('-', "").to_ascii_uppercase()
+}
+
+/// `CC-XXX-YY-NNNNN`, with or without hyphens.
+fn is_isrc(raw: &str) -> bool {
+    let clean: Vec<char> = raw.chars().filter(|c| *c != '-').collect();
+    clean.len() == 12
+        && clean[..2].iter().all(char::is_ascii_uppercase)
+        && clean[2..5]
+            .iter()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+        && clean[5..].iter().all(char::is_ascii_digit)
+}
+
+/// Shortest decimal form of an `f32`, so `124.3` is not written as
+/// `124.30000305175781` and whole BPMs stay integers.
+pub(crate) fn f32_value(value: f32) -> Value {
+    value
+        .to_string()
+        .parse::<serde_json::Number>()
+        .map(Value::Number)
+        .unwrap_or(Value::Null)
+}
+
+/// Writes the known fields in README order, keeping any extra keys in place.
+fn write_track_fields(entry: &mut Map<String, Value>, track: &LocalTrackData) -> Result<()> {
+    let Value::Object(mut fields) = serde_json::to_value(track)? else {
+        bail!("track did not serialize to a JSON object");
+    };
+    fields.insert("bpm".to_string(), f32_value(track.bpm));
+    if let Some(confidence) = track.bpm_confidence {
+        fields.insert("bpm_confidence".to_string(), f32_value(confidence));
+    }
+
+    for key in TRACK_FIELDS {
+        match fields.shift_remove(key) {
+            Some(value) => {
+                entry.insert(key.to_string(), value);
+            }
+            None => {
+                entry.shift_remove(key);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn apply_fields(track: &mut LocalTrackData, fields: &TrackFields) {
+    if let Some(name) = &fields.name {
+        track.name = name.trim().to_string();
+    }
+    if let Some(artist) = &fields.artist {
+        track.artist = artist.trim().to_string();
+    }
+    if let Some(bpm) = fields.bpm {
+        track.bpm = bpm;
+    }
+    if let Some(key) = &fields.key_camelot {
+        // Any notation is accepted; readable keys are stored as Camelot.
+        track.key_camelot = key_to_camelot(key).unwrap_or_else(|| key.trim().to_string());
+    }
+    if fields.clear_duration {
+        track.duration_ms = None;
+    } else if fields.duration_ms.is_some() {
+        track.duration_ms = fields.duration_ms;
+    }
+}
+
+fn track_from_fields(fields: &TrackFields) -> Result<LocalTrackData> {
+    let missing = |flag: &str| anyhow!("db add needs {flag}");
+    let mut track = LocalTrackData {
+        bpm: fields.bpm.ok_or_else(|| missing("--bpm"))?,
+        ..LocalTrackData::default()
+    };
+    fields.name.as_ref().ok_or_else(|| missing("--name"))?;
+    fields.artist.as_ref().ok_or_else(|| missing("--artist"))?;
+    fields
+        .key_camelot
+        .as_ref()
+        .ok_or_else(|| missing("--key"))?;
+    apply_fields(&mut track, fields);
+    Ok(track)
+}
+
+pub(crate) fn describe_track(track: &LocalTrackData, notation: Notation) -> String {
+    format!(
+        "{} - {} | {} BPM | {} | {}",
+        track.artist,
+        track.name,
+        track.bpm,
+        display_key(&track.key_camelot, notation),
+        track
+            .duration_ms
+            .map(format_duration)
+            .unwrap_or_else(|| "-:--".to_string())
+    )
+}
+
+pub(crate) fn load_local_db_file(path: &str) -> Result<LocalDbFile> {
+    let content = fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+    let mut db =
+        LocalDbFile::from_json(&content).with_context(|| format!("failed to parse {path}"))?;
+    db.path = Some(path.to_string());
+    Ok(db)
+}
+
+/// Opens the database at `path` for editing; `.sqlite`, `.sqlite3` and `.db`
+/// paths use the SQLite backend, anything else is JSON.
+pub(crate) fn open_track_store(path: &str) -> Result<Box<dyn TrackStore>> {
+    if is_sqlite_path(path) {
+        Ok(Box::new(SqliteStore::open_for_edit(path)?))
+    } else {
+        Ok(Box::new(load_local_db_file(path)?))
+    }
+}
+
+/// Reads every track without holding a write lock, for sorting and explain.
+/// SQLite stores are read in full too, so their indexes only serve edits.
+pub(crate) fn load_tracks(path: &str) -> Result<Vec<LocalTrackData>> {
+    if is_sqlite_path(path) {
+        SqliteStore::open(path)?.tracks()
+    } else {
+        load_local_db_file(path)?.tracks()
+    }
+    .with_context(|| format!("failed to parse {path}"))
+}
+
+/// Writes through a temporary file so an interrupted write never leaves a
+/// truncated database behind.
+pub(crate) fn save_local_db_file(path: &str, db: &LocalDbFile) -> Result<()> {
+    let temp_path = format!("{path}.tmp");
+    fs::write(&temp_path, db.to_json()?).with_context(|| format!("failed to write {temp_path}"))?;
+    fs::rename(&temp_path, path).with_context(|| format!("failed to replace {path}"))
+}
+
+fn print_promote_report(report: &PromoteReport, mode: PromoteMode, notation: Notation) {
+    for track in &report.added {
+        println!("[ADD] {}", describe_track(track, notation));
+    }
+    for (track, reason) in &report.invalid {
+        println!("[INVALID] {} ({reason})", describe_track(track, notation));
+    }
+    for conflict in &report.conflicts {
+        println!(
+            "[CONFLICT] #{} {} - {}: curated {} BPM {}, imported {} BPM {} ({})",
+            conflict.number,
+            conflict.curated.artist,
+            conflict.curated.name,
+            conflict.curated.bpm,
+            display_key(&conflict.curated.key_camelot, notation),
+            conflict.incoming.bpm,
+            display_key(&conflict.incoming.key_camelot, notation),
+            if mode == PromoteMode::Overwrite {
+                "overwritten"
+            } else {
+                "kept curated"
+            }
+        );
+    }
+    for track in &report.duplicates {
+        println!(
+            "[DUPLICATE] {} (repeats an earlier imported row)",
+            describe_track(track, notation)
+        );
+    }
+    println!(
+        "Promote summary: added={}, conflicts={}, unchanged={}, invalid={}, duplicates={}",
+        report.added.len(),
+        report.conflicts.len(),
+        report.unchanged,
+        report.invalid.len(),
+        report.duplicates.len()
+    );
+}
+
+fn run_promote(
+    path: &str,
+    metadata: &MetadataArgs,
+    mode: PromoteMode,
+    notation: Notation,
+) -> Result<()> {
+    let overlays = overlay_sources(metadata, MetadataSource::Exportify.default_priority());
+    if overlays.is_empty() {
+        bail!(
+            "db promote needs at least one --exportify-csv, --rekordbox-xml, --traktor-nml, --serato, --engine-db, --virtualdj-xml or --csv <path>"
+        );
+    }
+
+    let mut incoming = Vec::new();
+    for overlay in &overlays {
+        incoming.extend(load_source_entries(overlay)?);
+    }
+    promote_entries(path, incoming, mode, notation)
+}
+
+/// Merges `incoming` into the database at `path` under `mode`, printing
+/// the report and saving unless nothing changed or `mode` is a dry run.
+pub(crate) fn promote_entries(
+    path: &str,
+    incoming: Vec<MetadataEntry>,
+    mode: PromoteMode,
+    notation: Notation,
+) -> Result<()> {
+    let mut db = open_track_store(path)?;
+    let report = db.promote(incoming, mode)?;
+    print_promote_report(&report, mode, notation);
+
+    let changed = !report.added.is_empty()
+        || (mode == PromoteMode::Overwrite && !report.conflicts.is_empty());
+    if mode == PromoteMode::ShowConflicts {
+        println!("Dry run: {path} was not changed.");
+    } else if changed {
+        db.save()?;
+        println!("Saved {path}.");
+    }
+    Ok(())
+}
+
+fn run_migrate(path: &str) -> Result<()> {
+    if is_sqlite_path(path) {
+        println!("{path} is a SQLite store; its schema is always current.");
+        return Ok(());
+    }
+    let mut db = load_local_db_file(path)?;
+    let from = db.version();
+    if !db.migrate()? {
+        println!("{path} is already at version {LOCAL_DB_VERSION}.");
+        return Ok(());
+    }
+
+    save_local_db_file(path, &db)?;
+    println!(
+        "Migrated {path} from version {from} to {LOCAL_DB_VERSION} ({} tracks).",
+        db.entries.len()
+    );
+    Ok(())
+}
+
+pub(crate) fn run_db_command(
+    metadata: &MetadataArgs,
+    command: &DbCommand,
+    notation: Notation,
+) -> Result<()> {
+    let path = local_db_path(metadata);
+    match command {
+        DbCommand::Promote { mode } => return run_promote(path, metadata, *mode, notation),
+        DbCommand::ImportTemplate { template_path } => {
+            return crate::fill_in::run_import_template(path, template_path, notation)
+        }
+        DbCommand::Check => return crate::lint::run_check(path),
+        DbCommand::Migrate => return run_migrate(path),
+        DbCommand::Convert { target_path } => {
+            return crate::sqlite_store::run_convert(path, target_path)
+        }
+        _ => {}
+    }
+    let mut db = open_track_store(path)?;
+
+    match command {
+        DbCommand::Add(fields) => {
+            let track = track_from_fields(fields)?;
+            let number = db.add(track.clone())?;
+            db.save()?;
+            println!(
+                "Added #{number} {} to {path}.",
+                describe_track(&track, notation)
+            );
+        }
+        DbCommand::Edit { selector, fields } => {
+            let number = db.edit(selector, fields)?;
+            let track = db.track_at(number - 1)?;
+            db.save()?;
+            println!(
+                "Updated #{number} {} in {path}.",
+                describe_track(&track, notation)
+            );
+        }
+        DbCommand::Remove { selector } => {
+            let track = db.remove(selector)?;
+            db.save()?;
+            println!("Removed {} from {path}.", describe_track(&track, notation));
+        }
+        DbCommand::Search { query } => {
+            let results = db.search(query)?;
+            for (number, track) in &results {
+                println!("#{number} {}", describe_track(track, notation));
+            }
+            println!("{} matching entries in {path}.", results.len());
+        }
+        DbCommand::Promote { .. }
+        | DbCommand::ImportTemplate { .. }
+        | DbCommand::Check
+        | DbCommand::Migrate
+        | DbCommand::Convert { .. } => {
+            unreachable!("handled before loading the database")
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DB: &str = r#"[
+  {
+    "name": "Losing It",
+    "artist": "FISHER",
+    "bpm": 125,
+    "key_camelot": "10B",
+    "duration_ms": 248000,
+    "comment": "festival edit"
+  },
+  {
+    "name": "Space Song",
+    "artist": "Beach House",
+    "bpm": 147,
+    "key_camelot": "5A"
+  }
+]
+"#;
+
+    fn fields() -> TrackFields {
+        TrackFields::default()
+    }
+
+    #[test]
+    fn round_trip_preserves_formatting_and_unknown_fields() {
+        let db = LocalDbFile::from_json(DB).unwrap();
+        assert_eq!(db.to_json().unwrap(), DB);
+    }
+
+    #[test]
+    fn edit_rewrites_only_the_selected_entry() {
+        let mut db = LocalDbFile::from_json(DB).unwrap();
+        let number = db
+            .edit(
+                &EntrySelector::Track("fisher - losing it".to_string()),
+                &TrackFields {
+                    bpm: Some(126.5),
+                    // Stored as Camelot.
+                    key_camelot: Some("D major".to_string()),
+                    clear_duration: true,
+                    ..fields()
+                },
+            )
+            .unwrap();
+
+        assert_eq!(number, 1);
+        let json = db.to_json().unwrap();
+        assert!(json.contains(
+            "\"bpm\": 126.5,\n    \"key_camelot\": \"10B\",\n    \"comment\": \"festival edit\""
+        ));
+        assert!(json.ends_with(&DB[DB.find("  {\n    \"name\": \"Space Song\"").unwrap()..]));
+    }
+
+    #[test]
+    fn add_validates_key_bpm_and_duplicates() {
+        let mut db = LocalDbFile::from_json(DB).unwrap();
+        let track = |bpm: f32, key: &str, duration_ms: Option<u32>| LocalTrackData {
+            name: "Space Song".to_string(),
+            artist: "Beach House".to_string(),
+            bpm,
+            key_camelot: key.to_string(),
+            duration_ms,
+            ..LocalTrackData::default()
+        };
+
+        let err = db.add(track(147.0, "13A", None)).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("not a Camelot, Open Key or musical key"));
+        let err = db.add(track(f32::NAN, "5A", None)).unwrap_err();
+        assert!(err.to_string().contains("outside"));
+        let err = db.add(track(147.0, "5A", None)).unwrap_err();
+        assert!(err.to_string().contains("duplicate of #2"));
+
+        assert_eq!(db.add(track(147.0, "5A", Some(320_000))).unwrap(), 3);
+        assert!(db
+            .to_json()
+            .unwrap()
+            .contains("\"duration_ms\": 320000\n  }\n]\n"));
+    }
+
+    #[test]
+    fn writes_store_keys_in_camelot_and_promote_compares_parsed_keys() {
+        let mut db = LocalDbFile::from_json(&DB.replace("\"10B\"", "\"D major\"")).unwrap();
+        db.add(LocalTrackData {
+            name: "Glue".to_string(),
+            artist: "Bicep".to_string(),
+            bpm: 130.0,
+            key_camelot: "Am".to_string(),
+            ..LocalTrackData::default()
+        })
+        .unwrap();
+        assert_eq!(db.tracks().unwrap()[2].key_camelot, "8A");
+
+        // The curated `D major` is the incoming `10B`.
+        let report = db
+            .promote(
+                vec![exportify("Losing It", 125.0, "10B", 248_000)],
+                PromoteMode::Overwrite,
+            )
+            .unwrap();
+        assert_eq!((report.unchanged, report.conflicts.len()), (1, 0));
+        assert_eq!(db.tracks().unwrap()[0].key_camelot, "D major");
+    }
+
+    fn exportify(name: &str, bpm: f32, key_camelot: &str, duration_ms: u32) -> MetadataEntry {
+        MetadataEntry {
+            name: name.to_string(),
+            artist: if name == "Losing It" {
+                "FISHER"
+            } else {
+                "Bicep"
+            }
+            .to_string(),
+            bpm,
+            bpm_confidence: None,
+            key: Key::parse(key_camelot).unwrap(),
+            duration_ms: Some(duration_ms),
+            genre: None,
+            source: crate::MetadataSource::Exportify.into(),
+        }
+    }
+
+    fn promote(mode: PromoteMode) -> (LocalDbFile, PromoteReport) {
+        let mut db = LocalDbFile::from_json(DB).unwrap();
+        let report = db
+            .promote(
+                vec![
+                    exportify("Losing It", 124.987, "11B", 249_000),
+                    exportify("Glue", 129.96, "4A", 269_000),
+                    exportify("Losing It", 125.0, "10B", 180_000),
+                    exportify("Losing It", 126.0, "10B", 180_500),
+                ],
+                mode,
+            )
+            .unwrap();
+        (db, report)
+    }
+
+    #[test]
+    fn promote_adds_missing_rows_and_reports_conflicts() {
+        let (db, report) = promote(PromoteMode::AddMissing);
+
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].number, 1);
+        assert_eq!(report.conflicts[0].incoming.key_camelot, "11B");
+        let added: Vec<_> = report.added.iter().map(|track| track.bpm).collect();
+        assert_eq!(added, vec![130.0, 125.0]);
+        // A second export row for the radio edit is not a curated conflict.
+        assert_eq!(report.duplicates.len(), 1);
+        assert_eq!(report.duplicates[0].bpm, 126.0);
+
+        let tracks = db.tracks().unwrap();
+        assert_eq!(tracks.len(), 4);
+        assert_eq!(tracks[0].key_camelot, "10B");
+        assert_eq!(tracks[3].duration_ms, Some(180_000));
+    }
+
+    #[test]
+    fn promote_overwrite_replaces_conflicting_values_in_place() {
+        let (db, report) = promote(PromoteMode::Overwrite);
+
+        assert_eq!(report.conflicts.len(), 1);
+        let json = db.to_json().unwrap();
+        assert!(json.starts_with(
+            "[\n  {\n    \"name\": \"Losing It\",\n    \"artist\": \"FISHER\",\n    \"bpm\": 125,\n    \"key_camelot\": \"11B\""
+        ));
+        assert!(json.contains("\"comment\": \"festival edit\""));
+    }
+
+    #[test]
+    fn migrate_wraps_a_bare_array_and_keeps_optional_fields() {
+        let mut db = LocalDbFile::from_json(DB).unwrap();
+        assert_eq!(db.version(), 1);
+        assert!(db.migrate().unwrap());
+        assert!(!db.migrate().unwrap());
+
+        db.edit(
+            &EntrySelector::Index(2),
+            &TrackFields {
+                bpm: Some(147.3),
+                ..fields()
+            },
+        )
+        .unwrap();
+        let json = db.to_json().unwrap();
+        assert!(json.starts_with("{\n  \"version\": 2,\n  \"tracks\": [\n    {\n"));
+        assert!(json.contains("\"bpm\": 147.3,"));
+
+        let reloaded = LocalDbFile::from_json(&json.replace(
+            "\"key_camelot\": \"5A\"",
+            "\"key_camelot\": \"5A\",\n      \"tags\": [\"dreamy\"],\n      \"energy\": 4,\n      \"verified\": true",
+        ))
+        .unwrap();
+        assert_eq!(reloaded.version(), 2);
+        let track = &reloaded.tracks().unwrap()[1];
+        assert_eq!(track.tags, vec!["dreamy".to_string()]);
+        assert_eq!(track.energy, Some(4));
+        assert!(track.verified);
+        assert_eq!(reloaded.to_json().unwrap(), json.replace(
+            "\"key_camelot\": \"5A\"",
+            "\"key_camelot\": \"5A\",\n      \"tags\": [\n        \"dreamy\"\n      ],\n      \"energy\": 4,\n      \"verified\": true",
+        ));
+
+        let err = LocalDbFile::from_json("{\"version\": 3, \"tracks\": []}").unwrap_err();
+        assert!(err.to_string().contains("newer than this build supports"));
+    }
+
+    #[test]
+    fn validate_track_checks_optional_fields() {
+        let track = LocalTrackData {
+            name: "Glue".to_string(),
+            artist: "Bicep".to_string(),
+            bpm: 130.0,
+            key_camelot: "4A".to_string(),
+            isrc: Some("GB-CPS-17-00123".to_string()),
+            spotify_id: Some("2nOsiUa2nlXBGuDMjDIbDb".to_string()),
+            ..LocalTrackData::default()
+        };
+        assert!(validate_track(&track).is_ok());
+
+        let track = LocalTrackData {
+            energy: Some(11),
+            isrc: Some("GB123".to_string()),
+            bpm_confidence: Some(1.5),
+            ..track
+        };
+        assert_eq!(
+            optional_field_problems(&track),
+            vec![
+                "energy 11 is outside 1-10".to_string(),
+                "bpm_confidence 1.5 is outside 0-1".to_string(),
+                "'GB123' is not a 12-character ISRC".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn remove_and_search_use_one_based_positions() {
+        let mut db = LocalDbFile::from_json(DB).unwrap();
+        let found = db.search("beach song").unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0, 2);
+
+        let err = db.remove(&EntrySelector::Index(3)).unwrap_err();
+        assert!(err.to_string().contains("no entry #3"));
+        let removed = db.remove(&EntrySelector::Index(1)).unwrap();
+        assert_eq!(removed.name, "Losing It");
+        assert_eq!(db.tracks().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,114 @@
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OpenFlags};
+
+use crate::key::{Key, Mode};
+use crate::library::{LibraryImport, LibraryKey, LibraryTrack};
+use crate::MetadataSource;
+
+/// Engine DJ numbers keys round the circle of fifths from C major: even
+/// values are major, odd values their relative minor (0 = C, 1 = Am, 2 = G).
+fn engine_key(value: i64) -> Option<Key> {
+    if !(0..24).contains(&value) {
+        return None;
+    }
+    let mode = if value % 2 == 0 {
+        Mode::Major
+    } else {
+        Mode::Minor
+    };
+    Key::from_wheel(((value / 2 + 7) % 12 + 1) as u8, mode)
+}
+
+/// Reads the `Track` table of an Engine DJ 2.x+ `m.db` (under
+/// `Engine Library/Database2/`). The analysed BPM is preferred over the
+/// rounded one Engine displays.
+pub(crate) fn load_engine_db(path: &str) -> Result<LibraryImport> {
+    let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("failed to open Engine DJ library {path}"))?;
+    let mut statement = connection
+        .prepare("SELECT title, artist, COALESCE(bpmAnalyzed, bpm), key, length, genre FROM Track")
+        .with_context(|| format!("{path} is not an Engine DJ 2.x library"))?;
+
+    let tracks = statement.query_map([], |row| {
+        let text = |index| -> rusqlite::Result<String> {
+            Ok(row.get::<_, Option<String>>(index)?.unwrap_or_default())
+        };
+        let key = row.get::<_, Option<i64>>(3)?;
+        Ok(LibraryTrack {
+            name: text(0)?,
+            artist: text(1)?,
+            bpm: row
+                .get::<_, Option<f64>>(2)?
+                .map(|bpm| bpm.to_string())
+                .unwrap_or_default(),
+            bpm_confidence: None,
+            key: match key {
+                Some(value) => engine_key(value).map_or(LibraryKey::Invalid, LibraryKey::Parsed),
+                None => LibraryKey::Missing,
+            },
+            // Whole seconds.
+            duration_ms: row
+                .get::<_, Option<i64>>(4)?
+                .and_then(|seconds| seconds.checked_mul(1_000))
+                .and_then(|ms| u32::try_from(ms).ok()),
+            genre: text(5)?,
+        })
+    })?;
+
+    let mut import = LibraryImport::default();
+    for track in tracks {
+        import.push(track?, MetadataSource::EngineDj);
+    }
+    Ok(import)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::describe_entries;
+
+    #[test]
+    fn engine_key_walks_the_circle_of_fifths() {
+        let camelot = |value| engine_key(value).map(Key::camelot);
+        assert_eq!(camelot(0).as_deref(), Some("8B"));
+        assert_eq!(camelot(1).as_deref(), Some("8A"));
+        assert_eq!(camelot(4).as_deref(), Some("10B"));
+        assert_eq!(camelot(10).as_deref(), Some("1B"));
+        assert_eq!(camelot(23).as_deref(), Some("7A"));
+        assert_eq!(camelot(24), None);
+    }
+
+    #[test]
+    fn load_engine_db_reads_analysed_tracks() {
+        let path =
+            std::env::temp_dir().join(format!("mix_sorter_engine_{}_m.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let connection = Connection::open(&path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE Track (id INTEGER PRIMARY KEY, title TEXT, artist TEXT, genre TEXT,
+                     bpm INTEGER, bpmAnalyzed REAL, key INTEGER, length INTEGER, path TEXT);
+                 INSERT INTO Track (title, artist, genre, bpm, bpmAnalyzed, key, length) VALUES
+                     ('Losing It', 'FISHER', 'Tech House', 125, 125.000061, 4, 248),
+                     ('Glue', 'Bicep', NULL, 130, NULL, 1, 269),
+                     ('Unanalysed', 'Someone', NULL, NULL, NULL, NULL, 200),
+                     ('Odd Key', 'Someone', NULL, 120, 120.0, 31, 200),
+                     ('Corrupt Length', 'Someone', NULL, 122, 122.0, 0, 9223372036854775807);",
+            )
+            .unwrap();
+        drop(connection);
+
+        let import = load_engine_db(path.to_str().unwrap()).unwrap();
+        assert_eq!(
+            describe_entries(&import),
+            vec![
+                "FISHER - Losing It | 125 | 10B | Some(248000) | Some(\"Tech House\")",
+                "Bicep - Glue | 130 | 8A | Some(269000) | None",
+                "Someone - Corrupt Length | 122 | 8B | None | None",
+            ]
+        );
+        assert_eq!(import.entries[0].source.kind, MetadataSource::EngineDj);
+        assert_eq!((import.missing_bpm, import.invalid_key), (1, 1));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
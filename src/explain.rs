@@ -0,0 +1,392 @@
+use anyhow::{anyhow, Result};
+use std::fmt::Write;
+
+use crate::conflicts::{resolve_match, ConflictPolicy};
+use crate::key::Notation;
+use crate::overrides::{MatchOverrides, OverrideResolution};
+use crate::{
+    format_duration, fuzzy_candidates, normalize, rank_candidates, score_breakdown, ArtistMatch,
+    MetadataEntry, MetadataIndex, ScoreBreakdown, ScoredCandidate, TrackInfo,
+    DURATION_MATCH_TOLERANCE_MS,
+};
+
+/// Builds a synthetic track for `explain` from `Artist - Title` input.
+pub(crate) fn track_from_text(raw: &str, duration_ms: Option<u32>) -> Result<TrackInfo> {
+    let (artist, title) = raw
+        .split_once(" - ")
+        .filter(|(artist, title)| !artist.trim().is_empty() && !title.trim().is_empty())
+        .ok_or_else(|| {
+            anyhow!("expected a Spotify track ID/URL or 'Artist - Title', got '{raw}'")
+        })?;
+    let duration_ms = duration_ms
+        .ok_or_else(|| anyhow!("explain needs --duration for 'Artist - Title' input"))?;
+
+    Ok(TrackInfo {
+        id: String::new(),
+        name: title.trim().to_string(),
+        artist: artist.trim().to_string(),
+        key: None,
+        tempo: None,
+        duration_ms,
+    })
+}
+
+fn describe_entry(entry: &MetadataEntry, notation: Notation) -> String {
+    format!(
+        "{} {} - {} | {:.1} BPM | {} | {}",
+        entry.source.label(),
+        entry.artist,
+        entry.name,
+        entry.bpm,
+        entry.key.format(notation),
+        entry
+            .duration_ms
+            .map(format_duration)
+            .unwrap_or_else(|| "-:--".to_string())
+    )
+}
+
+fn describe_breakdown(breakdown: ScoreBreakdown) -> String {
+    let artist = match breakdown.artist {
+        ArtistMatch::Exact => "artist exact +100".to_string(),
+        ArtistMatch::Partial => "artist contains +80".to_string(),
+        ArtistMatch::Mismatch => return "rejected: artist mismatch".to_string(),
+    };
+    let duration = match breakdown.duration_diff_ms {
+        Some(diff) => format!(
+            "duration off by {:.1}s ({} {:.1}s) {:+}",
+            diff as f64 / 1_000.0,
+            if diff <= DURATION_MATCH_TOLERANCE_MS {
+                "within"
+            } else {
+                "outside"
+            },
+            DURATION_MATCH_TOLERANCE_MS as f64 / 1_000.0,
+            breakdown.duration_points()
+        ),
+        None => "no duration +0".to_string(),
+    };
+    let title = if breakdown.title_exact {
+        "title exact +20"
+    } else {
+        "title differs +0"
+    };
+
+    format!(
+        "{artist}, {duration}, {title} => score {}",
+        breakdown.score().unwrap_or_default()
+    )
+}
+
+fn explain_candidates<'a>(
+    out: &mut String,
+    track: &TrackInfo,
+    candidates: impl IntoIterator<Item = &'a MetadataEntry>,
+    notation: Notation,
+) {
+    let spot_artist_norm = normalize(&track.artist);
+    let spot_title_norm = normalize(&track.name);
+
+    for entry in candidates {
+        let breakdown = score_breakdown(track, &spot_artist_norm, &spot_title_norm, entry);
+        let _ = writeln!(out, "  - {}", describe_entry(entry, notation));
+        let _ = writeln!(
+            out,
+            "      {} (source priority {})",
+            describe_breakdown(breakdown),
+            entry.source.priority()
+        );
+    }
+}
+
+fn describe_win(ranked: &[ScoredCandidate<'_>]) -> String {
+    let winner = ranked[0];
+    let Some(runner_up) = ranked.get(1) else {
+        return "only candidate with a compatible artist".to_string();
+    };
+
+    if winner.score != runner_up.score {
+        format!(
+            "highest score ({} vs runner-up {})",
+            winner.score, runner_up.score
+        )
+    } else if winner.entry.source.priority() != runner_up.entry.source.priority() {
+        format!(
+            "tied on score {} and won on source priority ({} beats {})",
+            winner.score,
+            winner.entry.source.label(),
+            runner_up.entry.source.label()
+        )
+    } else {
+        format!(
+            "tied on score {} and source priority; won by load order",
+            winner.score
+        )
+    }
+}
+
+/// Renders every candidate `find_match_for_track` would consider for `track`,
+/// with score components and the reason the winner was chosen. A source
+/// conflict is settled by `conflict_policy`, as `sort` would; keys are
+/// written in `notation`.
+pub(crate) fn explain_match(
+    track: &TrackInfo,
+    metadata_index: &MetadataIndex,
+    overrides: &MatchOverrides,
+    conflict_policy: ConflictPolicy,
+    notation: Notation,
+) -> String {
+    let mut out = String::new();
+    let title_key = normalize(&track.name);
+
+    let _ = writeln!(
+        out,
+        "Explaining {} - {} ({})",
+        track.artist,
+        track.name,
+        format_duration(track.duration_ms)
+    );
+    let _ = writeln!(
+        out,
+        "Normalized artist \"{}\", title \"{title_key}\"",
+        normalize(&track.artist)
+    );
+
+    match overrides.resolve(track, metadata_index) {
+        Some(OverrideResolution::Ignore) => {
+            let _ = writeln!(
+                out,
+                "Override: ignore (the scorer below is never consulted)"
+            );
+        }
+        Some(OverrideResolution::Matched(entry)) => {
+            let _ = writeln!(
+                out,
+                "Override: {} (the scorer below is never consulted)",
+                describe_entry(&entry, notation)
+            );
+        }
+        Some(OverrideResolution::MissingEntry { name, artist }) => {
+            let _ = writeln!(
+                out,
+                "Override: entry '{artist} - {name}' not found, falling back to the scorer"
+            );
+        }
+        None => {}
+    }
+
+    let bucket = metadata_index
+        .by_title
+        .get(&title_key)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let _ = writeln!(out, "Exact title bucket: {} candidate(s)", bucket.len());
+    explain_candidates(&mut out, track, bucket, notation);
+
+    let mut ranked = rank_candidates(track, bucket);
+    let path = if ranked.is_empty() {
+        let fuzzy: Vec<&MetadataEntry> = fuzzy_candidates(track, metadata_index).collect();
+        let _ = writeln!(
+            out,
+            "Fuzzy fallback (title and artist containment): {} candidate(s)",
+            fuzzy.len()
+        );
+        explain_candidates(&mut out, track, fuzzy.iter().copied(), notation);
+        ranked = rank_candidates(track, fuzzy);
+        "fuzzy fallback"
+    } else {
+        let _ = writeln!(
+            out,
+            "Fuzzy fallback: skipped, the exact title bucket produced a match"
+        );
+        "exact title bucket"
+    };
+
+    let (chosen, conflict) = resolve_match(track, &ranked, conflict_policy);
+    if let Some(conflict) = &conflict {
+        let _ = writeln!(out, "Source conflict: {}", conflict.describe(notation));
+    }
+    match (chosen, conflict) {
+        (Some(winner), None) => {
+            let _ = writeln!(
+                out,
+                "Winner via {path}: {}",
+                describe_entry(&winner, notation)
+            );
+            let _ = writeln!(out, "Reason: {}", describe_win(&ranked));
+        }
+        (Some(winner), Some(_)) => {
+            let _ = writeln!(
+                out,
+                "Winner via {path}: {}",
+                describe_entry(&winner, notation)
+            );
+            let _ = writeln!(
+                out,
+                "Reason: the {} conflict policy picked it from the competing layers",
+                conflict_policy.label()
+            );
+        }
+        (None, Some(_)) => {
+            let _ = writeln!(
+                out,
+                "No match: the {} conflict policy leaves the track unsorted",
+                conflict_policy.label()
+            );
+        }
+        (None, None) => {
+            let _ = writeln!(out, "No match: the track would be reported as [MISSING]");
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::Key;
+    use crate::{build_metadata_index, MetadataSource, SourceLayer};
+
+    fn entry(
+        name: &str,
+        artist: &str,
+        duration_ms: Option<u32>,
+        source: MetadataSource,
+    ) -> MetadataEntry {
+        MetadataEntry {
+            name: name.to_string(),
+            artist: artist.to_string(),
+            bpm: 125.0,
+            bpm_confidence: None,
+            key: Key::parse("10B").unwrap(),
+            duration_ms,
+            genre: None,
+            source: source.into(),
+        }
+    }
+
+    #[test]
+    fn track_from_text_requires_separator_and_duration() {
+        let track = track_from_text("FISHER - Losing It", Some(248_000)).unwrap();
+        assert_eq!(track.artist, "FISHER");
+        assert_eq!(track.name, "Losing It");
+
+        assert!(track_from_text("Losing It", Some(248_000)).is_err());
+        assert!(track_from_text("FISHER - Losing It", None).is_err());
+    }
+
+    #[test]
+    fn explain_match_reports_components_and_priority_tiebreak() {
+        let metadata_index = build_metadata_index(vec![
+            entry(
+                "Losing It",
+                "FISHER",
+                Some(248_000),
+                MetadataSource::Exportify,
+            ),
+            entry(
+                "Losing It",
+                "FISHER",
+                Some(248_000),
+                MetadataSource::LocalDb,
+            ),
+            entry("Losing It", "Someone Else", None, MetadataSource::LocalDb),
+        ]);
+        let track = track_from_text("FISHER - Losing It", Some(250_000)).unwrap();
+
+        let output = explain_match(
+            &track,
+            &metadata_index,
+            &MatchOverrides::default(),
+            ConflictPolicy::Priority,
+            Notation::OpenKey,
+        );
+
+        assert!(output.contains("Exact title bucket: 3 candidate(s)"));
+        assert!(output.contains(
+            "artist exact +100, duration off by 2.0s (within 5.0s) +50, title exact +20 => score 170"
+        ));
+        assert!(output.contains("rejected: artist mismatch"));
+        assert!(output.contains("Fuzzy fallback: skipped"));
+        assert!(output.contains(
+            "Winner via exact title bucket: LOCAL_DB FISHER - Losing It | 125.0 BPM | 3d | 4:08"
+        ));
+        assert!(output.contains("won on source priority (LOCAL_DB beats EXPORTIFY)"));
+    }
+
+    #[test]
+    fn explain_match_settles_conflicts_by_policy() {
+        let layered = |layer: &str, priority: i32, key: &str| MetadataEntry {
+            key: Key::parse(key).unwrap(),
+            source: SourceLayer {
+                kind: MetadataSource::LocalDb,
+                name: layer.to_string(),
+                priority,
+                modified: None,
+            },
+            ..entry(
+                "Losing It",
+                "FISHER",
+                Some(248_000),
+                MetadataSource::LocalDb,
+            )
+        };
+        let metadata_index = build_metadata_index(vec![
+            layered("personal", 3, "8A"),
+            layered("team", 2, "9A"),
+            layered("shared", 1, "9A"),
+        ]);
+        let track = track_from_text("FISHER - Losing It", Some(248_000)).unwrap();
+        let explain = |policy| {
+            explain_match(
+                &track,
+                &metadata_index,
+                &MatchOverrides::default(),
+                policy,
+                Notation::Camelot,
+            )
+        };
+
+        let output = explain(ConflictPolicy::Priority);
+        assert!(output.contains(
+            "Source conflict: FISHER - Losing It: personal 125 BPM 8A (score 170) | team 125 BPM 9A (score 170) | shared 125 BPM 9A (score 170) -> chose personal by priority"
+        ));
+        assert!(output.contains("Winner via exact title bucket: personal FISHER - Losing It"));
+
+        let output = explain(ConflictPolicy::Majority);
+        assert!(output.contains("-> chose team by majority"));
+        assert!(output.contains("Winner via exact title bucket: team FISHER - Losing It"));
+        assert!(output.contains("Reason: the majority conflict policy picked it"));
+
+        let output = explain(ConflictPolicy::Unresolved);
+        assert!(
+            output.contains("No match: the unresolved conflict policy leaves the track unsorted")
+        );
+    }
+
+    #[test]
+    fn explain_match_falls_back_to_fuzzy_candidates() {
+        let metadata_index = build_metadata_index(vec![entry(
+            "Space Song",
+            "Beach House",
+            None,
+            MetadataSource::LocalDb,
+        )]);
+        let track =
+            track_from_text("Beach House - Space Song - Remastered", Some(320_000)).unwrap();
+
+        let output = explain_match(
+            &track,
+            &metadata_index,
+            &MatchOverrides::default(),
+            ConflictPolicy::Priority,
+            Notation::Camelot,
+        );
+
+        assert!(output.contains("Exact title bucket: 0 candidate(s)"));
+        assert!(output.contains("Fuzzy fallback (title and artist containment): 1 candidate(s)"));
+        assert!(output.contains("Winner via fuzzy fallback"));
+        assert!(output.contains("only candidate with a compatible artist"));
+    }
+}
@@ -0,0 +1,382 @@
+use anyhow::{bail, Context, Result};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::db::{describe_track, open_track_store, TrackStore};
+use crate::key::{Key, Notation};
+use crate::{LocalTrackData, TrackInfo};
+
+const TEMPLATE_COLUMNS: [&str; 6] = [
+    "spotify_id",
+    "artist",
+    "name",
+    "duration_ms",
+    "bpm",
+    "key_camelot",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TemplateFormat {
+    Csv,
+    Json,
+}
+
+impl TemplateFormat {
+    fn from_path(path: &str) -> Self {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Self::Json,
+            _ => Self::Csv,
+        }
+    }
+}
+
+/// One unmatched track waiting for a BPM and key. `bpm` and `key_camelot`
+/// hold whatever the user typed, so a bad value can be reported per row.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct FillInRow {
+    pub(crate) spotify_id: String,
+    pub(crate) artist: String,
+    pub(crate) name: String,
+    pub(crate) duration_ms: Option<u32>,
+    pub(crate) bpm: String,
+    pub(crate) key_camelot: String,
+}
+
+impl FillInRow {
+    fn from_track(track: &TrackInfo) -> Self {
+        Self {
+            spotify_id: track.id.clone(),
+            artist: track.artist.clone(),
+            name: track.name.clone(),
+            duration_ms: Some(track.duration_ms),
+            ..Self::default()
+        }
+    }
+
+    fn is_blank(&self) -> bool {
+        self.bpm.trim().is_empty() && self.key_camelot.trim().is_empty()
+    }
+
+    fn describe(&self) -> String {
+        format!("{} - {} ({})", self.artist, self.name, self.spotify_id)
+    }
+
+    fn to_track(&self) -> Result<LocalTrackData> {
+        if self.bpm.trim().is_empty() || self.key_camelot.trim().is_empty() {
+            bail!("fill in both bpm and key_camelot");
+        }
+        let bpm = self
+            .bpm
+            .trim()
+            .parse()
+            .with_context(|| format!("BPM '{}' is not a number", self.bpm.trim()))?;
+        let key = Key::parse(&self.key_camelot).with_context(|| {
+            format!(
+                "'{}' is not a Camelot, Open Key or musical key",
+                self.key_camelot.trim()
+            )
+        })?;
+
+        Ok(LocalTrackData {
+            name: self.name.trim().to_string(),
+            artist: self.artist.trim().to_string(),
+            bpm,
+            key_camelot: key.camelot(),
+            duration_ms: self.duration_ms,
+            spotify_id: Some(self.spotify_id.clone()).filter(|id| !id.is_empty()),
+            ..LocalTrackData::default()
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct TemplateImport {
+    pub(crate) added: Vec<LocalTrackData>,
+    pub(crate) unfilled: usize,
+    pub(crate) rejected: Vec<(FillInRow, String)>,
+    /// Unfilled and rejected rows in template order; they stay in the template.
+    pub(crate) remaining: Vec<FillInRow>,
+}
+
+fn parse_csv(content: &str) -> Result<Vec<FillInRow>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header.trim().trim_start_matches('\u{feff}') == name)
+    };
+    let columns: Vec<Option<usize>> = TEMPLATE_COLUMNS.iter().map(|name| column(name)).collect();
+    if columns[0].is_none() {
+        bail!("missing spotify_id column");
+    }
+
+    reader
+        .records()
+        .map(|record| {
+            let record = record?;
+            let field = |index: usize| {
+                columns[index]
+                    .and_then(|column| record.get(column))
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string()
+            };
+            Ok(FillInRow {
+                spotify_id: field(0),
+                artist: field(1),
+                name: field(2),
+                duration_ms: field(3).parse().ok(),
+                bpm: field(4),
+                key_camelot: field(5),
+            })
+        })
+        .collect()
+}
+
+fn render_csv(rows: &[FillInRow]) -> Result<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(TEMPLATE_COLUMNS)?;
+    for row in rows {
+        writer.write_record([
+            row.spotify_id.as_str(),
+            row.artist.as_str(),
+            row.name.as_str(),
+            &row.duration_ms
+                .map(|duration| duration.to_string())
+                .unwrap_or_default(),
+            row.bpm.as_str(),
+            row.key_camelot.as_str(),
+        ])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+fn json_text(entry: &Map<String, Value>, key: &str) -> String {
+    match entry.get(key) {
+        Some(Value::String(text)) => text.trim().to_string(),
+        Some(Value::Number(number)) => number.to_string(),
+        _ => String::new(),
+    }
+}
+
+fn parse_json(content: &str) -> Result<Vec<FillInRow>> {
+    let entries: Vec<Map<String, Value>> = serde_json::from_str(content)?;
+    Ok(entries
+        .iter()
+        .map(|entry| FillInRow {
+            spotify_id: json_text(entry, "spotify_id"),
+            artist: json_text(entry, "artist"),
+            name: json_text(entry, "name"),
+            duration_ms: json_text(entry, "duration_ms").parse().ok(),
+            bpm: json_text(entry, "bpm"),
+            key_camelot: json_text(entry, "key_camelot"),
+        })
+        .collect())
+}
+
+fn render_json(rows: &[FillInRow]) -> Result<String> {
+    let entries: Vec<Value> = rows
+        .iter()
+        .map(|row| {
+            let bpm = match serde_json::from_str::<serde_json::Number>(&row.bpm) {
+                Ok(bpm) => Value::Number(bpm),
+                Err(_) if row.bpm.is_empty() => Value::Null,
+                Err(_) => Value::from(row.bpm.as_str()),
+            };
+            serde_json::json!({
+                "spotify_id": row.spotify_id,
+                "artist": row.artist,
+                "name": row.name,
+                "duration_ms": row.duration_ms,
+                "bpm": bpm,
+                "key_camelot": row.key_camelot,
+            })
+        })
+        .collect();
+    Ok(serde_json::to_string_pretty(&entries)? + "\n")
+}
+
+fn read_template(path: &str) -> Result<Vec<FillInRow>> {
+    let content = fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+    match TemplateFormat::from_path(path) {
+        TemplateFormat::Csv => parse_csv(&content),
+        TemplateFormat::Json => parse_json(&content),
+    }
+    .with_context(|| format!("failed to parse {path}"))
+}
+
+/// Writes through a temporary file so an interrupted write never truncates a
+/// half-filled template.
+fn write_template(path: &str, rows: &[FillInRow]) -> Result<()> {
+    let content = match TemplateFormat::from_path(path) {
+        TemplateFormat::Csv => render_csv(rows)?,
+        TemplateFormat::Json => render_json(rows)?,
+    };
+    let temp_path = format!("{path}.tmp");
+    fs::write(&temp_path, content).with_context(|| format!("failed to write {temp_path}"))?;
+    fs::rename(&temp_path, path).with_context(|| format!("failed to replace {path}"))
+}
+
+/// Appends `missing` tracks to the template at `path`, keeping rows that are
+/// already there (filled in or not). Returns how many rows were added.
+pub(crate) fn write_missing_template(path: &str, missing: &[TrackInfo]) -> Result<usize> {
+    let mut rows = if Path::new(path).exists() {
+        read_template(path)?
+    } else {
+        Vec::new()
+    };
+    let mut known: HashSet<String> = rows.iter().map(|row| row.spotify_id.clone()).collect();
+
+    let before = rows.len();
+    for track in missing {
+        if known.insert(track.id.clone()) {
+            rows.push(FillInRow::from_track(track));
+        }
+    }
+    write_template(path, &rows)?;
+    Ok(rows.len() - before)
+}
+
+/// Adds every filled-in row to `db` with the same validation as `db add`.
+pub(crate) fn import_rows(db: &mut dyn TrackStore, rows: Vec<FillInRow>) -> TemplateImport {
+    let mut import = TemplateImport::default();
+
+    for row in rows {
+        if row.is_blank() {
+            import.unfilled += 1;
+            import.remaining.push(row);
+            continue;
+        }
+        match row.to_track().and_then(|track| {
+            db.add(track.clone())?;
+            Ok(track)
+        }) {
+            Ok(track) => import.added.push(track),
+            Err(err) => {
+                import.remaining.push(row.clone());
+                import.rejected.push((row, format!("{err:#}")));
+            }
+        }
+    }
+
+    import
+}
+
+/// Imports a filled-in template into the local DB, then rewrites the template
+/// with only the rows that were left blank or rejected.
+pub(crate) fn run_import_template(
+    db_path: &str,
+    template_path: &str,
+    notation: Notation,
+) -> Result<()> {
+    let mut db = open_track_store(db_path)?;
+    let import = import_rows(db.as_mut(), read_template(template_path)?);
+
+    for track in &import.added {
+        println!("[ADD] {}", describe_track(track, notation));
+    }
+    for (row, reason) in &import.rejected {
+        println!("[INVALID] {}: {reason}", row.describe());
+    }
+    println!(
+        "Template import summary: added={}, rejected={}, unfilled={}",
+        import.added.len(),
+        import.rejected.len(),
+        import.unfilled
+    );
+
+    if !import.added.is_empty() {
+        db.save()?;
+        write_template(template_path, &import.remaining)?;
+        println!(
+            "Saved {db_path}; {} rows remain in {template_path}.",
+            import.remaining.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::LocalDbFile;
+
+    fn track(id: &str, name: &str) -> TrackInfo {
+        TrackInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            artist: "Bicep".to_string(),
+            key: None,
+            tempo: None,
+            duration_ms: 269_000,
+        }
+    }
+
+    #[test]
+    fn templates_round_trip_in_both_formats() {
+        let mut rows = vec![
+            FillInRow::from_track(&track("id-1", "Glue")),
+            FillInRow::from_track(&track("id-2", "Apricots, Live")),
+        ];
+        rows[0].bpm = "130".to_string();
+        rows[0].key_camelot = "4a".to_string();
+
+        let csv = render_csv(&rows).unwrap();
+        assert!(csv.starts_with(
+            "spotify_id,artist,name,duration_ms,bpm,key_camelot\nid-1,Bicep,Glue,269000,130,4a\n"
+        ));
+        assert_eq!(parse_csv(&csv).unwrap(), rows);
+
+        let json = render_json(&rows).unwrap();
+        assert!(json.contains("\"bpm\": null"));
+        assert_eq!(parse_json(&json).unwrap(), rows);
+    }
+
+    #[test]
+    fn import_rows_validates_and_keeps_unfinished_rows() {
+        let mut db = LocalDbFile::from_json("[]").unwrap();
+        let row = |name: &str, bpm: &str, key: &str| FillInRow {
+            bpm: bpm.to_string(),
+            key_camelot: key.to_string(),
+            ..FillInRow::from_track(&track(&format!("{name:0>22}"), name))
+        };
+
+        let import = import_rows(
+            &mut db,
+            vec![
+                row("good", "130", "4a"),
+                row("blank", "", ""),
+                row("half", "128", ""),
+                row("badkey", "128", "13C"),
+                row("good", "130", "4A"),
+            ],
+        );
+
+        assert_eq!(import.added.len(), 1);
+        assert_eq!(import.added[0].key_camelot, "4A");
+        assert_eq!(
+            import.added[0].spotify_id.as_deref(),
+            Some("000000000000000000good")
+        );
+        assert_eq!(import.unfilled, 1);
+        let reasons: Vec<&str> = import
+            .rejected
+            .iter()
+            .map(|(_, reason)| reason.as_str())
+            .collect();
+        assert!(reasons[0].contains("fill in both"));
+        assert!(reasons[1].contains("not a Camelot, Open Key or musical key"));
+        assert!(reasons[2].contains("duplicate of #1"));
+        let remaining: Vec<&str> = import
+            .remaining
+            .iter()
+            .map(|row| row.name.as_str())
+            .collect();
+        assert_eq!(remaining, vec!["blank", "half", "badkey", "good"]);
+    }
+}
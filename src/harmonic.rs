@@ -0,0 +1,280 @@
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+
+use crate::key::{Key, Mode};
+
+/// A step from one key to the next, named as in the rules file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Move {
+    /// `8A` to `8A`.
+    SameKey,
+    /// One step either way in the same mode, `8A` to `7A` or `9A`.
+    Adjacent,
+    /// Same number, other mode, `8A` to `8B`.
+    Relative,
+    /// Two steps clockwise, a whole tone up, `8A` to `10A`.
+    EnergyBoost,
+    /// Seven steps clockwise, a semitone up, `8A` to `3A`.
+    EnergyLift,
+    /// One step clockwise from minor or anticlockwise from major into the
+    /// other mode, `8A` to `9B` and `8B` to `7A`.
+    Diagonal,
+    /// The opposite diagonal, `8A` to `7B` and `8B` to `9A`.
+    ModeSwitch,
+}
+
+impl Move {
+    pub(crate) const ALL: [Self; 7] = [
+        Self::SameKey,
+        Self::Adjacent,
+        Self::Relative,
+        Self::EnergyBoost,
+        Self::EnergyLift,
+        Self::Diagonal,
+        Self::ModeSwitch,
+    ];
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Self::SameKey => "same_key",
+            Self::Adjacent => "adjacent",
+            Self::Relative => "relative",
+            Self::EnergyBoost => "energy_boost",
+            Self::EnergyLift => "energy_lift",
+            Self::Diagonal => "diagonal",
+            Self::ModeSwitch => "mode_switch",
+        }
+    }
+
+    /// The move that takes `from` to `to`, if it is one of the named ones.
+    /// At most one move fits any pair of keys.
+    pub(crate) fn between(from: Key, to: Key) -> Option<Self> {
+        let (diagonal, mode_switch) = match from.mode() {
+            Mode::Minor => (from.step(1).relative(), from.step(-1).relative()),
+            Mode::Major => (from.step(-1).relative(), from.step(1).relative()),
+        };
+        Self::ALL.into_iter().find(|candidate| match candidate {
+            Self::SameKey => to == from,
+            Self::Adjacent => from.neighbours().contains(&to),
+            Self::Relative => to == from.relative(),
+            Self::EnergyBoost => to == from.energy_boost(),
+            Self::EnergyLift => to == from.step(7),
+            Self::Diagonal => to == diagonal,
+            Self::ModeSwitch => to == mode_switch,
+        })
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    moves: BTreeMap<Move, f32>,
+}
+
+/// The moves a DJ accepts between consecutive tracks, each weighted from
+/// just above 0 (only if nothing better is left) to 1 (ideal).
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct HarmonicRules {
+    weights: BTreeMap<Move, f32>,
+}
+
+impl Default for HarmonicRules {
+    /// The classic Camelot moves: same key, one step, relative and energy
+    /// boost.
+    fn default() -> Self {
+        Self {
+            weights: BTreeMap::from([
+                (Move::SameKey, 1.0),
+                (Move::Adjacent, 0.9),
+                (Move::Relative, 0.8),
+                (Move::EnergyBoost, 0.6),
+            ]),
+        }
+    }
+}
+
+impl HarmonicRules {
+    /// Reads `{"moves": {"same_key": 1.0, ...}}`. Moves left out, or
+    /// weighted 0, are not allowed.
+    pub(crate) fn from_json(content: &str) -> Result<Self> {
+        let file: RulesFile = serde_json::from_str(content)?;
+        for (harmonic_move, weight) in &file.moves {
+            if !(0.0..=1.0).contains(weight) {
+                bail!(
+                    "weight for {} must be between 0 and 1, got {weight}",
+                    harmonic_move.label()
+                );
+            }
+        }
+        Ok(Self {
+            weights: file
+                .moves
+                .into_iter()
+                .filter(|(_, weight)| *weight > 0.0)
+                .collect(),
+        })
+    }
+
+    /// The allowed move from `from` to `to` and its weight.
+    pub(crate) fn score(&self, from: Key, to: Key) -> Option<(Move, f32)> {
+        let harmonic_move = Move::between(from, to)?;
+        Some((harmonic_move, *self.weights.get(&harmonic_move)?))
+    }
+
+    /// Allowed moves, highest weight first, for the summary line.
+    pub(crate) fn describe(&self) -> String {
+        let mut moves: Vec<(&Move, &f32)> = self.weights.iter().collect();
+        moves.sort_by(|a, b| b.1.total_cmp(a.1));
+        moves
+            .into_iter()
+            .map(|(harmonic_move, weight)| format!("{} {weight}", harmonic_move.label()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+pub(crate) fn load_harmonic_rules(path: &str) -> Result<HarmonicRules> {
+    let content = fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+    HarmonicRules::from_json(&content).with_context(|| format!("failed to parse {path}"))
+}
+
+/// How matched tracks are ordered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum SortMode {
+    /// Camelot wheel position, then BPM.
+    #[default]
+    Wheel,
+    /// Starts from the first track in wheel order, then repeatedly takes
+    /// the track reached by the highest-weighted allowed move, closest in
+    /// BPM on a tie. When no allowed move is left it jumps to the next
+    /// track in wheel order.
+    Harmonic,
+}
+
+impl SortMode {
+    pub(crate) fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "wheel" => Some(Self::Wheel),
+            "harmonic" => Some(Self::Harmonic),
+            _ => None,
+        }
+    }
+}
+
+/// Reorders `(key, bpm, item)` triples, already in wheel order, for
+/// [`SortMode::Harmonic`].
+pub(crate) fn chain_by_moves<T>(
+    mut remaining: Vec<(Key, f32, T)>,
+    rules: &HarmonicRules,
+) -> Vec<(Key, f32, T)> {
+    let mut chained = Vec::with_capacity(remaining.len());
+    if remaining.is_empty() {
+        return chained;
+    }
+    chained.push(remaining.remove(0));
+
+    while !remaining.is_empty() {
+        let (key, bpm, _) = chained[chained.len() - 1];
+        let next = remaining
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (next_key, next_bpm, _))| {
+                let (_, weight) = rules.score(key, *next_key)?;
+                Some((index, weight, (next_bpm - bpm).abs()))
+            })
+            // `min_by` keeps the first of equals, so ties stay in wheel order.
+            .min_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.2.total_cmp(&b.2)))
+            .map_or(0, |(index, _, _)| index);
+        chained.push(remaining.remove(next));
+    }
+    chained
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(raw: &str) -> Key {
+        Key::parse(raw).unwrap()
+    }
+
+    #[test]
+    fn between_names_every_move_from_minor_and_major() {
+        let from_minor: Vec<(&str, Option<Move>)> =
+            ["8A", "9A", "8B", "10A", "3A", "9B", "7B", "2A", "6B"]
+                .into_iter()
+                .map(|to| (to, Move::between(key("8A"), key(to))))
+                .collect();
+        assert_eq!(
+            from_minor,
+            [
+                ("8A", Some(Move::SameKey)),
+                ("9A", Some(Move::Adjacent)),
+                ("8B", Some(Move::Relative)),
+                ("10A", Some(Move::EnergyBoost)),
+                ("3A", Some(Move::EnergyLift)),
+                ("9B", Some(Move::Diagonal)),
+                ("7B", Some(Move::ModeSwitch)),
+                ("2A", None),
+                ("6B", None),
+            ]
+        );
+        assert_eq!(Move::between(key("8B"), key("7A")), Some(Move::Diagonal));
+        assert_eq!(Move::between(key("8B"), key("9A")), Some(Move::ModeSwitch));
+        assert_eq!(
+            Move::between(key("12B"), key("2B")),
+            Some(Move::EnergyBoost)
+        );
+        // Energy boosts only go clockwise.
+        assert_eq!(Move::between(key("10A"), key("8A")), None);
+    }
+
+    #[test]
+    fn from_json_replaces_the_default_moves() {
+        let rules = HarmonicRules::from_json(
+            r#"{"moves": {"same_key": 1.0, "energy_lift": 0.5, "adjacent": 0}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            rules.score(key("8A"), key("3A")),
+            Some((Move::EnergyLift, 0.5))
+        );
+        assert_eq!(rules.score(key("8A"), key("9A")), None);
+        assert_eq!(rules.score(key("8A"), key("8B")), None);
+        assert_eq!(rules.describe(), "same_key 1, energy_lift 0.5");
+
+        assert!(HarmonicRules::from_json(r#"{"moves": {"same_key": 2}}"#).is_err());
+        assert!(HarmonicRules::from_json(r#"{"moves": {"tritone": 1}}"#).is_err());
+    }
+
+    #[test]
+    fn chain_by_moves_follows_the_heaviest_move_then_closest_bpm() {
+        let tracks: Vec<(Key, f32, &str)> = [
+            ("1A", 120.0, "start"),
+            ("3A", 126.0, "boost from start"),
+            ("8A", 124.0, "far"),
+            ("12A", 128.0, "adjacent fast"),
+            ("12A", 121.0, "adjacent slow"),
+        ]
+        .into_iter()
+        .map(|(raw, bpm, name)| (key(raw), bpm, name))
+        .collect();
+
+        let order: Vec<&str> = chain_by_moves(tracks, &HarmonicRules::default())
+            .into_iter()
+            .map(|(_, _, name)| name)
+            .collect();
+        assert_eq!(
+            order,
+            [
+                "start",
+                "adjacent slow",
+                "adjacent fast",
+                "boost from start",
+                "far"
+            ]
+        );
+    }
+}
@@ -0,0 +1,348 @@
+use anyhow::{Context, Result};
+use std::io::{BufRead, Write};
+
+use crate::db::{MAX_BPM, MIN_BPM};
+use crate::key::{Key, Notation};
+use crate::overrides::{save_overrides, MatchOverrides, OverrideAction, OverrideResolution};
+use crate::{
+    ambiguous_candidates, find_match_candidates, format_duration, MetadataIndex, ScoredCandidate,
+    TrackInfo,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Choice {
+    Candidate(usize),
+    Values { bpm: f32, key: Key },
+    Ignore,
+    Skip,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Decision {
+    /// The ranking was unambiguous; keep the automatic winner.
+    Automatic,
+    /// The user deferred; keep the automatic winner for this run only.
+    Skipped,
+    Resolved(OverrideResolution),
+}
+
+/// Asks the user to settle a close call between candidates.
+pub(crate) trait CandidatePrompt {
+    fn choose(&mut self, track: &TrackInfo, candidates: &[ScoredCandidate<'_>]) -> Result<Choice>;
+}
+
+pub(crate) struct TerminalPrompt<R, W> {
+    input: R,
+    output: W,
+    /// How candidate keys are written.
+    notation: Notation,
+}
+
+impl<R: BufRead, W: Write> TerminalPrompt<R, W> {
+    pub(crate) fn new(input: R, output: W, notation: Notation) -> Self {
+        Self {
+            input,
+            output,
+            notation,
+        }
+    }
+
+    /// Reads one trimmed line, or `None` at end of input.
+    fn ask(&mut self, question: &str) -> Result<Option<String>> {
+        write!(self.output, "{question}")?;
+        self.output.flush().context("failed to flush prompt")?;
+
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim().to_string()))
+    }
+
+    fn ask_values(&mut self) -> Result<Option<Choice>> {
+        let bpm = loop {
+            let Some(raw) = self.ask("BPM: ")? else {
+                return Ok(None);
+            };
+            match raw.parse::<f32>() {
+                Ok(bpm) if (MIN_BPM..=MAX_BPM).contains(&bpm) => break bpm,
+                _ => writeln!(
+                    self.output,
+                    "Enter a BPM from {MIN_BPM} to {MAX_BPM}, e.g. 124 or 127.5."
+                )?,
+            }
+        };
+
+        let key = loop {
+            let Some(raw) = self.ask("Key: ")? else {
+                return Ok(None);
+            };
+            if let Some(key) = Key::parse(&raw) {
+                break key;
+            }
+            writeln!(self.output, "Enter a key such as 8A, 1m, Am or F# minor.")?;
+        };
+
+        Ok(Some(Choice::Values { bpm, key }))
+    }
+}
+
+impl<R: BufRead, W: Write> CandidatePrompt for TerminalPrompt<R, W> {
+    fn choose(&mut self, track: &TrackInfo, candidates: &[ScoredCandidate<'_>]) -> Result<Choice> {
+        writeln!(
+            self.output,
+            "[AMBIGUOUS] {} - {} ({})",
+            track.artist,
+            track.name,
+            format_duration(track.duration_ms)
+        )?;
+        for (index, candidate) in candidates.iter().enumerate() {
+            let entry = candidate.entry;
+            writeln!(
+                self.output,
+                "  {}) {} - {} | {} | {:.1} BPM | {} | {} | score {}",
+                index + 1,
+                entry.artist,
+                entry.name,
+                entry.source.label(),
+                entry.bpm,
+                entry.key.format(self.notation),
+                entry
+                    .duration_ms
+                    .map(format_duration)
+                    .unwrap_or_else(|| "-:--".to_string()),
+                candidate.score
+            )?;
+        }
+
+        loop {
+            let question = format!(
+                "Pick 1-{}, (m)anual values, (i)gnore, or (s)kip [s]: ",
+                candidates.len()
+            );
+            let Some(answer) = self.ask(&question)? else {
+                return Ok(Choice::Skip);
+            };
+
+            match answer.to_ascii_lowercase().as_str() {
+                "" | "s" | "skip" => return Ok(Choice::Skip),
+                "i" | "ignore" => return Ok(Choice::Ignore),
+                "m" | "manual" => return Ok(self.ask_values()?.unwrap_or(Choice::Skip)),
+                other => match other.parse::<usize>() {
+                    Ok(number) if (1..=candidates.len()).contains(&number) => {
+                        return Ok(Choice::Candidate(number - 1));
+                    }
+                    _ => writeln!(self.output, "Unrecognized choice '{answer}'.")?,
+                },
+            }
+        }
+    }
+}
+
+/// Prompts for close calls during enrichment and persists every decision to
+/// the overrides file so the same question is never asked twice.
+pub(crate) struct Disambiguation<'a> {
+    pub(crate) prompt: &'a mut dyn CandidatePrompt,
+    pub(crate) overrides_path: &'a str,
+}
+
+impl Disambiguation<'_> {
+    pub(crate) fn decide(
+        &mut self,
+        track: &TrackInfo,
+        metadata_index: &MetadataIndex,
+        overrides: &mut MatchOverrides,
+    ) -> Result<Decision> {
+        let ranked = find_match_candidates(track, metadata_index);
+        let candidates = ambiguous_candidates(&ranked);
+        if candidates.is_empty() {
+            return Ok(Decision::Automatic);
+        }
+
+        let action = match self.prompt.choose(track, candidates)? {
+            Choice::Skip => return Ok(Decision::Skipped),
+            Choice::Ignore => OverrideAction::Ignore,
+            Choice::Values { bpm, key } => OverrideAction::Values { bpm, key },
+            Choice::Candidate(index) => {
+                let chosen = candidates[index].entry;
+                overrides.record(
+                    &track.id,
+                    OverrideAction::Entry {
+                        name: chosen.name.clone(),
+                        artist: chosen.artist.clone(),
+                        duration_ms: chosen.duration_ms,
+                    },
+                );
+
+                // Entries that share title, artist and duration cannot be pinned
+                // by identity, so fall back to storing the chosen values.
+                match overrides.resolve(track, metadata_index) {
+                    Some(OverrideResolution::Matched(entry)) if entry == *chosen => {
+                        save_overrides(self.overrides_path, overrides)?;
+                        return Ok(Decision::Resolved(OverrideResolution::Matched(entry)));
+                    }
+                    _ => OverrideAction::Values {
+                        bpm: chosen.bpm,
+                        key: chosen.key,
+                    },
+                }
+            }
+        };
+
+        overrides.record(&track.id, action);
+        save_overrides(self.overrides_path, overrides)?;
+        match overrides.resolve(track, metadata_index) {
+            Some(resolution) => Ok(Decision::Resolved(resolution)),
+            None => Ok(Decision::Automatic),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MetadataEntry, MetadataSource};
+
+    fn track() -> TrackInfo {
+        TrackInfo {
+            id: "track-id".to_string(),
+            name: "Losing It".to_string(),
+            artist: "FISHER".to_string(),
+            key: None,
+            tempo: None,
+            duration_ms: 248_000,
+        }
+    }
+
+    fn entries() -> Vec<MetadataEntry> {
+        [125.0, 124.0]
+            .into_iter()
+            .map(|bpm| MetadataEntry {
+                name: "Losing It".to_string(),
+                artist: "FISHER".to_string(),
+                bpm,
+                bpm_confidence: None,
+                key: Key::parse("10B").unwrap(),
+                duration_ms: None,
+                genre: None,
+                source: MetadataSource::LocalDb.into(),
+            })
+            .collect()
+    }
+
+    fn choose(input: &str) -> (Choice, String) {
+        let entries = entries();
+        let candidates: Vec<ScoredCandidate<'_>> = entries
+            .iter()
+            .map(|entry| ScoredCandidate { entry, score: 120 })
+            .collect();
+        let mut output = Vec::new();
+        let choice = TerminalPrompt::new(input.as_bytes(), &mut output, Notation::Camelot)
+            .choose(&track(), &candidates)
+            .unwrap();
+        (choice, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn choose_lists_candidates_and_accepts_a_number() {
+        let (choice, output) = choose("7\n2\n");
+        assert_eq!(choice, Choice::Candidate(1));
+        assert!(output.contains("2) FISHER - Losing It | LOCAL_DB | 124.0 BPM | 10B | -:--"));
+        assert!(output.contains("Unrecognized choice '7'."));
+    }
+
+    #[test]
+    fn choose_validates_manual_values() {
+        let (choice, _) = choose("m\nfast\n126\n13A\n8a\n");
+        assert_eq!(
+            choice,
+            Choice::Values {
+                bpm: 126.0,
+                key: Key::parse("8A").unwrap()
+            }
+        );
+    }
+
+    #[test]
+    fn choose_asks_again_for_bpm_outside_the_database_range() {
+        let (choice, output) = choose("m\n300\n20\n126\n8A\n");
+        assert_eq!(
+            choice,
+            Choice::Values {
+                bpm: 126.0,
+                key: Key::parse("8A").unwrap()
+            }
+        );
+        assert_eq!(output.matches("Enter a BPM from 40 to 250").count(), 2);
+    }
+
+    struct Scripted(Choice);
+
+    impl CandidatePrompt for Scripted {
+        fn choose(&mut self, _: &TrackInfo, _: &[ScoredCandidate<'_>]) -> Result<Choice> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn decide_persists_values_when_entries_cannot_be_pinned() {
+        let metadata_index = crate::build_metadata_index(entries());
+        let path = std::env::temp_dir().join(format!(
+            "mix_sorter_decide_overrides_{}.json",
+            std::process::id()
+        ));
+        let path = path.to_str().unwrap();
+        let mut prompt = Scripted(Choice::Candidate(0));
+        let mut overrides = MatchOverrides::default();
+
+        let resolution = Disambiguation {
+            prompt: &mut prompt,
+            overrides_path: path,
+        }
+        .decide(&track(), &metadata_index, &mut overrides)
+        .unwrap();
+
+        match resolution {
+            Decision::Resolved(OverrideResolution::Matched(entry)) => {
+                assert_eq!(entry.bpm, 125.0);
+                assert_eq!(entry.source.kind, MetadataSource::Override);
+            }
+            other => panic!("unexpected resolution: {other:?}"),
+        }
+        let saved = crate::overrides::load_overrides(path).unwrap();
+        assert_eq!(saved, overrides);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn decide_does_not_ask_about_bpm_rounding_noise() {
+        struct NeverAsked;
+
+        impl CandidatePrompt for NeverAsked {
+            fn choose(&mut self, track: &TrackInfo, _: &[ScoredCandidate<'_>]) -> Result<Choice> {
+                panic!("asked about {} - {}", track.artist, track.name);
+            }
+        }
+
+        let mut entries = entries();
+        entries[1].bpm = 124.99;
+        let metadata_index = crate::build_metadata_index(entries);
+        let mut overrides = MatchOverrides::default();
+
+        let decision = Disambiguation {
+            prompt: &mut NeverAsked,
+            overrides_path: "unused_overrides.json",
+        }
+        .decide(&track(), &metadata_index, &mut overrides)
+        .unwrap();
+        assert_eq!(decision, Decision::Automatic);
+        assert_eq!(overrides, MatchOverrides::default());
+    }
+
+    #[test]
+    fn choose_skips_on_empty_answer_or_end_of_input() {
+        assert_eq!(choose("\n").0, Choice::Skip);
+        assert_eq!(choose("").0, Choice::Skip);
+        assert_eq!(choose("i\n").0, Choice::Ignore);
+    }
+}
@@ -0,0 +1,401 @@
+use rspotify::model::Modality;
+use std::cmp::Ordering;
+
+/// How keys are written in reports and prompts. Stored data always uses
+/// Camelot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Notation {
+    /// `8A`, `10B`.
+    #[default]
+    Camelot,
+    /// `1m`, `3d`, as shown by Traktor.
+    OpenKey,
+    /// `Am`, `D`, `F#m`, `Bb`.
+    Musical,
+    /// Spotify's pitch class and mode, `9:0` for A minor.
+    Spotify,
+}
+
+impl Notation {
+    pub(crate) fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "camelot" => Some(Self::Camelot),
+            "open-key" => Some(Self::OpenKey),
+            "musical" => Some(Self::Musical),
+            "spotify" => Some(Self::Spotify),
+            _ => None,
+        }
+    }
+}
+
+/// Formats a stored key in `notation`. Text that is not a key is shown as
+/// it is.
+pub(crate) fn display_key(raw: &str, notation: Notation) -> String {
+    match Key::parse(raw) {
+        Some(key) => key.format(notation),
+        None => raw.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Mode {
+    Major,
+    Minor,
+}
+
+impl Mode {
+    fn flip(self) -> Self {
+        match self {
+            Self::Major => Self::Minor,
+            Self::Minor => Self::Major,
+        }
+    }
+}
+
+/// A major or minor key, by tonic pitch class (0 = C, as Spotify numbers
+/// them) and mode.
+///
+/// The Camelot wheel orders keys round the circle of fifths: each step
+/// clockwise adds a fifth (seven semitones), `8B` is C major, and the minor
+/// key at the same number is the relative minor, three semitones lower.
+/// Keys order by wheel position, `1A`, `1B`, `2A` ... `12B`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Key {
+    pitch_class: i32,
+    mode: Mode,
+}
+
+const SHARP_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+const FLAT_NAMES: [&str; 12] = [
+    "C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B",
+];
+
+impl Key {
+    pub(crate) fn new(pitch_class: i32, mode: Mode) -> Option<Self> {
+        (0..12)
+            .contains(&pitch_class)
+            .then_some(Self { pitch_class, mode })
+    }
+
+    pub(crate) fn from_spotify(pitch_class: i32, mode: Modality) -> Option<Self> {
+        match mode {
+            Modality::Major => Self::new(pitch_class, Mode::Major),
+            Modality::Minor => Self::new(pitch_class, Mode::Minor),
+            Modality::NoResult => None,
+        }
+    }
+
+    /// Reads Camelot (`8A`), Open Key (`1m`), musical (`Am`, `C#maj`,
+    /// `D♭ minor`) or Spotify (`9:0`) notation, ignoring case and padding.
+    pub(crate) fn parse(raw: &str) -> Option<Self> {
+        let raw = raw.trim();
+        Self::from_camelot(raw)
+            .or_else(|| Self::from_open_key(raw))
+            .or_else(|| Self::from_musical(raw))
+            .or_else(|| Self::from_spotify_text(raw))
+    }
+
+    /// `number` is the wheel position, 1-12.
+    pub(crate) fn from_wheel(number: u8, mode: Mode) -> Option<Self> {
+        if !(1..=12).contains(&number) {
+            return None;
+        }
+        // Seven is its own inverse modulo 12, so stepping back from 8B by
+        // fifths undoes the `7 * pitch` in `wheel`.
+        let major = (7 * (i32::from(number) - 8)).rem_euclid(12);
+        match mode {
+            Mode::Major => Self::new(major, mode),
+            Mode::Minor => Self::new((major - 3).rem_euclid(12), mode),
+        }
+    }
+
+    pub(crate) fn from_camelot(raw: &str) -> Option<Self> {
+        let raw = raw.trim().to_ascii_uppercase();
+        let mode = match raw.chars().last()? {
+            'A' => Mode::Minor,
+            'B' => Mode::Major,
+            _ => return None,
+        };
+        Self::from_wheel(raw[..raw.len() - 1].parse().ok()?, mode)
+    }
+
+    /// Open Key `1d` is C major (Camelot `8B`); numbers run round the same
+    /// circle of fifths, seven steps ahead of Camelot.
+    pub(crate) fn from_open_key(raw: &str) -> Option<Self> {
+        let raw = raw.trim().to_ascii_lowercase();
+        let mode = match raw.chars().last()? {
+            'd' => Mode::Major,
+            'm' => Mode::Minor,
+            _ => return None,
+        };
+        let number: u8 = raw[..raw.len() - 1].parse().ok()?;
+        if !(1..=12).contains(&number) {
+            return None;
+        }
+        Self::from_wheel((number + 6) % 12 + 1, mode)
+    }
+
+    fn from_musical(raw: &str) -> Option<Self> {
+        let mut chars = raw.chars();
+        let natural: i32 = match chars.next()?.to_ascii_uppercase() {
+            'C' => 0,
+            'D' => 2,
+            'E' => 4,
+            'F' => 5,
+            'G' => 7,
+            'A' => 9,
+            'B' => 11,
+            _ => return None,
+        };
+        let rest = chars.as_str();
+        let (shift, rest) = match rest.chars().next() {
+            Some(accidental @ ('#' | '♯')) => (1, &rest[accidental.len_utf8()..]),
+            Some(accidental @ ('b' | '♭')) => (-1, &rest[accidental.len_utf8()..]),
+            _ => (0, rest),
+        };
+        let mode = match rest.trim().to_ascii_lowercase().as_str() {
+            "" | "maj" | "major" => Mode::Major,
+            "m" | "min" | "minor" => Mode::Minor,
+            _ => return None,
+        };
+        Self::new((natural + shift).rem_euclid(12), mode)
+    }
+
+    fn from_spotify_text(raw: &str) -> Option<Self> {
+        let (pitch_class, mode) = raw.split_once(':')?;
+        let mode = match mode.trim() {
+            "1" => Modality::Major,
+            "0" => Modality::Minor,
+            _ => return None,
+        };
+        Self::from_spotify(pitch_class.trim().parse().ok()?, mode)
+    }
+
+    pub(crate) fn mode(self) -> Mode {
+        self.mode
+    }
+
+    /// Camelot wheel position, 1-12.
+    pub(crate) fn wheel(self) -> u8 {
+        let major = match self.mode {
+            Mode::Major => self.pitch_class,
+            Mode::Minor => self.pitch_class + 3,
+        };
+        ((7 * major + 7) % 12 + 1) as u8
+    }
+
+    /// Moves `steps` positions round the wheel (negative is anticlockwise),
+    /// keeping the mode.
+    pub(crate) fn step(self, steps: i32) -> Self {
+        let number = (i32::from(self.wheel()) - 1 + steps).rem_euclid(12) + 1;
+        Self::from_wheel(number as u8, self.mode).expect("wheel numbers are 1-12")
+    }
+
+    /// The same mode `semitones` higher (negative is lower). Seven semitones
+    /// is one step clockwise.
+    pub(crate) fn transpose(self, semitones: i32) -> Self {
+        Self {
+            pitch_class: (self.pitch_class + semitones).rem_euclid(12),
+            mode: self.mode,
+        }
+    }
+
+    /// One step either way round the wheel, anticlockwise first.
+    pub(crate) fn neighbours(self) -> [Self; 2] {
+        [self.step(-1), self.step(1)]
+    }
+
+    /// The key at the same wheel number in the other mode (`8A` and `8B`).
+    pub(crate) fn relative(self) -> Self {
+        Self::from_wheel(self.wheel(), self.mode.flip()).expect("wheel numbers are 1-12")
+    }
+
+    /// Two steps clockwise, a whole tone up: the usual energy-boost mix.
+    pub(crate) fn energy_boost(self) -> Self {
+        self.step(2)
+    }
+
+    /// Steps between the two wheel numbers the short way round, 0-6,
+    /// whatever the modes.
+    pub(crate) fn wheel_distance(self, other: Self) -> u8 {
+        let diff = (i32::from(other.wheel()) - i32::from(self.wheel())).rem_euclid(12);
+        diff.min(12 - diff) as u8
+    }
+
+    pub(crate) fn camelot(self) -> String {
+        let letter = match self.mode {
+            Mode::Major => 'B',
+            Mode::Minor => 'A',
+        };
+        format!("{}{letter}", self.wheel())
+    }
+
+    pub(crate) fn open_key(self) -> String {
+        let letter = match self.mode {
+            Mode::Major => 'd',
+            Mode::Minor => 'm',
+        };
+        format!("{}{letter}", (self.wheel() + 4) % 12 + 1)
+    }
+
+    /// Flats for the keys usually written with them (`Bb`, `Ebm`), sharps
+    /// otherwise (`F#`, `C#m`).
+    pub(crate) fn musical(self) -> String {
+        let pitch = self.pitch_class as usize;
+        match self.mode {
+            Mode::Major if [1, 3, 8, 10].contains(&pitch) => FLAT_NAMES[pitch].to_string(),
+            Mode::Major => SHARP_NAMES[pitch].to_string(),
+            Mode::Minor if [3, 10].contains(&pitch) => format!("{}m", FLAT_NAMES[pitch]),
+            Mode::Minor => format!("{}m", SHARP_NAMES[pitch]),
+        }
+    }
+
+    pub(crate) fn spotify(self) -> String {
+        let mode = match self.mode {
+            Mode::Major => 1,
+            Mode::Minor => 0,
+        };
+        format!("{}:{mode}", self.pitch_class)
+    }
+
+    pub(crate) fn format(self, notation: Notation) -> String {
+        match notation {
+            Notation::Camelot => self.camelot(),
+            Notation::OpenKey => self.open_key(),
+            Notation::Musical => self.musical(),
+            Notation::Spotify => self.spotify(),
+        }
+    }
+}
+
+/// Written as Camelot; read from any notation [`Key::parse`] accepts.
+impl serde::Serialize for Key {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.camelot())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Key {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        Key::parse(&raw).ok_or_else(|| {
+            serde::de::Error::custom(format!("'{raw}' is not a Camelot, Open Key or musical key"))
+        })
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let position = |key: &Self| (key.wheel(), key.mode == Mode::Major);
+        position(self).cmp(&position(other))
+    }
+}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_every_notation() {
+        let a_minor = Key::new(9, Mode::Minor);
+        for raw in [
+            "8A", "08a", " 8A ", "1m", "1M", "Am", "A minor", "Amin", "9:0",
+        ] {
+            assert_eq!(Key::parse(raw), a_minor, "{raw}");
+        }
+        let d_flat = Key::new(1, Mode::Major);
+        for raw in ["3B", "8d", "Db", "D♭", "C#maj", "C♯ major", "1:1"] {
+            assert_eq!(Key::parse(raw), d_flat, "{raw}");
+        }
+        for raw in ["", "13A", "0B", "13d", "H", "Cx", "12:1", "9:2", "A dorian"] {
+            assert_eq!(Key::parse(raw), None, "{raw}");
+        }
+    }
+
+    #[test]
+    fn every_notation_round_trips_all_24_keys() {
+        for pitch_class in 0..12 {
+            for mode in [Mode::Major, Mode::Minor] {
+                let key = Key::new(pitch_class, mode).unwrap();
+                for notation in [
+                    Notation::Camelot,
+                    Notation::OpenKey,
+                    Notation::Musical,
+                    Notation::Spotify,
+                ] {
+                    assert_eq!(Key::parse(&key.format(notation)), Some(key), "{key:?}");
+                }
+            }
+        }
+        assert_eq!(Key::new(12, Mode::Major), None);
+
+        let e_flat_minor = Key::parse("2A").unwrap();
+        assert_eq!(
+            [
+                e_flat_minor.open_key(),
+                e_flat_minor.musical(),
+                e_flat_minor.spotify()
+            ],
+            ["7m", "Ebm", "3:0"]
+        );
+
+        assert_eq!(serde_json::to_string(&e_flat_minor).unwrap(), "\"2A\"");
+        assert_eq!(
+            serde_json::from_str::<Key>("\"Ebm\"").unwrap(),
+            e_flat_minor
+        );
+        let err = serde_json::from_str::<Key>("\"13A\"").unwrap_err();
+        assert!(err.to_string().contains("'13A' is not a Camelot"));
+    }
+
+    #[test]
+    fn wheel_matches_spotify_pitch_classes() {
+        assert_eq!(Key::from_camelot("5A"), Key::new(0, Mode::Minor));
+        let d_major = Key::from_camelot("10B").unwrap();
+        assert_eq!((d_major.pitch_class, d_major.mode), (2, Mode::Major));
+        assert_eq!(Key::from_camelot("13B"), None);
+        assert_eq!(Key::from_camelot("5"), None);
+        assert_eq!(Key::from_spotify(-1, Modality::Major), None);
+        assert_eq!(Key::from_spotify(9, Modality::NoResult), None);
+
+        // Every wheel step clockwise is a fifth up.
+        for number in 1..=12 {
+            for mode in [Mode::Major, Mode::Minor] {
+                let key = Key::from_wheel(number, mode).unwrap();
+                assert_eq!(key.wheel(), number);
+                assert_eq!(key.step(1).pitch_class, (key.pitch_class + 7) % 12);
+            }
+        }
+        let mut keys: Vec<Key> = ["12B", "1B", "8A", "1A", "8B"]
+            .iter()
+            .filter_map(|raw| Key::parse(raw))
+            .collect();
+        keys.sort();
+        let sorted: Vec<String> = keys.into_iter().map(Key::camelot).collect();
+        assert_eq!(sorted, ["1A", "1B", "8A", "8B", "12B"]);
+    }
+
+    #[test]
+    fn wheel_moves_wrap_round_the_circle() {
+        let key = |raw| Key::parse(raw).unwrap();
+        assert_eq!(key("1A").neighbours(), [key("12A"), key("2A")]);
+        assert_eq!(key("8A").relative(), key("8B"));
+        assert_eq!(key("C").relative(), key("Am"));
+        assert_eq!(key("11B").energy_boost(), key("1B"));
+        assert_eq!(key("12A").step(-13), key("11A"));
+        assert_eq!(key("8A").transpose(1), key("3A"));
+        assert_eq!(key("8A").transpose(-13), key("1A"));
+        assert_eq!(key("8B").transpose(7), key("8B").step(1));
+        assert_eq!(key("1A").wheel_distance(key("12B")), 1);
+        assert_eq!(key("2A").wheel_distance(key("8A")), 6);
+        assert_eq!(key("9B").wheel_distance(key("5A")), 4);
+    }
+}
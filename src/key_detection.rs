@@ -0,0 +1,176 @@
+use crate::analysis::{for_each_spectrum, Audio, ANALYSIS_RATE};
+use crate::key::{Key, Mode};
+
+/// About 0.75 s per frame at [`ANALYSIS_RATE`], fine enough to separate
+/// semitones down to A1.
+const FRAME_SIZE: usize = 8192;
+const HOP: usize = 4096;
+/// A1 to roughly B6: below this bins are wider than a semitone, above it
+/// the spectrum is mostly harmonics and percussion.
+const MIN_FREQUENCY: f32 = 55.0;
+const MAX_FREQUENCY: f32 = 2_000.0;
+
+/// Krumhansl-Kessler probe-tone profiles, from the tonic upwards.
+const MAJOR_PROFILE: [f32; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f32; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+/// One key reading and how well the track's pitch content fits it
+/// (Pearson correlation, -1 to 1).
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct KeyCandidate {
+    pub(crate) key: Key,
+    pub(crate) correlation: f32,
+}
+
+/// The best-fitting key and the next best, which is usually the relative
+/// or a neighbouring key when the track is ambiguous.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct KeyEstimate {
+    pub(crate) key: Key,
+    /// The best key's correlation, clipped to 0-1.
+    pub(crate) confidence: f32,
+    pub(crate) runner_up: KeyCandidate,
+}
+
+/// Energy per pitch class (0 = C), each frame normalized so loud passages
+/// do not outweigh quiet ones.
+fn chromagram(audio: &Audio) -> [f32; 12] {
+    let bin_width = ANALYSIS_RATE as f32 / FRAME_SIZE as f32;
+    let pitch_classes: Vec<Option<usize>> = (0..=FRAME_SIZE / 2)
+        .map(|bin| {
+            let frequency = bin as f32 * bin_width;
+            (MIN_FREQUENCY..=MAX_FREQUENCY)
+                .contains(&frequency)
+                .then(|| {
+                    let midi = 69.0 + 12.0 * (frequency / 440.0).log2();
+                    (midi.round() as i64).rem_euclid(12) as usize
+                })
+        })
+        .collect();
+
+    let mut chroma = [0.0; 12];
+    for_each_spectrum(audio, FRAME_SIZE, HOP, |magnitudes| {
+        let mut frame = [0.0; 12];
+        for (magnitude, pitch_class) in magnitudes.iter().zip(&pitch_classes) {
+            if let Some(pitch_class) = pitch_class {
+                frame[*pitch_class] += magnitude;
+            }
+        }
+        let total: f32 = frame.iter().sum();
+        if total > f32::EPSILON {
+            for (sum, value) in chroma.iter_mut().zip(frame) {
+                *sum += value / total;
+            }
+        }
+    });
+    chroma
+}
+
+fn correlation(chroma: &[f32; 12], profile: &[f32; 12], tonic: usize) -> f32 {
+    let rotated: Vec<f32> = (0..12).map(|pc| profile[(pc + 12 - tonic) % 12]).collect();
+    let mean_chroma = chroma.iter().sum::<f32>() / 12.0;
+    let mean_profile = rotated.iter().sum::<f32>() / 12.0;
+    let (mut covariance, mut chroma_var, mut profile_var) = (0.0, 0.0, 0.0);
+    for (c, p) in chroma.iter().zip(&rotated) {
+        covariance += (c - mean_chroma) * (p - mean_profile);
+        chroma_var += (c - mean_chroma).powi(2);
+        profile_var += (p - mean_profile).powi(2);
+    }
+    covariance / (chroma_var * profile_var).sqrt()
+}
+
+/// Correlates the track's chromagram with all 24 major and minor key
+/// profiles. Returns `None` for audio with no pitched content.
+pub(crate) fn estimate_key(audio: &Audio) -> Option<KeyEstimate> {
+    let chroma = chromagram(audio);
+
+    let mut candidates: Vec<KeyCandidate> =
+        [(Mode::Major, &MAJOR_PROFILE), (Mode::Minor, &MINOR_PROFILE)]
+            .into_iter()
+            .flat_map(|(mode, profile)| {
+                // A flat (or silent) chromagram has no variance and no correlation.
+                (0..12).filter_map(move |tonic| {
+                    Some(KeyCandidate {
+                        key: Key::new(tonic as i32, mode)?,
+                        correlation: Some(correlation(&chroma, profile, tonic))
+                            .filter(|r| r.is_finite())?,
+                    })
+                })
+            })
+            .collect();
+    candidates.sort_by(|a, b| b.correlation.total_cmp(&a.correlation));
+
+    let mut candidates = candidates.into_iter();
+    let best = candidates.next()?;
+    Some(KeyEstimate {
+        key: best.key,
+        confidence: best.correlation.clamp(0.0, 1.0),
+        runner_up: candidates.next()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two seconds per chord; each note is a sine with two weaker overtones.
+    fn progression(chords: &[&[f32]]) -> Audio {
+        let rate = ANALYSIS_RATE as f32;
+        let chord_samples = 2 * ANALYSIS_RATE as usize;
+        let samples = (0..chords.len() * chord_samples)
+            .map(|n| {
+                let t = n as f32 / rate;
+                chords[n / chord_samples]
+                    .iter()
+                    .map(|midi| {
+                        let frequency = 440.0 * 2f32.powf((midi - 69.0) / 12.0);
+                        (1..=3)
+                            .map(|harmonic| {
+                                let h = harmonic as f32;
+                                (2.0 * std::f32::consts::PI * frequency * h * t).sin() / (h * h)
+                            })
+                            .sum::<f32>()
+                    })
+                    .sum::<f32>()
+                    * 0.1
+            })
+            .collect();
+        Audio { samples }
+    }
+
+    #[test]
+    fn estimate_key_finds_minor_and_major_progressions() {
+        // Am - Dm - E - Am: i, iv, V with the G# leading tone.
+        let a_minor = progression(&[
+            &[57.0, 60.0, 64.0],
+            &[50.0, 62.0, 65.0, 69.0],
+            &[52.0, 56.0, 59.0, 64.0],
+            &[45.0, 57.0, 60.0, 64.0],
+        ]);
+        let estimate = estimate_key(&a_minor).unwrap();
+        assert_eq!(estimate.key.camelot(), "8A", "{estimate:?}");
+        assert!(estimate.confidence > 0.5, "{estimate:?}");
+        assert!(estimate.runner_up.correlation < estimate.confidence);
+
+        // D - G - A - D.
+        let d_major = progression(&[
+            &[50.0, 62.0, 66.0, 69.0],
+            &[55.0, 62.0, 67.0, 71.0],
+            &[57.0, 61.0, 64.0, 69.0],
+            &[50.0, 62.0, 66.0, 69.0],
+        ]);
+        assert_eq!(estimate_key(&d_major).unwrap().key.camelot(), "10B");
+    }
+
+    #[test]
+    fn estimate_key_gives_up_on_silence() {
+        let silence = Audio {
+            samples: vec![0.0; ANALYSIS_RATE as usize * 5],
+        };
+        assert_eq!(estimate_key(&silence), None);
+    }
+}
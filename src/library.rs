@@ -0,0 +1,137 @@
+use crate::key::Key;
+use crate::{MetadataEntry, MetadataSource};
+
+/// Why a DJ library track could not become a metadata entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SkipReason {
+    MissingBpm,
+    MissingKey,
+    InvalidBpm,
+    InvalidKey,
+}
+
+/// A library track's key, parsed as it is read from the export.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) enum LibraryKey {
+    #[default]
+    Missing,
+    Invalid,
+    Parsed(Key),
+}
+
+impl LibraryKey {
+    /// Reads `raw` with `parse`; blank text is a missing key.
+    pub(crate) fn read(raw: &str, parse: impl FnOnce(&str) -> Option<Key>) -> Self {
+        let raw = raw.trim();
+        if raw.is_empty() {
+            return Self::Missing;
+        }
+        parse(raw).map_or(Self::Invalid, Self::Parsed)
+    }
+}
+
+/// Raw fields of one track in a DJ library export.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct LibraryTrack {
+    pub(crate) name: String,
+    pub(crate) artist: String,
+    pub(crate) bpm: String,
+    /// 0-1, for BPMs estimated from audio.
+    pub(crate) bpm_confidence: Option<f32>,
+    pub(crate) key: LibraryKey,
+    pub(crate) duration_ms: Option<u32>,
+    pub(crate) genre: String,
+}
+
+impl LibraryTrack {
+    /// Converts the raw fields; a BPM of 0 means the track was never analysed.
+    /// BPMs are rounded to two decimals to drop float noise such as `125.000061`.
+    pub(crate) fn into_entry(
+        self,
+        kind: MetadataSource,
+    ) -> std::result::Result<MetadataEntry, SkipReason> {
+        let bpm_raw = self.bpm.trim();
+        if bpm_raw.is_empty() {
+            return Err(SkipReason::MissingBpm);
+        }
+        let bpm = bpm_raw
+            .parse::<f32>()
+            .ok()
+            .filter(|bpm| bpm.is_finite() && *bpm >= 0.0)
+            .ok_or(SkipReason::InvalidBpm)?;
+        if bpm == 0.0 {
+            return Err(SkipReason::MissingBpm);
+        }
+
+        let key = match self.key {
+            LibraryKey::Missing => return Err(SkipReason::MissingKey),
+            LibraryKey::Invalid => return Err(SkipReason::InvalidKey),
+            LibraryKey::Parsed(key) => key,
+        };
+
+        Ok(MetadataEntry {
+            name: self.name.trim().to_string(),
+            artist: self.artist.trim().to_string(),
+            bpm: (bpm * 100.0).round() / 100.0,
+            bpm_confidence: self.bpm_confidence,
+            key,
+            duration_ms: self.duration_ms.filter(|duration| *duration > 0),
+            genre: Some(self.genre.trim().to_string()).filter(|genre| !genre.is_empty()),
+            source: kind.into(),
+        })
+    }
+}
+
+/// Entries read from a DJ library export, and counts of skipped tracks.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct LibraryImport {
+    pub(crate) entries: Vec<MetadataEntry>,
+    pub(crate) missing_bpm: usize,
+    pub(crate) missing_key: usize,
+    pub(crate) invalid_bpm: usize,
+    pub(crate) invalid_key: usize,
+}
+
+impl LibraryImport {
+    pub(crate) fn push(&mut self, track: LibraryTrack, kind: MetadataSource) {
+        match track.into_entry(kind) {
+            Ok(entry) => self.entries.push(entry),
+            Err(SkipReason::MissingBpm) => self.missing_bpm += 1,
+            Err(SkipReason::MissingKey) => self.missing_key += 1,
+            Err(SkipReason::InvalidBpm) => self.invalid_bpm += 1,
+            Err(SkipReason::InvalidKey) => self.invalid_key += 1,
+        }
+    }
+
+    pub(crate) fn skipped(&self) -> usize {
+        self.missing_bpm + self.missing_key + self.invalid_bpm + self.invalid_key
+    }
+
+    pub(crate) fn skip_summary(&self) -> String {
+        format!(
+            "missing_bpm={}, missing_key={}, invalid_bpm={}, invalid_key={}",
+            self.missing_bpm, self.missing_key, self.invalid_bpm, self.invalid_key
+        )
+    }
+}
+
+/// One `Artist - Title | bpm | key | duration | genre` line per imported
+/// entry, for the importer tests.
+#[cfg(test)]
+pub(crate) fn describe_entries(import: &LibraryImport) -> Vec<String> {
+    import
+        .entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "{} - {} | {} | {} | {:?} | {:?}",
+                entry.artist,
+                entry.name,
+                entry.bpm,
+                entry.key.camelot(),
+                entry.duration_ms,
+                entry.genre
+            )
+        })
+        .collect()
+}
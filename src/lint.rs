@@ -0,0 +1,454 @@
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+
+use crate::db::{open_track_store, optional_field_problems, MAX_BPM, MIN_BPM};
+use crate::key::Key;
+use crate::{normalize, LocalTrackData, DURATION_MATCH_TOLERANCE_MS};
+
+/// BPMs below this are usually a half-time reading of a faster track.
+const HALF_TIME_BELOW: f32 = 70.0;
+/// BPMs above this are usually a double-time reading of a slower track.
+const DOUBLE_TIME_ABOVE: f32 = 180.0;
+/// How far a BPM ratio may stray from exactly 2 and still count as half/double.
+const TEMPO_RATIO_TOLERANCE: f32 = 0.03;
+/// Title suffixes that name the plain recording rather than another version.
+const ORIGINAL_VERSION: [&str; 3] = ["original mix", "original version", "original"];
+/// Where a featured-artist credit starts; titles are padded with spaces.
+const FEATURE_MARKERS: [&str; 4] = [" feat. ", " feat ", " ft. ", " featuring "];
+/// Where a secondary artist starts in an artist field.
+const ARTIST_SEPARATORS: [&str; 2] = [" & ", ", "];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Error => "ERROR",
+            Self::Warning => "WARN",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Finding {
+    pub(crate) severity: Severity,
+    /// 1-based entry position, as printed by `db search`.
+    pub(crate) number: usize,
+    pub(crate) message: String,
+}
+
+fn describe(track: &LocalTrackData) -> String {
+    format!("{} - {}", track.artist, track.name)
+}
+
+fn check_entry(number: usize, track: &LocalTrackData, findings: &mut Vec<Finding>) {
+    let mut report = |severity, message: String| {
+        findings.push(Finding {
+            severity,
+            number,
+            message,
+        })
+    };
+
+    if track.name.trim().is_empty() {
+        report(Severity::Error, "empty track name".to_string());
+    }
+    if track.artist.trim().is_empty() {
+        report(Severity::Error, "empty artist".to_string());
+    }
+    if Key::parse(&track.key_camelot).is_none() {
+        report(
+            Severity::Error,
+            format!("invalid key '{}'", track.key_camelot),
+        );
+    }
+    for problem in optional_field_problems(track) {
+        report(Severity::Error, problem);
+    }
+
+    if !track.bpm.is_finite() {
+        report(Severity::Error, format!("non-finite BPM {}", track.bpm));
+    } else if !(MIN_BPM..=MAX_BPM).contains(&track.bpm) {
+        report(
+            Severity::Error,
+            format!("BPM {} is outside {MIN_BPM}-{MAX_BPM}", track.bpm),
+        );
+    } else if track.bpm < HALF_TIME_BELOW {
+        report(
+            Severity::Warning,
+            format!(
+                "BPM {} looks like half-time; double-time would be {}",
+                track.bpm,
+                track.bpm * 2.0
+            ),
+        );
+    } else if track.bpm > DOUBLE_TIME_ABOVE {
+        report(
+            Severity::Warning,
+            format!(
+                "BPM {} looks like double-time; half-time would be {}",
+                track.bpm,
+                track.bpm / 2.0
+            ),
+        );
+    }
+}
+
+/// Keys in different notations are equal when they name the same key;
+/// unparseable ones only when they read the same.
+fn same_key(left: &str, right: &str) -> bool {
+    match (Key::parse(left), Key::parse(right)) {
+        (Some(left), Some(right)) => left == right,
+        _ => left.eq_ignore_ascii_case(right),
+    }
+}
+
+/// Cuts `text` at the first of `markers`.
+fn cut_at<'a>(text: &'a str, markers: &[&str]) -> &'a str {
+    markers
+        .iter()
+        .filter_map(|marker| text.find(marker))
+        .min()
+        .map_or(text, |position| &text[..position])
+}
+
+/// A bracketed or dashed title part that credits the original version or a
+/// featured artist, `(Original Mix)` or `[feat. Clara La San]`.
+fn is_credit(part: &str) -> bool {
+    ORIGINAL_VERSION.contains(&part.trim())
+        || cut_at(&format!(" {} ", part.trim()), &FEATURE_MARKERS).is_empty()
+}
+
+/// Drops an unbracketed `feat. X` from a title part, up to the next bracket.
+fn drop_features(part: &str) -> String {
+    let padded = format!(" {part} ");
+    let head = cut_at(&padded, &FEATURE_MARKERS);
+    let tail = padded[head.len()..]
+        .find(['(', '['])
+        .map_or("", |bracket| &padded[head.len() + bracket..]);
+    format!("{head} {tail}")
+}
+
+/// The title with original-version and featured-artist credits removed, so
+/// `Glue (Original Mix)` and `Glue feat. X` both read `glue` while
+/// `Glue (Extended Mix)` stays a version of its own.
+fn base_title(name: &str) -> String {
+    let lower = name.to_lowercase();
+    let mut kept = String::new();
+    let mut rest = lower.as_str();
+    while let Some(open) = rest.find(['(', '[']) {
+        let close = if rest[open..].starts_with('(') {
+            ')'
+        } else {
+            ']'
+        };
+        let Some(length) = rest[open..].find(close) else {
+            break;
+        };
+        kept.push_str(&rest[..open]);
+        if !is_credit(&rest[open + 1..open + length]) {
+            kept.push_str(&rest[open..=open + length]);
+        }
+        rest = &rest[open + length + 1..];
+    }
+    kept.push_str(rest);
+
+    let parts: Vec<String> = kept
+        .split(" - ")
+        .enumerate()
+        .filter(|(index, part)| *index == 0 || !is_credit(part))
+        .map(|(_, part)| drop_features(part))
+        .collect();
+    normalize(&parts.join(" "))
+}
+
+/// The first credited artist, `bicep` for `Bicep feat. Clara La San`.
+fn base_artist(artist: &str) -> String {
+    let lower = format!(" {} ", artist.to_lowercase());
+    let separators: Vec<&str> = FEATURE_MARKERS
+        .iter()
+        .chain(&ARTIST_SEPARATORS)
+        .copied()
+        .collect();
+    normalize(cut_at(&lower, &separators))
+}
+
+fn is_half_or_double(left: f32, right: f32) -> bool {
+    let ratio = left.max(right) / left.min(right);
+    (ratio - 2.0).abs() <= 2.0 * TEMPO_RATIO_TOLERANCE
+}
+
+/// Compares `later` against an earlier entry with the same normalized title
+/// and artist.
+fn check_pair(
+    (first_number, first): (usize, &LocalTrackData),
+    (number, later): (usize, &LocalTrackData),
+    findings: &mut Vec<Finding>,
+) {
+    let mut report = |severity, message: String| {
+        findings.push(Finding {
+            severity,
+            number,
+            message,
+        })
+    };
+
+    let same_values = first.bpm == later.bpm && same_key(&first.key_camelot, &later.key_camelot);
+
+    match (first.duration_ms, later.duration_ms) {
+        (left, right) if left == right && same_values => report(
+            Severity::Error,
+            format!("exact duplicate of #{first_number}"),
+        ),
+        (left, right) if left == right => report(
+            Severity::Error,
+            format!("duplicate of #{first_number} with the same duration but different BPM/key"),
+        ),
+        (Some(left), Some(right))
+            if (i64::from(left) - i64::from(right)).abs() <= DURATION_MATCH_TOLERANCE_MS =>
+        {
+            report(
+                Severity::Error,
+                format!(
+                    "duration conflicts with #{first_number}: {left}ms vs {right}ms cannot be told apart by the matcher"
+                ),
+            )
+        }
+        (None, _) | (_, None) => report(
+            Severity::Error,
+            format!(
+                "duration conflicts with #{first_number}: add duration_ms to every version of a duplicated track"
+            ),
+        ),
+        _ => report(
+            Severity::Warning,
+            format!("other version of #{first_number} (different duration)"),
+        ),
+    }
+
+    if first.bpm.is_finite() && later.bpm.is_finite() && is_half_or_double(first.bpm, later.bpm) {
+        report(
+            Severity::Warning,
+            format!(
+                "BPM {} is half/double of #{first_number} ({}); one of them may be a half/double-time reading",
+                later.bpm, first.bpm
+            ),
+        );
+    }
+}
+
+/// Lints every entry and every pair of entries sharing a normalized title and
+/// artist, and flags entries that only differ by original-version or
+/// featured-artist credits. Findings are ordered by entry position, errors
+/// first.
+pub(crate) fn check_tracks(tracks: &[(usize, LocalTrackData)]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut seen: HashMap<(String, String), Vec<usize>> = HashMap::new();
+    let mut seen_base: HashMap<(String, String), Vec<usize>> = HashMap::new();
+
+    for (position, (number, track)) in tracks.iter().enumerate() {
+        check_entry(*number, track, &mut findings);
+
+        let identity = (normalize(&track.name), normalize(&track.artist));
+        let earlier = seen.entry(identity.clone()).or_default();
+        for &earlier_position in earlier.iter() {
+            let (first_number, first) = &tracks[earlier_position];
+            check_pair((*first_number, first), (*number, track), &mut findings);
+        }
+        earlier.push(position);
+
+        let base = (base_title(&track.name), base_artist(&track.artist));
+        let earlier = seen_base.entry(base).or_default();
+        for &earlier_position in earlier.iter() {
+            let (first_number, first) = &tracks[earlier_position];
+            if (normalize(&first.name), normalize(&first.artist)) != identity {
+                findings.push(Finding {
+                    severity: Severity::Warning,
+                    number: *number,
+                    message: format!(
+                        "near duplicate of #{first_number}: only mix or featured-artist credits differ"
+                    ),
+                });
+            }
+        }
+        earlier.push(position);
+    }
+
+    findings.sort_by_key(|finding| (finding.number, finding.severity));
+    findings
+}
+
+pub(crate) fn run_check(path: &str) -> Result<()> {
+    let parsed_tracks = open_track_store(path)?.parsed_tracks()?;
+    let entry_count = parsed_tracks.len();
+    let mut findings = Vec::new();
+    let mut tracks = Vec::new();
+
+    for (position, parsed) in parsed_tracks.into_iter().enumerate() {
+        match parsed {
+            Ok(track) => tracks.push((position + 1, track)),
+            Err(err) => findings.push(Finding {
+                severity: Severity::Error,
+                number: position + 1,
+                message: format!("{:#}", err),
+            }),
+        }
+    }
+    findings.extend(check_tracks(&tracks));
+    findings.sort_by_key(|finding| (finding.number, finding.severity));
+
+    let names: HashMap<usize, &LocalTrackData> = tracks
+        .iter()
+        .map(|(number, track)| (*number, track))
+        .collect();
+    for finding in &findings {
+        let track = names
+            .get(&finding.number)
+            .map(|track| format!(" {}", describe(track)))
+            .unwrap_or_default();
+        println!(
+            "[{}] #{}{track}: {}",
+            finding.severity.label(),
+            finding.number,
+            finding.message
+        );
+    }
+
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    println!(
+        "Checked {} entries in {path}: {errors} errors, {} warnings.",
+        entry_count,
+        findings.len() - errors
+    );
+    if errors > 0 {
+        bail!("{path} has {errors} errors");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(name: &str, bpm: f32, key: &str, duration_ms: Option<u32>) -> LocalTrackData {
+        LocalTrackData {
+            name: name.to_string(),
+            artist: "Bicep".to_string(),
+            bpm,
+            key_camelot: key.to_string(),
+            duration_ms,
+            ..LocalTrackData::default()
+        }
+    }
+
+    fn check(tracks: Vec<LocalTrackData>) -> Vec<(Severity, usize, String)> {
+        let numbered: Vec<_> = tracks
+            .into_iter()
+            .enumerate()
+            .map(|(i, t)| (i + 1, t))
+            .collect();
+        check_tracks(&numbered)
+            .into_iter()
+            .map(|finding| (finding.severity, finding.number, finding.message))
+            .collect()
+    }
+
+    #[test]
+    fn check_tracks_reports_invalid_fields_and_tempo_suspicions() {
+        let findings = check(vec![
+            track("", 130.0, "13A", None),
+            track("Glue", f32::INFINITY, "4A", None),
+            track("Apricots", 65.0, "8a", None),
+            track("Atlas", 260.0, "9B", None),
+        ]);
+
+        assert_eq!(
+            findings,
+            vec![
+                (Severity::Error, 1, "empty track name".to_string()),
+                (Severity::Error, 1, "invalid key '13A'".to_string()),
+                (Severity::Error, 2, "non-finite BPM inf".to_string()),
+                (
+                    Severity::Warning,
+                    3,
+                    "BPM 65 looks like half-time; double-time would be 130".to_string()
+                ),
+                (Severity::Error, 4, "BPM 260 is outside 40-250".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_tracks_classifies_duplicates_by_duration() {
+        let findings = check(vec![
+            track("Glue", 130.0, "4A", Some(269_000)),
+            track("glue", 130.0, "4A", Some(269_000)),
+            track("Glue", 65.0, "4A", Some(271_000)),
+            track("Glue (Extended)", 130.0, "4A", None),
+            track("Glue", 130.0, "4A", Some(400_000)),
+            track("Glue", 130.0, "F minor", Some(400_000)),
+        ]);
+        let messages: Vec<_> = findings
+            .iter()
+            .map(|(severity, number, message)| format!("{severity:?} #{number} {message}"))
+            .collect();
+
+        assert!(messages.contains(&"Error #2 exact duplicate of #1".to_string()));
+        assert!(messages
+            .iter()
+            .any(|message| message.starts_with("Error #3 duration conflicts with #1")));
+        assert!(messages
+            .iter()
+            .any(|message| message.starts_with("Warning #3 BPM 65 is half/double of #1")));
+        assert!(
+            messages.contains(&"Warning #5 other version of #1 (different duration)".to_string())
+        );
+        assert!(!messages.iter().any(|message| message.contains("#4")));
+        // `F minor` is `4A` in musical notation.
+        assert!(messages.contains(&"Error #6 exact duplicate of #5".to_string()));
+    }
+
+    #[test]
+    fn check_tracks_flags_titles_that_only_differ_by_credits() {
+        let featured = |name: &str| LocalTrackData {
+            artist: "Bicep feat. Clara La San".to_string(),
+            ..track(name, 130.0, "4A", Some(269_000))
+        };
+        let findings = check(vec![
+            track("Glue", 130.0, "4A", Some(269_000)),
+            track("Glue (Original Mix)", 130.0, "4A", Some(269_000)),
+            featured("Glue"),
+            track(
+                "Glue [feat. Clara La San] - Original Mix",
+                130.0,
+                "4A",
+                None,
+            ),
+            track("Glue (Extended Mix)", 130.0, "4A", Some(400_000)),
+            track("Glue feat. Clara La San (Extended Mix)", 130.0, "4A", None),
+        ]);
+        let near: Vec<(usize, String)> = findings
+            .into_iter()
+            .map(|(_, number, message)| (number, message))
+            .collect();
+
+        let expected: Vec<(usize, String)> =
+            [(2, 1), (3, 1), (3, 2), (4, 1), (4, 2), (4, 3), (6, 5)]
+                .into_iter()
+                .map(|(number, first)| {
+                    (
+                number,
+                format!("near duplicate of #{first}: only mix or featured-artist credits differ"),
+            )
+                })
+                .collect();
+        assert_eq!(near, expected);
+    }
+}
@@ -1,52 +1,154 @@
 use anyhow::{anyhow, bail, Context, Result};
-use csv::StringRecord;
 use rspotify::{
-    model::{Modality, PlayableId, PlayableItem, PlaylistId, TrackId},
+    model::{FullTrack, PlayableId, PlayableItem, PlaylistId, TrackId},
     prelude::*,
     scopes, AuthCodeSpotify, Credentials, OAuth,
 };
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fs;
 use std::io::{self, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+mod analysis;
+mod cli;
+mod conflicts;
+mod csv_import;
+mod db;
+mod engine_dj;
+mod explain;
+mod fill_in;
+mod harmonic;
+mod interactive;
+mod key;
+mod key_detection;
+mod library;
+mod lint;
+mod match_cache;
+mod overrides;
+mod pitch;
+mod rekordbox;
+mod scan;
+mod serato;
+mod sources;
+mod sqlite_store;
+mod tempo;
+mod traktor;
+mod virtualdj;
+
+use cli::{parse_args_from, Command, MetadataArgs, SortArgs};
+use conflicts::{resolve_match, write_conflict_report, Conflict, ConflictPolicy};
+use csv_import::CsvMapping;
+use harmonic::{chain_by_moves, load_harmonic_rules, HarmonicRules, Move, SortMode};
+use interactive::{Decision, Disambiguation, TerminalPrompt};
+use key::{Key, Notation};
+use match_cache::{load_match_cache, save_match_cache, MatchCache};
+use overrides::{load_overrides, MatchOverrides, OverrideResolution};
+use pitch::{
+    effective_key, median_bpm, suggest_pitch, tempo_change_percent, MAX_SUGGESTED_SEMITONES,
+};
+use sources::SourceSpec;
 
 const LOCAL_DB_PATH: &str = "local_db.json";
+const OVERRIDES_PATH: &str = "overrides.json";
+const MATCH_CACHE_PATH: &str = "match_cache.json";
+const SOURCES_PATH: &str = "sources.json";
+const HARMONIC_RULES_PATH: &str = "harmonic_rules.json";
 const DURATION_MATCH_TOLERANCE_MS: i64 = 5_000;
-const CAMELOT_KEYS: [&str; 24] = [
-    "1A", "1B", "2A", "2B", "3A", "3B", "4A", "4B", "5A", "5B", "6A", "6B", "7A", "7B", "8A", "8B",
-    "9A", "9B", "10A", "10B", "11A", "11B", "12A", "12B",
-];
+const AMBIGUITY_SCORE_MARGIN: i32 = 10;
+/// Sources whose BPMs are this close agree; the rest is rounding noise.
+const BPM_CONFLICT_TOLERANCE: f32 = 0.5;
 
 #[derive(Debug, Clone, PartialEq)]
 struct TrackInfo {
     id: String,
     name: String,
     artist: String,
-    key: Option<i32>,
-    mode: Option<Modality>,
+    key: Option<Key>,
     tempo: Option<f32>,
     duration_ms: u32,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 enum MetadataSource {
+    Override,
     LocalDb,
     Exportify,
+    Rekordbox,
+    Traktor,
+    Serato,
+    Csv,
+    EngineDj,
+    #[serde(rename = "virtualdj")]
+    VirtualDj,
+    FileTags,
 }
 
 impl MetadataSource {
     fn label(self) -> &'static str {
         match self {
+            Self::Override => "OVERRIDE",
             Self::LocalDb => "LOCAL_DB",
             Self::Exportify => "EXPORTIFY",
+            Self::Rekordbox => "REKORDBOX",
+            Self::Traktor => "TRAKTOR",
+            Self::Serato => "SERATO",
+            Self::Csv => "CSV",
+            Self::EngineDj => "ENGINE_DJ",
+            Self::VirtualDj => "VIRTUALDJ",
+            Self::FileTags => "FILE_TAGS",
         }
     }
 
-    fn priority(self) -> u8 {
+    /// Priority used when no sources file names the layer.
+    fn default_priority(self) -> i32 {
         match self {
+            Self::Override => 3,
             Self::LocalDb => 2,
-            Self::Exportify => 1,
+            Self::Exportify
+            | Self::Rekordbox
+            | Self::Traktor
+            | Self::Serato
+            | Self::Csv
+            | Self::EngineDj
+            | Self::VirtualDj
+            | Self::FileTags => 1,
+        }
+    }
+}
+
+/// The named layer an entry was loaded from. Without a sources file each
+/// layer is named after its kind, e.g. `LOCAL_DB`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SourceLayer {
+    kind: MetadataSource,
+    name: String,
+    /// Higher wins when candidates tie on score.
+    priority: i32,
+    /// When the source file was last changed, for the `most-recent` policy.
+    modified: Option<SystemTime>,
+}
+
+impl SourceLayer {
+    fn label(&self) -> &str {
+        &self.name
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+}
+
+impl From<MetadataSource> for SourceLayer {
+    fn from(kind: MetadataSource) -> Self {
+        Self {
+            kind,
+            name: kind.label().to_string(),
+            priority: kind.default_priority(),
+            modified: None,
         }
     }
 }
@@ -56,77 +158,137 @@ struct MetadataEntry {
     name: String,
     artist: String,
     bpm: f32,
-    key_camelot: String,
+    /// 0-1, for BPMs estimated from audio.
+    bpm_confidence: Option<f32>,
+    key: Key,
     duration_ms: Option<u32>,
-    source: MetadataSource,
+    genre: Option<String>,
+    source: SourceLayer,
 }
 
-#[derive(Debug, Clone, serde::Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize, PartialEq)]
 struct LocalTrackData {
     name: String,
     artist: String,
     bpm: f32,
     key_camelot: String,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     duration_ms: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    genre: Option<String>,
+    /// 1-10, as used by Mixed In Key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    energy: Option<u8>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// 0-5 stars.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rating: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    isrc: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    spotify_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    notes: Option<String>,
+    /// 0-1, how sure the BPM source was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bpm_confidence: Option<f32>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    verified: bool,
 }
 
-#[derive(Debug, Clone)]
-struct MetadataIndex {
-    by_title: HashMap<String, Vec<MetadataEntry>>,
-    all_entries: Vec<MetadataEntry>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArtistMatch {
+    Exact,
+    Partial,
+    Mismatch,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-struct CliArgs {
-    binary_name: String,
-    playlist_input: String,
-    exportify_csv_paths: Vec<String>,
+/// The individual components that `score_candidate` adds up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ScoreBreakdown {
+    artist: ArtistMatch,
+    duration_diff_ms: Option<i64>,
+    title_exact: bool,
 }
 
-#[derive(Debug, Clone, Copy)]
-struct ExportifyColumns {
-    track_name: usize,
-    artist_names: usize,
-    duration_ms: usize,
-    key: usize,
-    mode: usize,
-    tempo: usize,
+impl ScoreBreakdown {
+    fn artist_points(self) -> Option<i32> {
+        match self.artist {
+            ArtistMatch::Exact => Some(100),
+            ArtistMatch::Partial => Some(80),
+            ArtistMatch::Mismatch => None,
+        }
+    }
+
+    fn duration_points(self) -> i32 {
+        match self.duration_diff_ms {
+            Some(diff) if diff <= DURATION_MATCH_TOLERANCE_MS => 50,
+            Some(_) => -50,
+            None => 0,
+        }
+    }
+
+    fn title_points(self) -> i32 {
+        if self.title_exact {
+            20
+        } else {
+            0
+        }
+    }
+
+    fn score(self) -> Option<i32> {
+        Some(self.artist_points()? + self.duration_points() + self.title_points())
+    }
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
-struct ExportifySkipStats {
-    missing_tempo: usize,
-    missing_key_mode: usize,
-    invalid_tempo: usize,
-    invalid_key_mode: usize,
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ScoredCandidate<'a> {
+    entry: &'a MetadataEntry,
+    score: i32,
 }
 
-impl ExportifySkipStats {
-    fn total(self) -> usize {
-        self.missing_tempo + self.missing_key_mode + self.invalid_tempo + self.invalid_key_mode
-    }
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct NormalizedFields {
+    title: String,
+    artist: String,
 }
 
-#[derive(Debug, Clone, Default, PartialEq)]
-struct ExportifyImport {
-    entries: Vec<MetadataEntry>,
-    skipped: ExportifySkipStats,
+#[derive(Debug, Clone)]
+struct MetadataIndex {
+    by_title: HashMap<String, Vec<MetadataEntry>>,
+    all_entries: Vec<MetadataEntry>,
+    /// `normalize`d title and artist for each entry in `all_entries`.
+    normalized: Vec<NormalizedFields>,
+    titles: FieldIndex,
+    artists: FieldIndex,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum ExportifySkipReason {
-    MissingTempo,
-    MissingKeyMode,
-    InvalidTempo,
-    InvalidKeyMode,
+/// Lookups over one `normalize`d field of every entry in `all_entries`.
+#[derive(Debug, Clone, Default)]
+struct FieldIndex {
+    /// Whole value -> positions in `all_entries`, ascending.
+    exact: HashMap<String, Vec<usize>>,
+    /// Character trigram -> positions in `all_entries`, ascending and deduplicated.
+    trigrams: HashMap<String, Vec<usize>>,
+    /// Length of the longest value, in characters.
+    longest: usize,
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 struct EnrichmentStats {
     matched: usize,
     unmatched: usize,
-    invalid_key: usize,
+    ignored: usize,
+    cached: usize,
+    /// Matched tracks per source layer name.
+    by_source: BTreeMap<String, usize>,
+    /// Tracks left unsorted because the `unresolved` policy refused to pick.
+    unresolved: usize,
+    /// Every source conflict, in playlist order.
+    conflicts: Vec<Conflict>,
+    /// Tracks reported as `[MISSING]`, in playlist order.
+    missing: Vec<TrackInfo>,
 }
 
 fn normalize(input: &str) -> String {
@@ -141,140 +303,29 @@ fn normalize(input: &str) -> String {
         .join(" ")
 }
 
-fn camelot_to_spotify(camelot: &str) -> Option<(i32, Modality)> {
-    let clean = camelot.trim().to_uppercase();
-    let suffix = clean.chars().last()?;
-    let mode = match suffix {
-        'A' => Modality::Minor,
-        'B' => Modality::Major,
-        _ => return None,
-    };
-
-    let num: i32 = clean[..clean.len().saturating_sub(1)].parse().ok()?;
-    let pitch = match (num, mode) {
-        (1, Modality::Major) => 11,
-        (1, Modality::Minor) => 8,
-        (2, Modality::Major) => 6,
-        (2, Modality::Minor) => 3,
-        (3, Modality::Major) => 1,
-        (3, Modality::Minor) => 10,
-        (4, Modality::Major) => 8,
-        (4, Modality::Minor) => 5,
-        (5, Modality::Major) => 3,
-        (5, Modality::Minor) => 0,
-        (6, Modality::Major) => 10,
-        (6, Modality::Minor) => 7,
-        (7, Modality::Major) => 5,
-        (7, Modality::Minor) => 2,
-        (8, Modality::Major) => 0,
-        (8, Modality::Minor) => 9,
-        (9, Modality::Major) => 7,
-        (9, Modality::Minor) => 4,
-        (10, Modality::Major) => 2,
-        (10, Modality::Minor) => 11,
-        (11, Modality::Major) => 9,
-        (11, Modality::Minor) => 6,
-        (12, Modality::Major) => 4,
-        (12, Modality::Minor) => 1,
-        _ => return None,
-    };
-
-    Some((pitch, mode))
+fn format_duration(duration_ms: u32) -> String {
+    let total_seconds = duration_ms / 1_000;
+    format!("{}:{:02}", total_seconds / 60, total_seconds % 60)
 }
 
-fn spotify_to_camelot(pitch: i32, mode: Modality) -> Option<String> {
-    CAMELOT_KEYS
-        .iter()
-        .find(|camelot| camelot_to_spotify(camelot) == Some((pitch, mode)))
-        .map(|camelot| (*camelot).to_string())
-}
-
-fn get_sort_weight(pitch: i32, mode: Modality) -> i32 {
-    let (num, is_major) = match (pitch, mode) {
-        (11, Modality::Major) => (1, true),
-        (8, Modality::Minor) => (1, false),
-        (6, Modality::Major) => (2, true),
-        (3, Modality::Minor) => (2, false),
-        (1, Modality::Major) => (3, true),
-        (10, Modality::Minor) => (3, false),
-        (8, Modality::Major) => (4, true),
-        (5, Modality::Minor) => (4, false),
-        (3, Modality::Major) => (5, true),
-        (0, Modality::Minor) => (5, false),
-        (10, Modality::Major) => (6, true),
-        (7, Modality::Minor) => (6, false),
-        (5, Modality::Major) => (7, true),
-        (2, Modality::Minor) => (7, false),
-        (0, Modality::Major) => (8, true),
-        (9, Modality::Minor) => (8, false),
-        (7, Modality::Major) => (9, true),
-        (4, Modality::Minor) => (9, false),
-        (2, Modality::Major) => (10, true),
-        (11, Modality::Minor) => (10, false),
-        (9, Modality::Major) => (11, true),
-        (6, Modality::Minor) => (11, false),
-        (4, Modality::Major) => (12, true),
-        (1, Modality::Minor) => (12, false),
-        _ => (99, false),
-    };
-
-    (num * 10) + if is_major { 1 } else { 0 }
-}
-
-fn usage(binary_name: &str) -> String {
-    format!(
-        "Usage: {binary_name} [--exportify-csv <path>]... <playlist_id_or_url>\n\nExamples:\n  {binary_name} 2nOsiUa2nlXBGuDMjDIbDb\n  {binary_name} https://open.spotify.com/playlist/2nOsiUa2nlXBGuDMjDIbDb\n  {binary_name} --exportify-csv exports/playlist.csv spotify:playlist:2nOsiUa2nlXBGuDMjDIbDb"
-    )
+/// Converts a key in any notation [`Key::parse`] reads to Camelot.
+fn key_to_camelot(raw: &str) -> Option<String> {
+    Key::parse(raw).map(Key::camelot)
 }
 
-fn parse_args_from<I>(args: I) -> Result<CliArgs>
-where
-    I: IntoIterator<Item = String>,
-{
-    let mut args = args.into_iter();
-    let binary_name = args
-        .next()
-        .unwrap_or_else(|| "spotify-key-bpm-sorter".to_string());
-    let mut playlist_input = None;
-    let mut exportify_csv_paths = Vec::new();
-
-    while let Some(arg) = args.next() {
-        match arg.as_str() {
-            "-h" | "--help" => bail!("{}", usage(&binary_name)),
-            "--exportify-csv" => {
-                let path = args.next().ok_or_else(|| {
-                    anyhow!(
-                        "missing value for --exportify-csv\n\n{}",
-                        usage(&binary_name)
-                    )
-                })?;
-                exportify_csv_paths.push(path);
-            }
-            _ => {
-                if let Some(path) = arg.strip_prefix("--exportify-csv=") {
-                    if path.is_empty() {
-                        bail!(
-                            "missing value for --exportify-csv\n\n{}",
-                            usage(&binary_name)
-                        );
-                    }
-                    exportify_csv_paths.push(path.to_string());
-                } else if arg.starts_with('-') {
-                    bail!("unknown option: {arg}\n\n{}", usage(&binary_name));
-                } else if playlist_input.replace(arg).is_some() {
-                    bail!("{}", usage(&binary_name));
-                }
-            }
-        }
-    }
-
-    let playlist_input = playlist_input.ok_or_else(|| anyhow!(usage(&binary_name)))?;
+fn extract_track_id(raw_input: &str) -> Option<String> {
+    let trimmed = raw_input.trim();
+    let id = if let Some(id) = trimmed.strip_prefix("spotify:track:") {
+        id
+    } else if let Some((_, rest)) = trimmed.split_once("open.spotify.com/track/") {
+        rest.split(['?', '/']).next().unwrap_or_default()
+    } else {
+        trimmed
+    };
 
-    Ok(CliArgs {
-        binary_name,
-        playlist_input,
-        exportify_csv_paths,
-    })
+    TrackId::from_id(id)
+        .ok()
+        .map(|track_id| track_id.id().to_string())
 }
 
 fn extract_playlist_id(raw_input: &str) -> Result<String> {
@@ -302,306 +353,543 @@ fn validate_playlist_id(id: &str) -> Result<String> {
         .map_err(|_| anyhow!("invalid Spotify playlist input: {id}"))
 }
 
+/// Reads the database rows as metadata entries. Hand-edited rows may use any
+/// notation `Key::parse` reads; rows with an unreadable key are reported and
+/// left out.
 fn load_local_db_entries(path: &str) -> Result<Vec<MetadataEntry>> {
-    let file_content =
-        fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
-    let all_entries: Vec<LocalTrackData> =
-        serde_json::from_str(&file_content).with_context(|| format!("failed to parse {path}"))?;
+    let all_entries = db::load_tracks(path)?;
 
     Ok(all_entries
         .into_iter()
-        .map(|entry| MetadataEntry {
-            name: entry.name,
-            artist: entry.artist,
-            bpm: entry.bpm,
-            key_camelot: entry.key_camelot,
-            duration_ms: entry.duration_ms,
-            source: MetadataSource::LocalDb,
+        .filter_map(|entry| {
+            let Some(key) = Key::parse(&entry.key_camelot) else {
+                eprintln!(
+                    "[KEY ERROR] Invalid key '{}' for {} - {} in {path}",
+                    entry.key_camelot, entry.artist, entry.name
+                );
+                return None;
+            };
+            Some(MetadataEntry {
+                name: entry.name,
+                artist: entry.artist,
+                bpm: entry.bpm,
+                bpm_confidence: entry.bpm_confidence,
+                key,
+                duration_ms: entry.duration_ms,
+                genre: entry.genre,
+                source: MetadataSource::LocalDb.into(),
+            })
         })
         .collect())
 }
 
+/// Byte offsets of every character boundary in `text`, including its end.
+fn char_bounds(text: &str) -> Vec<usize> {
+    text.char_indices()
+        .map(|(offset, _)| offset)
+        .chain([text.len()])
+        .collect()
+}
+
+fn trigrams(normalized: &str) -> impl Iterator<Item = &str> + '_ {
+    let bounds = char_bounds(normalized);
+    (3..bounds.len()).map(move |end| &normalized[bounds[end - 3]..bounds[end]])
+}
+
+impl FieldIndex {
+    fn add(&mut self, normalized: &str, position: usize) {
+        self.exact
+            .entry(normalized.to_string())
+            .or_default()
+            .push(position);
+        self.longest = self.longest.max(normalized.chars().count());
+        for trigram in trigrams(normalized) {
+            let positions = self.trigrams.entry(trigram.to_string()).or_default();
+            if positions.last() != Some(&position) {
+                positions.push(position);
+            }
+        }
+    }
+
+    /// Positions that may contain `query` or be contained in it, ascending.
+    /// `None` when `query` is shorter than a trigram and cannot be looked up.
+    ///
+    /// Values containing `query` share all of its trigrams, so only the
+    /// postings of its rarest trigram are returned for them; a word as common
+    /// as `the` or `remix` never decides the candidate set on its own. Values
+    /// contained in `query` are found by looking up each of its substrings.
+    fn containment_candidates(&self, query: &str) -> Option<Vec<usize>> {
+        let rarest = trigrams(query)
+            .map(|trigram| self.trigrams.get(trigram).map_or(&[][..], Vec::as_slice))
+            .min_by_key(|positions| positions.len())?;
+        let mut positions = rarest.to_vec();
+
+        let bounds = char_bounds(query);
+        for (index, &start) in bounds.iter().enumerate() {
+            for &end in bounds[index + 1..].iter().take(self.longest) {
+                if let Some(found) = self.exact.get(&query[start..end]) {
+                    positions.extend(found);
+                }
+            }
+        }
+        if let Some(empty) = self.exact.get("") {
+            positions.extend(empty);
+        }
+        positions.sort_unstable();
+        positions.dedup();
+        Some(positions)
+    }
+}
+
 fn build_metadata_index(all_entries: Vec<MetadataEntry>) -> MetadataIndex {
     let mut by_title: HashMap<String, Vec<MetadataEntry>> = HashMap::new();
-    for entry in &all_entries {
+    let mut normalized = Vec::with_capacity(all_entries.len());
+    let mut titles = FieldIndex::default();
+    let mut artists = FieldIndex::default();
+
+    for (position, entry) in all_entries.iter().enumerate() {
+        let fields = NormalizedFields {
+            title: normalize(&entry.name),
+            artist: normalize(&entry.artist),
+        };
+        titles.add(&fields.title, position);
+        artists.add(&fields.artist, position);
         by_title
-            .entry(normalize(&entry.name))
+            .entry(fields.title.clone())
             .or_default()
             .push(entry.clone());
+        normalized.push(fields);
     }
 
     MetadataIndex {
         by_title,
         all_entries,
+        normalized,
+        titles,
+        artists,
     }
 }
 
-fn normalized_header(header: &str) -> String {
-    header
-        .trim()
-        .trim_start_matches('\u{feff}')
-        .to_ascii_lowercase()
+fn score_breakdown(
+    spotify_track: &TrackInfo,
+    spot_artist_norm: &str,
+    spot_title_norm: &str,
+    candidate: &MetadataEntry,
+) -> ScoreBreakdown {
+    let db_artist_norm = normalize(&candidate.artist);
+    let artist = if db_artist_norm == spot_artist_norm {
+        ArtistMatch::Exact
+    } else if db_artist_norm.contains(spot_artist_norm)
+        || spot_artist_norm.contains(&db_artist_norm)
+    {
+        ArtistMatch::Partial
+    } else {
+        ArtistMatch::Mismatch
+    };
+
+    ScoreBreakdown {
+        artist,
+        duration_diff_ms: candidate
+            .duration_ms
+            .map(|db_dur| (db_dur as i64 - spotify_track.duration_ms as i64).abs()),
+        title_exact: normalize(&candidate.name) == spot_title_norm,
+    }
 }
 
-fn find_header_index(headers: &StringRecord, expected: &[&str]) -> Option<usize> {
-    headers.iter().position(|header| {
-        let normalized = normalized_header(header);
-        expected.iter().any(|candidate| normalized == *candidate)
-    })
+fn score_candidate(
+    spotify_track: &TrackInfo,
+    spot_artist_norm: &str,
+    spot_title_norm: &str,
+    candidate: &MetadataEntry,
+) -> Option<i32> {
+    score_breakdown(spotify_track, spot_artist_norm, spot_title_norm, candidate).score()
 }
 
-impl ExportifyColumns {
-    fn from_headers(headers: &StringRecord) -> Result<Self> {
-        let track_name = find_header_index(headers, &["track name"])
-            .ok_or_else(|| anyhow!("Exportify CSV is missing the 'Track Name' column"))?;
-        let artist_names = find_header_index(headers, &["artist name(s)"])
-            .ok_or_else(|| anyhow!("Exportify CSV is missing the 'Artist Name(s)' column"))?;
-        let duration_ms = find_header_index(headers, &["duration (ms)"])
-            .ok_or_else(|| anyhow!("Exportify CSV is missing the 'Duration (ms)' column"))?;
-        let key = find_header_index(headers, &["key"])
-            .ok_or_else(|| anyhow!("Exportify CSV is missing the 'Key' column"))?;
-        let mode = find_header_index(headers, &["mode", "mode (major or minor)"])
-            .ok_or_else(|| anyhow!("Exportify CSV is missing the 'Mode' column"))?;
-        let tempo = find_header_index(headers, &["tempo"])
-            .ok_or_else(|| anyhow!("Exportify CSV is missing the 'Tempo' column"))?;
-
-        Ok(Self {
-            track_name,
-            artist_names,
-            duration_ms,
-            key,
-            mode,
-            tempo,
+/// Scores every candidate with a compatible artist and returns them best-first.
+/// Equal scores are ordered by source priority, then by input order.
+fn rank_candidates<'a>(
+    spotify_track: &TrackInfo,
+    candidates: impl IntoIterator<Item = &'a MetadataEntry>,
+) -> Vec<ScoredCandidate<'a>> {
+    let spot_artist_norm = normalize(&spotify_track.artist);
+    let spot_title_norm = normalize(&spotify_track.name);
+
+    let mut ranked: Vec<ScoredCandidate<'a>> = candidates
+        .into_iter()
+        .filter_map(|entry| {
+            score_candidate(spotify_track, &spot_artist_norm, &spot_title_norm, entry)
+                .map(|score| ScoredCandidate { entry, score })
         })
-    }
-}
+        .collect();
 
-fn parse_exportify_mode(raw_mode: &str) -> Option<Modality> {
-    match raw_mode.trim().to_ascii_lowercase().as_str() {
-        "1" | "major" => Some(Modality::Major),
-        "0" | "minor" => Some(Modality::Minor),
-        _ => None,
-    }
+    ranked.sort_by(|a, b| {
+        (b.score, b.entry.source.priority()).cmp(&(a.score, a.entry.source.priority()))
+    });
+    ranked
 }
 
-fn first_exportify_artist(raw_artists: &str) -> String {
-    raw_artists
-        .split(';')
-        .next()
-        .unwrap_or(raw_artists)
-        .trim()
-        .to_string()
-}
-
-fn parse_exportify_record(
-    record: &StringRecord,
-    columns: ExportifyColumns,
-) -> std::result::Result<MetadataEntry, ExportifySkipReason> {
-    let tempo_raw = record.get(columns.tempo).unwrap_or_default().trim();
-    if tempo_raw.is_empty() {
-        return Err(ExportifySkipReason::MissingTempo);
-    }
-
-    let bpm = tempo_raw
-        .parse::<f32>()
-        .map_err(|_| ExportifySkipReason::InvalidTempo)?;
-
-    let key_raw = record.get(columns.key).unwrap_or_default().trim();
-    let mode_raw = record.get(columns.mode).unwrap_or_default().trim();
-    if key_raw.is_empty() || mode_raw.is_empty() {
-        return Err(ExportifySkipReason::MissingKeyMode);
-    }
-
-    let key = key_raw
-        .parse::<i32>()
-        .map_err(|_| ExportifySkipReason::InvalidKeyMode)?;
-    let mode = parse_exportify_mode(mode_raw).ok_or(ExportifySkipReason::InvalidKeyMode)?;
-    let key_camelot = spotify_to_camelot(key, mode).ok_or(ExportifySkipReason::InvalidKeyMode)?;
-
-    let duration_ms = record
-        .get(columns.duration_ms)
-        .map(str::trim)
-        .filter(|value| !value.is_empty())
-        .and_then(|value| value.parse::<u32>().ok());
-
-    Ok(MetadataEntry {
-        name: record
-            .get(columns.track_name)
-            .unwrap_or_default()
-            .trim()
-            .to_string(),
-        artist: first_exportify_artist(record.get(columns.artist_names).unwrap_or_default()),
-        bpm,
-        key_camelot,
-        duration_ms,
-        source: MetadataSource::Exportify,
-    })
-}
+/// Returns the leading candidates that score within `AMBIGUITY_SCORE_MARGIN`
+/// of the winner, or an empty slice when they all agree on BPM (within
+/// `BPM_CONFLICT_TOLERANCE`) and key.
+fn ambiguous_candidates<'r, 'a>(ranked: &'r [ScoredCandidate<'a>]) -> &'r [ScoredCandidate<'a>] {
+    let Some(best) = ranked.first() else {
+        return &[];
+    };
 
-fn load_exportify_csv_reader<R: io::Read>(reader: R) -> Result<ExportifyImport> {
-    let mut csv_reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
-    let headers = csv_reader.headers()?.clone();
-    let columns = ExportifyColumns::from_headers(&headers)?;
-    let mut import = ExportifyImport::default();
+    let close = ranked
+        .iter()
+        .take_while(|candidate| best.score - candidate.score <= AMBIGUITY_SCORE_MARGIN)
+        .count();
+    let close_candidates = &ranked[..close];
 
-    for record in csv_reader.records() {
-        let record = record?;
-        match parse_exportify_record(&record, columns) {
-            Ok(entry) => import.entries.push(entry),
-            Err(ExportifySkipReason::MissingTempo) => import.skipped.missing_tempo += 1,
-            Err(ExportifySkipReason::MissingKeyMode) => import.skipped.missing_key_mode += 1,
-            Err(ExportifySkipReason::InvalidTempo) => import.skipped.invalid_tempo += 1,
-            Err(ExportifySkipReason::InvalidKeyMode) => import.skipped.invalid_key_mode += 1,
-        }
-    }
+    let disagree = close_candidates.iter().any(|candidate| {
+        (candidate.entry.bpm - best.entry.bpm).abs() > BPM_CONFLICT_TOLERANCE
+            || candidate.entry.key != best.entry.key
+    });
 
-    Ok(import)
+    if disagree {
+        close_candidates
+    } else {
+        &[]
+    }
 }
 
-fn load_exportify_csv(path: &str) -> Result<ExportifyImport> {
-    let file = fs::File::open(path).with_context(|| format!("failed to read {path}"))?;
-    load_exportify_csv_reader(file)
-        .with_context(|| format!("failed to parse Exportify CSV at {path}"))
+fn contains_either(a: &str, b: &str) -> bool {
+    a.contains(b) || b.contains(a)
 }
 
-fn find_best_match(
-    spotify_track: &TrackInfo,
-    candidates: &[MetadataEntry],
-) -> Option<MetadataEntry> {
-    let mut best_candidate: Option<&MetadataEntry> = None;
-    let mut best_rank = (i32::MIN, 0_u8);
-    let spot_artist_norm = normalize(&spotify_track.artist);
-    let spot_title_norm = normalize(&spotify_track.name);
-
-    for candidate in candidates {
-        let mut score = 0;
-        let db_artist_norm = normalize(&candidate.artist);
-
-        if db_artist_norm == spot_artist_norm {
-            score += 100;
-        } else if db_artist_norm.contains(&spot_artist_norm)
-            || spot_artist_norm.contains(&db_artist_norm)
-        {
-            score += 80;
-        } else {
-            continue;
-        }
+/// Entries whose title and artist contain (or are contained in) the track's.
+///
+/// Only the candidates [`FieldIndex::containment_candidates`] finds for the
+/// title or the artist are checked, whichever side has fewer, so the cost
+/// scales with those instead of the database size. When both are too short to
+/// look up, every entry is checked.
+fn fuzzy_candidates<'a>(
+    track: &TrackInfo,
+    metadata_index: &'a MetadataIndex,
+) -> impl Iterator<Item = &'a MetadataEntry> {
+    let spot_title = normalize(&track.name);
+    let spot_artist = normalize(&track.artist);
+
+    let by_title = metadata_index.titles.containment_candidates(&spot_title);
+    let by_artist = metadata_index.artists.containment_candidates(&spot_artist);
+    let positions = match (by_title, by_artist) {
+        (Some(title), Some(artist)) if artist.len() < title.len() => artist,
+        (Some(positions), _) | (None, Some(positions)) => positions,
+        (None, None) => (0..metadata_index.all_entries.len()).collect(),
+    };
 
-        if let Some(db_dur) = candidate.duration_ms {
-            let diff = (db_dur as i64 - spotify_track.duration_ms as i64).abs();
-            if diff <= DURATION_MATCH_TOLERANCE_MS {
-                score += 50;
-            } else {
-                score -= 50;
-            }
-        }
+    positions.into_iter().filter_map(move |position| {
+        let fields = &metadata_index.normalized[position];
+        (contains_either(&fields.title, &spot_title)
+            && contains_either(&fields.artist, &spot_artist))
+        .then(|| &metadata_index.all_entries[position])
+    })
+}
 
-        if normalize(&candidate.name) == spot_title_norm {
-            score += 20;
-        }
+fn find_match_candidates<'a>(
+    track: &TrackInfo,
+    metadata_index: &'a MetadataIndex,
+) -> Vec<ScoredCandidate<'a>> {
+    let title_key = normalize(&track.name);
 
-        let candidate_rank = (score, candidate.source.priority());
-        if candidate_rank > best_rank {
-            best_rank = candidate_rank;
-            best_candidate = Some(candidate);
+    if let Some(candidates) = metadata_index.by_title.get(&title_key) {
+        let ranked = rank_candidates(track, candidates);
+        if !ranked.is_empty() {
+            return ranked;
         }
     }
 
-    best_candidate.cloned()
+    rank_candidates(track, fuzzy_candidates(track, metadata_index))
 }
 
+/// Picks the match for `track`, settling disagreements between source layers
+/// with `conflict_policy`.
 fn find_match_for_track(
     track: &TrackInfo,
     metadata_index: &MetadataIndex,
-) -> Option<MetadataEntry> {
-    let title_key = normalize(&track.name);
+    conflict_policy: ConflictPolicy,
+) -> (Option<MetadataEntry>, Option<Conflict>) {
+    resolve_match(
+        track,
+        &find_match_candidates(track, metadata_index),
+        conflict_policy,
+    )
+}
 
-    if let Some(candidates) = metadata_index.by_title.get(&title_key) {
-        if let Some(best_match) = find_best_match(track, candidates) {
-            return Some(best_match);
-        }
+/// The cached match for `track`, unless its sources now disagree. Then the
+/// record is dropped so the conflict is reported and settled by policy.
+///
+/// Only the cached entry's title bucket is checked for a conflict, so a hit
+/// never pays for the fuzzy fallback.
+fn cached_match(
+    match_cache: Option<&mut MatchCache>,
+    track: &TrackInfo,
+    metadata_index: &MetadataIndex,
+    conflict_policy: ConflictPolicy,
+) -> Option<MetadataEntry> {
+    let match_cache = match_cache?;
+    let entry = match_cache.lookup(track, metadata_index)?.clone();
+    let bucket = metadata_index
+        .by_title
+        .get(&normalize(&entry.name))
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    if resolve_match(track, &rank_candidates(track, bucket), conflict_policy)
+        .1
+        .is_some()
+    {
+        match_cache.forget(track);
+        return None;
     }
-
-    let fuzzy_candidates: Vec<MetadataEntry> = metadata_index
-        .all_entries
-        .iter()
-        .filter(|db_item| {
-            let db_title = normalize(&db_item.name);
-            let spot_title = normalize(&track.name);
-            let title_match = db_title.contains(&spot_title) || spot_title.contains(&db_title);
-
-            let db_artist = normalize(&db_item.artist);
-            let spot_artist = normalize(&track.artist);
-            let artist_match = db_artist.contains(&spot_artist) || spot_artist.contains(&db_artist);
-
-            title_match && artist_match
-        })
-        .cloned()
-        .collect();
-
-    find_best_match(track, &fuzzy_candidates)
+    Some(entry)
 }
 
-fn enrich_tracks(tracks: &mut [TrackInfo], metadata_index: &MetadataIndex) -> EnrichmentStats {
+fn enrich_tracks(
+    tracks: &mut [TrackInfo],
+    metadata_index: &MetadataIndex,
+    overrides: &mut MatchOverrides,
+    mut match_cache: Option<&mut MatchCache>,
+    mut disambiguation: Option<Disambiguation<'_>>,
+    conflict_policy: ConflictPolicy,
+    notation: Notation,
+) -> Result<EnrichmentStats> {
     let mut stats = EnrichmentStats::default();
 
     for track in tracks {
-        match find_match_for_track(track, metadata_index) {
-            Some(match_data) => match camelot_to_spotify(&match_data.key_camelot) {
-                Some((pitch, mode)) => {
-                    track.tempo = Some(match_data.bpm);
-                    track.key = Some(pitch);
-                    track.mode = Some(mode);
-                    stats.matched += 1;
-                    println!(
-                        "[MATCH:{}] {} - {} (metadata: {})",
-                        match_data.source.label(),
-                        track.artist,
-                        track.name,
-                        match_data.name
-                    );
+        let mut from_cache = false;
+        let mut unresolved = false;
+        let resolution = match overrides.resolve(track, metadata_index) {
+            Some(resolution) => Some(resolution),
+            None => match cached_match(
+                match_cache.as_deref_mut(),
+                track,
+                metadata_index,
+                conflict_policy,
+            ) {
+                Some(entry) => {
+                    from_cache = true;
+                    Some(OverrideResolution::Matched(entry))
                 }
                 None => {
-                    stats.invalid_key += 1;
-                    eprintln!(
-                        "[KEY ERROR] Invalid Camelot '{}' for {} - {}",
-                        match_data.key_camelot, track.artist, track.name
-                    );
+                    let decision = match disambiguation.as_mut() {
+                        Some(disambiguation) => {
+                            disambiguation.decide(track, metadata_index, overrides)?
+                        }
+                        None => Decision::Automatic,
+                    };
+
+                    match decision {
+                        Decision::Resolved(resolution) => Some(resolution),
+                        Decision::Automatic | Decision::Skipped => {
+                            let (best, conflict) =
+                                find_match_for_track(track, metadata_index, conflict_policy);
+                            // Conflicting matches are not cached, so every run
+                            // reports them and follows the current policy.
+                            if let (Decision::Automatic, None, Some(cache), Some(entry)) =
+                                (&decision, &conflict, match_cache.as_deref_mut(), &best)
+                            {
+                                cache.record(track, entry);
+                            }
+                            if let Some(conflict) = conflict {
+                                println!("[CONFLICT] {}", conflict.describe(notation));
+                                if conflict.chosen.is_none() {
+                                    unresolved = true;
+                                    stats.unresolved += 1;
+                                }
+                                stats.conflicts.push(conflict);
+                            }
+                            best.map(OverrideResolution::Matched)
+                        }
+                    }
                 }
             },
+        };
+
+        let matched = match resolution {
+            Some(OverrideResolution::Ignore) => {
+                stats.ignored += 1;
+                println!("[IGNORED] {} - {}", track.artist, track.name);
+                continue;
+            }
+            Some(OverrideResolution::Matched(entry)) => Some(entry),
+            Some(OverrideResolution::MissingEntry { name, artist }) => {
+                eprintln!(
+                    "[OVERRIDE ERROR] No metadata entry '{artist} - {name}' for {} - {}",
+                    track.artist, track.name
+                );
+                None
+            }
+            None => None,
+        };
+
+        match matched {
+            Some(match_data) => {
+                track.tempo = Some(match_data.bpm);
+                track.key = Some(match_data.key);
+                stats.matched += 1;
+                *stats
+                    .by_source
+                    .entry(match_data.source.name.clone())
+                    .or_default() += 1;
+                if from_cache {
+                    stats.cached += 1;
+                }
+                println!(
+                    "[MATCH:{}] {} - {} (metadata: {}{})",
+                    match_data.source.label(),
+                    track.artist,
+                    track.name,
+                    match_data.name,
+                    if from_cache { ", cached" } else { "" }
+                );
+            }
+            None if unresolved => {}
             None => {
                 stats.unmatched += 1;
+                stats.missing.push(track.clone());
                 eprintln!("[MISSING] {} - {}", track.artist, track.name);
             }
         }
     }
 
-    stats
+    Ok(stats)
 }
 
-fn sort_tracks(tracks: Vec<TrackInfo>) -> Vec<TrackInfo> {
+/// The key `track` is heard in: its written key, or with `target_bpm` the
+/// key it shifts to when played at that tempo without key lock.
+fn played_key(track: &TrackInfo, target_bpm: Option<f32>) -> Option<Key> {
+    let key = track.key?;
+    Some(match (target_bpm, track.tempo) {
+        (Some(target), Some(bpm)) if bpm > 0.0 => effective_key(key, bpm, target),
+        _ => key,
+    })
+}
+
+fn describe_played_key(track: &TrackInfo, played: Key, notation: Notation) -> String {
+    match track.key {
+        Some(written) if written != played => {
+            format!(
+                "{} as {}",
+                written.format(notation),
+                played.format(notation)
+            )
+        }
+        _ => played.format(notation),
+    }
+}
+
+/// The transition report printed after sorting: a `[CLASH]` line for each
+/// pair of adjacent keyed tracks that no allowed move joins, then a
+/// summary. Empty when there are fewer than two keyed tracks.
+///
+/// With `target_bpm` the keys compared are the ones heard at that tempo;
+/// with `suggest` each clash is followed by a `[PITCH]` line naming the
+/// smallest nudge of the incoming track that resolves it. Keys are written
+/// in `notation`.
+fn describe_transitions(
+    tracks: &[TrackInfo],
+    rules: &HarmonicRules,
+    target_bpm: Option<f32>,
+    suggest: bool,
+    notation: Notation,
+) -> Vec<String> {
+    let keyed: Vec<(Key, &TrackInfo)> = tracks
+        .iter()
+        .filter_map(|track| Some((played_key(track, target_bpm)?, track)))
+        .collect();
+    if keyed.len() < 2 {
+        return Vec::new();
+    }
+
+    let mut lines = Vec::new();
+    let mut counts: BTreeMap<Move, usize> = BTreeMap::new();
+    let mut largest_jump = 0;
+    for pair in keyed.windows(2) {
+        let ((from, from_track), (to, to_track)) = (pair[0], pair[1]);
+        largest_jump = largest_jump.max(from.wheel_distance(to));
+        if let Some((harmonic_move, _)) = rules.score(from, to) {
+            *counts.entry(harmonic_move).or_default() += 1;
+            continue;
+        }
+
+        lines.push(format!(
+            "[CLASH] {} - {} ({}) -> {} - {} ({})",
+            from_track.artist,
+            from_track.name,
+            describe_played_key(from_track, from, notation),
+            to_track.artist,
+            to_track.name,
+            describe_played_key(to_track, to, notation)
+        ));
+        if !suggest {
+            continue;
+        }
+        match suggest_pitch(from, to, rules) {
+            Some(suggestion) => lines.push(format!(
+                "[PITCH] {} - {}: {:+} semitone ({:+.1}% tempo) plays in {}, {} from {}",
+                to_track.artist,
+                to_track.name,
+                suggestion.semitones,
+                tempo_change_percent(suggestion.semitones),
+                suggestion.key.format(notation),
+                suggestion.harmonic_move.label(),
+                from.format(notation)
+            )),
+            None => lines.push(format!(
+                "[PITCH] {} - {}: no nudge of up to {MAX_SUGGESTED_SEMITONES} semitones makes an allowed move",
+                to_track.artist, to_track.name
+            )),
+        }
+    }
+
+    let allowed: usize = counts.values().sum();
+    let by_move: Vec<String> = counts
+        .iter()
+        .map(|(harmonic_move, count)| format!("{}={count}", harmonic_move.label()))
+        .collect();
+    lines.push(format!(
+        "Harmonic flow: {allowed} of {} transitions are allowed moves{}; largest jump {largest_jump} wheel steps",
+        keyed.len() - 1,
+        if by_move.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", by_move.join(", "))
+        }
+    ));
+    lines
+}
+
+/// Sorts by the key each track is heard in, which is its written key
+/// unless `target_bpm` is set (see [`played_key`]).
+fn sort_tracks(
+    tracks: Vec<TrackInfo>,
+    mode: SortMode,
+    rules: &HarmonicRules,
+    target_bpm: Option<f32>,
+) -> Vec<TrackInfo> {
     let mut with_features = Vec::new();
     let mut without_features = Vec::new();
 
     for track in tracks {
-        if let (Some(key), Some(mode), Some(tempo)) = (track.key, track.mode, track.tempo) {
-            with_features.push((key, mode, tempo, track));
+        if let (Some(key), Some(tempo)) = (played_key(&track, target_bpm), track.tempo) {
+            with_features.push((key, tempo, track));
         } else {
             without_features.push(track);
         }
     }
 
     with_features.sort_by(|a, b| {
-        let a_weight = get_sort_weight(a.0, a.1);
-        let b_weight = get_sort_weight(b.0, b.1);
-        a_weight
-            .cmp(&b_weight)
-            .then_with(|| a.2.partial_cmp(&b.2).unwrap_or(Ordering::Equal))
+        a.0.cmp(&b.0)
+            .then_with(|| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
     });
+    if mode == SortMode::Harmonic {
+        with_features = chain_by_moves(with_features, rules);
+    }
 
     let mut sorted_tracks: Vec<TrackInfo> = with_features
         .into_iter()
-        .map(|(_, _, _, track)| track)
+        .map(|(_, _, track)| track)
         .collect();
     sorted_tracks.extend(without_features);
     sorted_tracks
@@ -631,6 +919,24 @@ async fn authenticate_spotify() -> Result<AuthCodeSpotify> {
     Ok(spotify)
 }
 
+fn track_info_from_full(track: &FullTrack) -> Option<TrackInfo> {
+    let id = track.id.as_ref()?;
+    let artist_name = track
+        .artists
+        .first()
+        .map(|artist| artist.name.clone())
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    Some(TrackInfo {
+        id: id.id().to_string(),
+        name: track.name.clone(),
+        artist: artist_name,
+        key: None,
+        tempo: None,
+        duration_ms: track.duration.num_milliseconds().max(0) as u32,
+    })
+}
+
 async fn fetch_playlist_tracks(
     spotify: &AuthCodeSpotify,
     playlist_id: &PlaylistId<'_>,
@@ -650,24 +956,7 @@ async fn fetch_playlist_tracks(
 
         for item in page.items {
             if let Some(PlayableItem::Track(track)) = item.track {
-                if let Some(id) = track.id {
-                    let artist_name = track
-                        .artists
-                        .first()
-                        .map(|artist| artist.name.clone())
-                        .unwrap_or_else(|| "Unknown".to_string());
-                    let duration_ms = track.duration.num_milliseconds().max(0) as u32;
-
-                    all_tracks.push(TrackInfo {
-                        id: id.id().to_string(),
-                        name: track.name.clone(),
-                        artist: artist_name,
-                        key: None,
-                        mode: None,
-                        tempo: None,
-                        duration_ms,
-                    });
-                }
+                all_tracks.extend(track_info_from_full(&track));
             }
         }
 
@@ -718,36 +1007,152 @@ async fn update_playlist(
     Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    dotenvy::dotenv().ok();
+fn local_db_path(metadata: &MetadataArgs) -> &str {
+    metadata.local_db_path.as_deref().unwrap_or(LOCAL_DB_PATH)
+}
 
-    let cli_args = parse_args_from(env::args())?;
+fn load_source_entries(source: &SourceSpec) -> Result<Vec<MetadataEntry>> {
+    let SourceSpec {
+        layer,
+        path,
+        csv_mapping,
+    } = source;
+    let layer = &SourceLayer {
+        modified: fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok(),
+        ..layer.clone()
+    };
+    let entries = match layer.kind {
+        MetadataSource::LocalDb => {
+            println!("Loading {path}...");
+            load_local_db_entries(path)?
+        }
+        MetadataSource::Exportify => {
+            println!("Importing Exportify CSV: {path}");
+            let exportify = CsvMapping::preset("exportify").context("missing exportify preset")?;
+            let import = csv_import::load_csv(path, Some(&exportify), layer.kind)?.library;
+            if import.skipped() > 0 {
+                println!(
+                    "Skipped {} Exportify rows from {path} ({}).",
+                    import.skipped(),
+                    import.skip_summary()
+                );
+            }
+            import.entries
+        }
+        MetadataSource::EngineDj => {
+            println!("Importing Engine DJ library: {path}");
+            let import = engine_dj::load_engine_db(path)?;
+            if import.skipped() > 0 {
+                println!(
+                    "Skipped {} Engine DJ tracks from {path} ({}).",
+                    import.skipped(),
+                    import.skip_summary()
+                );
+            }
+            import.entries
+        }
+        MetadataSource::VirtualDj => {
+            println!("Importing VirtualDJ database: {path}");
+            let import = virtualdj::load_virtualdj_xml(path)?;
+            if import.skipped() > 0 {
+                println!(
+                    "Skipped {} VirtualDJ songs from {path} ({}).",
+                    import.skipped(),
+                    import.skip_summary()
+                );
+            }
+            import.entries
+        }
+        MetadataSource::FileTags => {
+            println!("Scanning audio tags: {path}");
+            let report = scan::scan_folder(path, layer.kind, false)?;
+            if report.import.skipped() + report.unreadable.len() > 0 {
+                println!(
+                    "Skipped {} of {} audio files in {path} ({}, unreadable={}).",
+                    report.import.skipped() + report.unreadable.len(),
+                    report.files,
+                    report.import.skip_summary(),
+                    report.unreadable.len()
+                );
+            }
+            report.import.entries
+        }
+        MetadataSource::Csv => {
+            let mapping = csv_mapping
+                .as_deref()
+                .map(CsvMapping::resolve)
+                .transpose()?;
+            let import = csv_import::load_csv(path, mapping.as_ref(), layer.kind)?;
+            println!("Imported CSV with {} columns: {path}", import.mapping);
+            if import.library.skipped() > 0 {
+                println!(
+                    "Skipped {} CSV rows from {path} ({}).",
+                    import.library.skipped(),
+                    import.library.skip_summary()
+                );
+            }
+            import.library.entries
+        }
+        MetadataSource::Rekordbox => {
+            println!("Importing Rekordbox XML: {path}");
+            let import = rekordbox::load_rekordbox_xml(path)?;
+            if import.skipped() > 0 {
+                println!(
+                    "Skipped {} Rekordbox tracks from {path} ({}).",
+                    import.skipped(),
+                    import.skip_summary()
+                );
+            }
+            import.entries
+        }
+        MetadataSource::Traktor => {
+            println!("Importing Traktor NML: {path}");
+            let import = traktor::load_traktor_nml(path)?;
+            if import.skipped() > 0 {
+                println!(
+                    "Skipped {} Traktor entries from {path} ({}).",
+                    import.skipped(),
+                    import.skip_summary()
+                );
+            }
+            import.entries
+        }
+        MetadataSource::Serato => {
+            println!("Importing Serato library: {path}");
+            let import = serato::load_serato(path)?;
+            if import.skipped() > 0 {
+                println!(
+                    "Skipped {} Serato tracks from {path} ({}).",
+                    import.skipped(),
+                    import.skip_summary()
+                );
+            }
+            import.entries
+        }
+        MetadataSource::Override => bail!("{} cannot be loaded as a metadata source", layer.name),
+    };
 
-    println!("Loading {LOCAL_DB_PATH}...");
-    let mut metadata_entries = load_local_db_entries(LOCAL_DB_PATH)?;
-    println!("Loaded {} local metadata entries.", metadata_entries.len());
+    println!(
+        "Loaded {} metadata entries into {} (priority {}).",
+        entries.len(),
+        layer.name,
+        layer.priority
+    );
+    Ok(entries
+        .into_iter()
+        .map(|entry| MetadataEntry {
+            source: layer.clone(),
+            ..entry
+        })
+        .collect())
+}
 
-    for exportify_csv_path in &cli_args.exportify_csv_paths {
-        println!("Importing Exportify CSV: {exportify_csv_path}");
-        let import = load_exportify_csv(exportify_csv_path)?;
-        println!(
-            "Imported {} Exportify metadata rows from {}.",
-            import.entries.len(),
-            exportify_csv_path
-        );
-        if import.skipped.total() > 0 {
-            println!(
-                "Skipped {} Exportify rows from {} (missing_tempo={}, missing_key_mode={}, invalid_tempo={}, invalid_key_mode={}).",
-                import.skipped.total(),
-                exportify_csv_path,
-                import.skipped.missing_tempo,
-                import.skipped.missing_key_mode,
-                import.skipped.invalid_tempo,
-                import.skipped.invalid_key_mode
-            );
-        }
-        metadata_entries.extend(import.entries);
+fn load_metadata_index(metadata: &MetadataArgs) -> Result<MetadataIndex> {
+    let mut metadata_entries = Vec::new();
+    for source in sources::metadata_sources(metadata)? {
+        metadata_entries.extend(load_source_entries(&source)?);
     }
 
     let metadata_index = build_metadata_index(metadata_entries);
@@ -755,8 +1160,51 @@ async fn main() -> Result<()> {
         "Metadata pool ready with {} total entries.",
         metadata_index.all_entries.len()
     );
+    Ok(metadata_index)
+}
+
+fn overrides_path(metadata: &MetadataArgs) -> &str {
+    metadata.overrides_path.as_deref().unwrap_or(OVERRIDES_PATH)
+}
+
+fn load_match_overrides(metadata: &MetadataArgs) -> Result<MatchOverrides> {
+    if metadata.overrides_path.is_none() && !Path::new(OVERRIDES_PATH).exists() {
+        return Ok(MatchOverrides::default());
+    }
+
+    let path = overrides_path(metadata);
+    println!("Loading match overrides from {path}...");
+    let overrides = load_overrides(path)?;
+    println!("Loaded {} match overrides.", overrides.len());
+    Ok(overrides)
+}
 
-    let playlist_id_value = extract_playlist_id(&cli_args.playlist_input)?;
+fn load_sort_rules(harmonic_rules_path: Option<&str>) -> Result<HarmonicRules> {
+    let path = match harmonic_rules_path {
+        Some(path) => path,
+        None if Path::new(HARMONIC_RULES_PATH).exists() => HARMONIC_RULES_PATH,
+        None => return Ok(HarmonicRules::default()),
+    };
+    println!("Loading harmonic rules from {path}...");
+    let rules = load_harmonic_rules(path)?;
+    println!("Allowed moves: {}", rules.describe());
+    Ok(rules)
+}
+
+async fn run_sort(metadata: &MetadataArgs, args: &SortArgs, notation: Notation) -> Result<()> {
+    let rules = load_sort_rules(args.harmonic_rules_path.as_deref())?;
+    let metadata_index = load_metadata_index(metadata)?;
+    let mut overrides = load_match_overrides(metadata)?;
+    let mut match_cache = match &args.match_cache_path {
+        Some(path) => {
+            let cache = load_match_cache(path)?;
+            println!("Loaded {} cached matches from {path}.", cache.len());
+            Some(cache)
+        }
+        None => None,
+    };
+
+    let playlist_id_value = extract_playlist_id(&args.playlist_input)?;
     let playlist_id = PlaylistId::from_id(&playlist_id_value)
         .map_err(|_| anyhow!("failed to construct playlist id from {}", playlist_id_value))?;
 
@@ -765,21 +1213,173 @@ async fn main() -> Result<()> {
     println!("Found {} tracks in playlist.", all_tracks.len());
 
     println!("Enriching data...");
-    let stats = enrich_tracks(&mut all_tracks, &metadata_index);
+    // Only lock stdin when there will be questions to read answers for.
+    let mut prompt = args
+        .interactive
+        .then(|| TerminalPrompt::new(io::stdin().lock(), io::stdout(), notation));
+    let disambiguation = prompt.as_mut().map(|prompt| Disambiguation {
+        prompt,
+        overrides_path: overrides_path(metadata),
+    });
+    let stats = enrich_tracks(
+        &mut all_tracks,
+        &metadata_index,
+        &mut overrides,
+        match_cache.as_mut(),
+        disambiguation,
+        args.conflict_policy,
+        notation,
+    )?;
     println!(
-        "Enrichment summary: matched={}, unmatched={}, invalid_key={}",
-        stats.matched, stats.unmatched, stats.invalid_key
+        "Enrichment summary: matched={}, unmatched={}, ignored={}, cached={}, conflicts={}, unresolved={}",
+        stats.matched,
+        stats.unmatched,
+        stats.ignored,
+        stats.cached,
+        stats.conflicts.len(),
+        stats.unresolved
     );
+    if !stats.by_source.is_empty() {
+        let counts: Vec<String> = stats
+            .by_source
+            .iter()
+            .map(|(name, count)| format!("{name}={count}"))
+            .collect();
+        println!("Matches by source: {}", counts.join(", "));
+    }
+    if let Some(path) = &args.conflict_report_path {
+        write_conflict_report(path, &stats.conflicts)?;
+        println!("Wrote {} conflicts to {path}.", stats.conflicts.len());
+    }
+    if let (Some(path), Some(cache)) = (&args.match_cache_path, &match_cache) {
+        if cache.is_dirty() {
+            save_match_cache(path, cache)?;
+            println!("Saved {} cached matches to {path}.", cache.len());
+        }
+    }
+    if let Some(path) = args
+        .missing_template_path
+        .as_ref()
+        .filter(|_| !stats.missing.is_empty())
+    {
+        let added = fill_in::write_missing_template(path, &stats.missing)?;
+        println!("Added {added} missing tracks to {path}; fill in bpm/key_camelot and run `db import-template {path}`.");
+    }
 
-    let sorted_tracks = sort_tracks(all_tracks);
+    let target_bpm = if args.pitch.shift {
+        let target = args.pitch.target_bpm.or_else(|| {
+            median_bpm(
+                all_tracks
+                    .iter()
+                    .filter(|track| track.key.is_some())
+                    .filter_map(|track| track.tempo)
+                    .collect(),
+            )
+        });
+        if let Some(target) = target {
+            println!("Checking keys as played at {target:.1} BPM without key lock.");
+        }
+        target
+    } else {
+        None
+    };
+    let sorted_tracks = sort_tracks(all_tracks, args.sort_mode, &rules, target_bpm);
+    for line in describe_transitions(
+        &sorted_tracks,
+        &rules,
+        target_bpm,
+        args.pitch.suggest,
+        notation,
+    ) {
+        println!("{line}");
+    }
     update_playlist(&spotify, &playlist_id, &sorted_tracks).await?;
 
     Ok(())
 }
 
+async fn run_explain(
+    metadata: &MetadataArgs,
+    track_input: &str,
+    duration_ms: Option<u32>,
+    conflict_policy: ConflictPolicy,
+    notation: Notation,
+) -> Result<()> {
+    let metadata_index = load_metadata_index(metadata)?;
+    let overrides = load_match_overrides(metadata)?;
+
+    let track = match extract_track_id(track_input) {
+        Some(track_id) => {
+            let spotify = authenticate_spotify().await?;
+            let track_id = TrackId::from_id(&track_id)
+                .map_err(|_| anyhow!("invalid Spotify track input: {track_input}"))?;
+            let full_track = spotify.track(track_id, None).await?;
+            let mut track = track_info_from_full(&full_track)
+                .ok_or_else(|| anyhow!("Spotify returned a track without an id"))?;
+            if let Some(duration_ms) = duration_ms {
+                track.duration_ms = duration_ms;
+            }
+            track
+        }
+        None => explain::track_from_text(track_input, duration_ms)?,
+    };
+
+    print!(
+        "{}",
+        explain::explain_match(
+            &track,
+            &metadata_index,
+            &overrides,
+            conflict_policy,
+            notation,
+        )
+    );
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenvy::dotenv().ok();
+
+    let cli_args = parse_args_from(env::args())?;
+    let notation = cli_args.key_notation;
+
+    match &cli_args.command {
+        Command::Sort(args) => run_sort(&cli_args.metadata, args, notation).await,
+        Command::Explain {
+            track_input,
+            duration_ms,
+            conflict_policy,
+        } => {
+            run_explain(
+                &cli_args.metadata,
+                track_input,
+                *duration_ms,
+                *conflict_policy,
+                notation,
+            )
+            .await
+        }
+        Command::Db(command) => db::run_db_command(&cli_args.metadata, command, notation),
+        Command::Scan {
+            folder,
+            mode,
+            analyze,
+        } => scan::run_scan(
+            local_db_path(&cli_args.metadata),
+            folder,
+            *mode,
+            *analyze,
+            notation,
+        ),
+        Command::Analyze { paths } => analysis::run_analyze(paths, notation),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::key::Mode;
 
     fn sample_track(name: &str, artist: &str, duration_ms: u32) -> TrackInfo {
         TrackInfo {
@@ -787,7 +1387,6 @@ mod tests {
             name: name.to_string(),
             artist: artist.to_string(),
             key: None,
-            mode: None,
             tempo: None,
             duration_ms,
         }
@@ -805,9 +1404,11 @@ mod tests {
             name: name.to_string(),
             artist: artist.to_string(),
             bpm,
-            key_camelot: key_camelot.to_string(),
+            bpm_confidence: None,
+            key: Key::parse(key_camelot).unwrap(),
             duration_ms,
-            source,
+            genre: None,
+            source: source.into(),
         }
     }
 
@@ -817,24 +1418,15 @@ mod tests {
     }
 
     #[test]
-    fn camelot_to_spotify_parses_minor_and_major() {
-        assert_eq!(camelot_to_spotify("5A"), Some((0, Modality::Minor)));
-        assert_eq!(camelot_to_spotify("10B"), Some((2, Modality::Major)));
-        assert_eq!(camelot_to_spotify("13B"), None);
-        assert_eq!(camelot_to_spotify("5"), None);
-    }
-
-    #[test]
-    fn spotify_to_camelot_round_trips_supported_keys() {
-        assert_eq!(
-            spotify_to_camelot(0, Modality::Minor),
-            Some("5A".to_string())
-        );
-        assert_eq!(
-            spotify_to_camelot(2, Modality::Major),
-            Some("10B".to_string())
-        );
-        assert_eq!(spotify_to_camelot(-1, Modality::Major), None);
+    fn key_to_camelot_reads_musical_and_camelot_notation() {
+        assert_eq!(key_to_camelot("Am").as_deref(), Some("8A"));
+        assert_eq!(key_to_camelot("F#m").as_deref(), Some("11A"));
+        assert_eq!(key_to_camelot("Db").as_deref(), Some("3B"));
+        assert_eq!(key_to_camelot("B").as_deref(), Some("1B"));
+        assert_eq!(key_to_camelot("Ebmin").as_deref(), Some("2A"));
+        assert_eq!(key_to_camelot(" 8a ").as_deref(), Some("8A"));
+        assert_eq!(key_to_camelot("H"), None);
+        assert_eq!(key_to_camelot("Cx"), None);
     }
 
     #[test]
@@ -853,7 +1445,7 @@ mod tests {
     }
 
     #[test]
-    fn find_best_match_prefers_exact_artist_and_duration() {
+    fn rank_candidates_prefers_exact_artist_and_duration() {
         let track = sample_track("Losing It", "FISHER", 248_000);
         let candidates = vec![
             metadata_entry(
@@ -882,7 +1474,7 @@ mod tests {
             ),
         ];
 
-        let best = find_best_match(&track, &candidates).unwrap();
+        let best = rank_candidates(&track, &candidates)[0].entry;
         assert_eq!(best.artist, "FISHER");
         assert_eq!(best.bpm, 125.0);
     }
@@ -890,24 +1482,195 @@ mod tests {
     #[test]
     fn find_match_for_track_uses_fuzzy_title_fallback() {
         let track = sample_track("Space Song - Remastered", "Beach House", 320_000);
-        let metadata_index = MetadataIndex {
-            by_title: HashMap::new(),
-            all_entries: vec![metadata_entry(
+        let metadata_index = build_metadata_index(vec![metadata_entry(
+            "Space Song",
+            "Beach House",
+            147.0,
+            "5A",
+            None,
+            MetadataSource::LocalDb,
+        )]);
+
+        let matched = find_match_for_track(&track, &metadata_index, ConflictPolicy::Priority)
+            .0
+            .unwrap();
+        assert_eq!(matched.name, "Space Song");
+    }
+
+    #[test]
+    fn fuzzy_candidates_checks_containment_in_load_order() {
+        let metadata_index = build_metadata_index(vec![
+            metadata_entry(
+                "Space Songs",
+                "Beach House",
+                147.0,
+                "5A",
+                None,
+                MetadataSource::LocalDb,
+            ),
+            metadata_entry(
+                "Space",
+                "Beach House",
+                100.0,
+                "5A",
+                None,
+                MetadataSource::LocalDb,
+            ),
+            metadata_entry(
+                "Space Song",
+                "Beach Boys",
+                120.0,
+                "5A",
+                None,
+                MetadataSource::LocalDb,
+            ),
+            metadata_entry(
                 "Space Song",
                 "Beach House",
                 147.0,
                 "5A",
                 None,
+                MetadataSource::Exportify,
+            ),
+        ]);
+        let track = sample_track("Space Song", "Beach House", 320_000);
+
+        let names: Vec<(&str, MetadataSource)> = fuzzy_candidates(&track, &metadata_index)
+            .map(|entry| (entry.name.as_str(), entry.source.kind))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("Space Songs", MetadataSource::LocalDb),
+                ("Space", MetadataSource::LocalDb),
+                ("Space Song", MetadataSource::Exportify),
+            ]
+        );
+    }
+
+    #[test]
+    fn fuzzy_candidates_keeps_sub_word_containment() {
+        let metadata_index = build_metadata_index(vec![
+            metadata_entry(
+                "Glued",
+                "Bicepz",
+                130.0,
+                "8A",
+                None,
+                MetadataSource::LocalDb,
+            ),
+            metadata_entry("Glue", "Bice", 130.0, "8A", None, MetadataSource::LocalDb),
+            metadata_entry(
+                "The Remix",
+                "The Remixers",
+                128.0,
+                "8A",
+                None,
                 MetadataSource::LocalDb,
-            )],
+            ),
+            metadata_entry("Go", "DJ", 120.0, "8A", None, MetadataSource::LocalDb),
+        ]);
+        let names = |track: TrackInfo| -> Vec<String> {
+            fuzzy_candidates(&track, &metadata_index)
+                .map(|entry| format!("{} - {}", entry.artist, entry.name))
+                .collect()
         };
 
-        let matched = find_match_for_track(&track, &metadata_index).unwrap();
-        assert_eq!(matched.name, "Space Song");
+        // Entry fields containing the track's, and contained in them.
+        assert_eq!(
+            names(sample_track("Glue", "Bicep", 0)),
+            ["Bicepz - Glued", "Bice - Glue"]
+        );
+        // Words as common as `the` and `remix` still find sub-word matches.
+        assert_eq!(
+            names(sample_track("The Remix (Edit)", "The Remixer", 0)),
+            ["The Remixers - The Remix"]
+        );
+        // Fields shorter than a trigram fall back to checking every entry.
+        assert_eq!(names(sample_track("Go", "DJ", 0)), ["DJ - Go"]);
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn fuzzy_matching_scales_to_large_libraries() {
+        let mut seed: u64 = 0x5eed;
+        let mut next = move |bound: usize| {
+            seed = seed
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (seed >> 33) as usize % bound
+        };
+        let syllables = [
+            "ka", "lo", "mi", "ra", "ten", "su", "vo", "ne", "bri", "ja", "qu", "ze",
+        ];
+        let mut words = |count: usize, syllable_count: usize| -> Vec<String> {
+            (0..count)
+                .map(|_| {
+                    (0..syllable_count)
+                        .map(|_| syllables[next(syllables.len())])
+                        .collect()
+                })
+                .collect()
+        };
+        let title_words = words(5_000, 3);
+        let artist_words = words(2_000, 2);
+
+        let entries: Vec<MetadataEntry> = (0..100_000)
+            .map(|_| {
+                // Real libraries repeat a handful of words across most titles.
+                let mut title = (0..2 + next(3))
+                    .map(|_| title_words[next(title_words.len())].as_str())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                if next(2) == 0 {
+                    title = format!("the {title}");
+                }
+                if next(2) == 0 {
+                    title.push_str(" (original mix)");
+                }
+                let mut artist = artist_words[next(artist_words.len())].clone();
+                if next(3) == 0 {
+                    artist.push_str(" feat. the remix crew");
+                }
+                metadata_entry(&title, &artist, 124.0, "8A", None, MetadataSource::LocalDb)
+            })
+            .collect();
+        let tracks: Vec<TrackInfo> = (0..10_000)
+            .map(|i| {
+                let entry = &entries[next(entries.len())];
+                let name = if i % 2 == 0 {
+                    format!("{} - Remastered", entry.name)
+                } else {
+                    format!("{} {}", entry.name, title_words[next(title_words.len())])
+                };
+                sample_track(&name, &entry.artist, 200_000)
+            })
+            .collect();
+
+        let started = std::time::Instant::now();
+        let metadata_index = build_metadata_index(entries);
+        let indexed = started.elapsed();
+        let matched = tracks
+            .iter()
+            .filter(|track| {
+                find_match_for_track(track, &metadata_index, ConflictPolicy::Priority)
+                    .0
+                    .is_some()
+            })
+            .count();
+        let total = started.elapsed();
+
+        println!(
+            "indexed 100k entries in {indexed:?}, matched {matched}/10k tracks in {:?}",
+            total - indexed
+        );
+        assert_eq!(matched, tracks.len());
+        assert!(total < std::time::Duration::from_secs(10));
     }
 
     #[test]
-    fn find_best_match_prefers_local_db_on_score_tie() {
+    fn rank_candidates_prefers_local_db_on_score_tie() {
         let track = sample_track("Losing It", "FISHER", 248_000);
         let local = metadata_entry(
             "Losing It",
@@ -926,79 +1689,346 @@ mod tests {
             MetadataSource::Exportify,
         );
 
-        let best = find_best_match(&track, &[exportify, local.clone()]).unwrap();
-        assert_eq!(best.source, MetadataSource::LocalDb);
-        assert_eq!(best, local);
+        let candidates = [exportify, local.clone()];
+        let best = rank_candidates(&track, &candidates)[0].entry;
+        assert_eq!(best.source.kind, MetadataSource::LocalDb);
+        assert_eq!(best, &local);
+    }
+
+    #[test]
+    fn ambiguous_candidates_requires_close_scores_and_differing_metadata() {
+        let track = sample_track("Losing It", "FISHER", 248_000);
+        let original = metadata_entry(
+            "Losing It",
+            "FISHER",
+            125.0,
+            "10B",
+            None,
+            MetadataSource::LocalDb,
+        );
+        let same_values = metadata_entry(
+            "Losing It",
+            "FISHER",
+            125.0,
+            "10b",
+            None,
+            MetadataSource::Exportify,
+        );
+        let other_key = metadata_entry(
+            "Losing It",
+            "FISHER",
+            125.0,
+            "11B",
+            None,
+            MetadataSource::Exportify,
+        );
+        let far_behind = metadata_entry(
+            "Losing It",
+            "FISHER",
+            124.0,
+            "10B",
+            Some(400_000),
+            MetadataSource::LocalDb,
+        );
+
+        let agreeing = [original.clone(), same_values, far_behind.clone()];
+        assert!(ambiguous_candidates(&rank_candidates(&track, &agreeing)).is_empty());
+
+        let disagreeing = [original, other_key, far_behind];
+        let ranked = rank_candidates(&track, &disagreeing);
+        let close = ambiguous_candidates(&ranked);
+        assert_eq!(close.len(), 2);
+        assert_eq!(close[1].entry.key.camelot(), "11B");
+    }
+
+    #[test]
+    fn enrich_tracks_keeps_cached_matches_when_better_entries_appear() {
+        let original = metadata_entry(
+            "Losing It",
+            "FISHER",
+            125.0,
+            "10B",
+            None,
+            MetadataSource::LocalDb,
+        );
+        let better = metadata_entry(
+            "Losing It",
+            "FISHER",
+            124.0,
+            "11B",
+            Some(248_000),
+            MetadataSource::LocalDb,
+        );
+        let mut cache = MatchCache::default();
+        let mut tracks = vec![sample_track("Losing It", "FISHER", 248_000)];
+
+        let first_index = build_metadata_index(vec![original]);
+        let stats = enrich_tracks(
+            &mut tracks,
+            &first_index,
+            &mut MatchOverrides::default(),
+            Some(&mut cache),
+            None,
+            ConflictPolicy::Priority,
+            Notation::Camelot,
+        )
+        .unwrap();
+        assert_eq!((stats.matched, stats.cached), (1, 0));
+
+        let mut second_entries = first_index.all_entries.clone();
+        second_entries.push(better);
+        let stats = enrich_tracks(
+            &mut tracks,
+            &build_metadata_index(second_entries),
+            &mut MatchOverrides::default(),
+            Some(&mut cache),
+            None,
+            ConflictPolicy::Priority,
+            Notation::Camelot,
+        )
+        .unwrap();
+        assert_eq!((stats.matched, stats.cached), (1, 1));
+        assert_eq!(tracks[0].tempo, Some(125.0));
+    }
+
+    #[test]
+    fn load_local_db_entries_parses_keys_and_skips_unreadable_ones() {
+        let path = std::env::temp_dir().join(format!(
+            "mix_sorter_local_keys_test_{}.json",
+            std::process::id()
+        ));
+        fs::write(
+            &path,
+            r#"[
+  {"name": "Losing It", "artist": "FISHER", "bpm": 125, "key_camelot": "A minor"},
+  {"name": "Broken", "artist": "Nobody", "bpm": 120, "key_camelot": "13A"}
+]"#,
+        )
+        .unwrap();
+
+        let entries = load_local_db_entries(path.to_str().unwrap()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].key, Key::parse("8A").unwrap());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn overlay_files_of_one_kind_are_separate_layers() {
+        let dir =
+            std::env::temp_dir().join(format!("mix_sorter_overlays_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let header = "Track Name,Artist Name(s),Duration (ms),Key,Mode,Tempo\n";
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        fs::write(path("local.json"), "[]").unwrap();
+        fs::write(
+            path("march.csv"),
+            format!("{header}Losing It,FISHER,248000,9,0,125.0\n"),
+        )
+        .unwrap();
+        fs::write(
+            path("april.csv"),
+            format!("{header}Losing It,FISHER,248000,10,0,125.0\n"),
+        )
+        .unwrap();
+
+        let metadata_index = load_metadata_index(&MetadataArgs {
+            local_db_path: Some(path("local.json")),
+            exportify_csv_paths: vec![path("march.csv"), path("april.csv")],
+            ..MetadataArgs::default()
+        })
+        .unwrap();
+        let track = sample_track("Losing It", "FISHER", 248_000);
+        let (chosen, conflict) =
+            find_match_for_track(&track, &metadata_index, ConflictPolicy::Majority);
+
+        let layers: Vec<&str> = conflict
+            .as_ref()
+            .unwrap()
+            .candidates
+            .iter()
+            .map(|(entry, _)| entry.source.label())
+            .collect();
+        assert_eq!(layers, ["EXPORTIFY:march", "EXPORTIFY:april"]);
+        // One vote each, so the tie falls back to load order.
+        assert_eq!(chosen.unwrap().source.label(), "EXPORTIFY:march");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn enrich_tracks_reports_conflicts_on_every_cached_run() {
+        let local = metadata_entry(
+            "Losing It",
+            "FISHER",
+            125.0,
+            "10B",
+            Some(248_000),
+            MetadataSource::LocalDb,
+        );
+        let exportify = metadata_entry(
+            "Losing It",
+            "FISHER",
+            124.0,
+            "11B",
+            Some(248_000),
+            MetadataSource::Exportify,
+        );
+        let mut cache = MatchCache::default();
+        // The second track only reaches the entries through the fuzzy fallback;
+        // its cache hit is checked against the entry's own title bucket.
+        let mut tracks = vec![
+            sample_track("Losing It", "FISHER", 248_000),
+            TrackInfo {
+                id: "radio-edit-id".to_string(),
+                ..sample_track("Losing It - Radio Edit", "FISHER", 248_000)
+            },
+        ];
+
+        // Cached while only one layer knew the track.
+        let single_layer = build_metadata_index(vec![local.clone()]);
+        enrich_tracks(
+            &mut tracks,
+            &single_layer,
+            &mut MatchOverrides::default(),
+            Some(&mut cache),
+            None,
+            ConflictPolicy::Priority,
+            Notation::Camelot,
+        )
+        .unwrap();
+        assert_eq!(cache.len(), 2);
+
+        let both_layers = build_metadata_index(vec![local, exportify]);
+        for _ in 0..2 {
+            let stats = enrich_tracks(
+                &mut tracks,
+                &both_layers,
+                &mut MatchOverrides::default(),
+                Some(&mut cache),
+                None,
+                ConflictPolicy::Unresolved,
+                Notation::Camelot,
+            )
+            .unwrap();
+            assert_eq!(
+                (stats.cached, stats.conflicts.len(), stats.unresolved),
+                (0, 2, 2)
+            );
+            assert_eq!(cache.len(), 0);
+        }
     }
 
     #[test]
     fn sort_tracks_puts_unmatched_tracks_last() {
         let matched_later = TrackInfo {
-            key: Some(11),
-            mode: Some(Modality::Major),
+            key: Key::new(11, Mode::Major),
             tempo: Some(128.0),
             ..sample_track("Later", "Artist", 200_000)
         };
         let matched_earlier = TrackInfo {
-            key: Some(0),
-            mode: Some(Modality::Minor),
+            key: Key::new(0, Mode::Minor),
             tempo: Some(120.0),
             ..sample_track("Earlier", "Artist", 200_000)
         };
         let unmatched = sample_track("Unknown", "Artist", 200_000);
+        let without_key = TrackInfo {
+            tempo: Some(124.0),
+            ..sample_track("Without Key", "Artist", 200_000)
+        };
 
-        let sorted = sort_tracks(vec![matched_later, unmatched.clone(), matched_earlier]);
+        let sorted = sort_tracks(
+            vec![
+                without_key,
+                matched_later,
+                unmatched.clone(),
+                matched_earlier,
+            ],
+            SortMode::Wheel,
+            &HarmonicRules::default(),
+            None,
+        );
         assert_eq!(sorted[0].name, "Later");
         assert_eq!(sorted[1].name, "Earlier");
-        assert_eq!(sorted[2], unmatched);
+        assert_eq!(sorted[2].name, "Without Key");
+        assert_eq!(sorted[3], unmatched);
     }
 
     #[test]
-    fn parse_args_accepts_repeated_exportify_flags() {
-        let cli = parse_args_from([
-            "mix_sorter".to_string(),
-            "--exportify-csv".to_string(),
-            "one.csv".to_string(),
-            "--exportify-csv=two.csv".to_string(),
-            "spotify:playlist:2nOsiUa2nlXBGuDMjDIbDb".to_string(),
-        ])
-        .unwrap();
+    fn describe_transitions_names_moves_and_clashes() {
+        let keyed = |name: &str, raw: &str| TrackInfo {
+            key: Key::parse(raw),
+            tempo: Some(124.0),
+            ..sample_track(name, "Artist", 200_000)
+        };
+        let tracks = vec![
+            keyed("One", "8A"),
+            keyed("Two", "9A"),
+            sample_track("Unknown", "Artist", 200_000),
+            keyed("Three", "9B"),
+            keyed("Four", "4B"),
+        ];
 
         assert_eq!(
-            cli,
-            CliArgs {
-                binary_name: "mix_sorter".to_string(),
-                playlist_input: "spotify:playlist:2nOsiUa2nlXBGuDMjDIbDb".to_string(),
-                exportify_csv_paths: vec!["one.csv".to_string(), "two.csv".to_string()],
-            }
+            describe_transitions(
+                &tracks,
+                &HarmonicRules::default(),
+                None,
+                false,
+                Notation::Camelot
+            ),
+            [
+                "[CLASH] Artist - Three (9B) -> Artist - Four (4B)",
+                "Harmonic flow: 2 of 3 transitions are allowed moves (adjacent=1, relative=1); largest jump 5 wheel steps",
+            ]
         );
+        assert_eq!(
+            describe_transitions(
+                &tracks,
+                &HarmonicRules::default(),
+                None,
+                false,
+                Notation::OpenKey
+            )[0],
+            "[CLASH] Artist - Three (2d) -> Artist - Four (9d)"
+        );
+        assert!(describe_transitions(
+            &tracks[..1],
+            &HarmonicRules::default(),
+            None,
+            false,
+            Notation::Camelot
+        )
+        .is_empty());
+
+        let resorted = sort_tracks(tracks, SortMode::Harmonic, &HarmonicRules::default(), None);
+        let names: Vec<&str> = resorted.iter().map(|track| track.name.as_str()).collect();
+        assert_eq!(names, ["Four", "One", "Two", "Three", "Unknown"]);
     }
 
     #[test]
-    fn load_exportify_csv_reader_imports_numeric_mode_with_bom_header() {
-        let csv = "\u{feff}Track Name,Artist Name(s),Duration (ms),Key,Mode,Tempo\nLosing It,FISHER;Chris Lake,248000,2,1,125.0\n";
-        let import = load_exportify_csv_reader(csv.as_bytes()).unwrap();
+    fn describe_transitions_checks_keys_as_played_and_suggests_nudges() {
+        let keyed = |name: &str, raw: &str, bpm: f32| TrackInfo {
+            key: Key::parse(raw),
+            tempo: Some(bpm),
+            ..sample_track(name, "Artist", 200_000)
+        };
+        // 3A at 127.1 BPM sounds a semitone lower, as 8A, at 120.
+        let tracks = vec![keyed("One", "8A", 120.0), keyed("Two", "3A", 127.1)];
+        let rules = HarmonicRules::default();
 
-        assert_eq!(import.skipped.total(), 0);
-        assert_eq!(import.entries.len(), 1);
-        assert_eq!(import.entries[0].name, "Losing It");
-        assert_eq!(import.entries[0].artist, "FISHER");
-        assert_eq!(import.entries[0].duration_ms, Some(248_000));
-        assert_eq!(import.entries[0].key_camelot, "10B");
-        assert_eq!(import.entries[0].source, MetadataSource::Exportify);
-    }
+        assert_eq!(
+            describe_transitions(&tracks, &rules, None, true, Notation::Camelot),
+            [
+                "[CLASH] Artist - One (8A) -> Artist - Two (3A)",
+                "[PITCH] Artist - Two: -1 semitone (-5.6% tempo) plays in 8A, same_key from 8A",
+                "Harmonic flow: 0 of 1 transitions are allowed moves; largest jump 5 wheel steps",
+            ]
+        );
+        assert_eq!(
+            describe_transitions(&tracks, &rules, Some(120.0), true, Notation::Camelot),
+            ["Harmonic flow: 1 of 1 transitions are allowed moves (same_key=1); largest jump 0 wheel steps"]
+        );
 
-    #[test]
-    fn load_exportify_csv_reader_tracks_skipped_rows() {
-        let csv = "Track Name,Artist Name(s),Duration (ms),Key,Mode,Tempo\n\
-                   Missing Tempo,FISHER,248000,2,1,\n\
-                   Invalid Mode,FISHER,248000,2,weird,125.0\n\
-                   Good Track,FISHER,248000,2,1,125.0\n";
-        let import = load_exportify_csv_reader(csv.as_bytes()).unwrap();
-
-        assert_eq!(import.entries.len(), 1);
-        assert_eq!(import.skipped.missing_tempo, 1);
-        assert_eq!(import.skipped.invalid_key_mode, 1);
-        assert_eq!(import.skipped.total(), 2);
+        let sorted = sort_tracks(tracks, SortMode::Wheel, &rules, Some(120.0));
+        assert_eq!(played_key(&sorted[1], Some(120.0)), Key::parse("8A"));
     }
 }
@@ -0,0 +1,259 @@
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{normalize, score_candidate, MetadataEntry, MetadataIndex, TrackInfo};
+
+const MATCH_CACHE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, PartialEq)]
+struct CachedMatch {
+    name: String,
+    artist: String,
+    source: String,
+    /// Changes whenever any field of the referenced entry changes.
+    fingerprint: String,
+    score: i32,
+    matched_at: u64,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, PartialEq)]
+struct MatchCacheFile {
+    version: u32,
+    matches: BTreeMap<String, CachedMatch>,
+}
+
+/// Spotify track ID -> previously resolved metadata entry, so repeated runs
+/// keep the same matches even when new similar entries are added.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct MatchCache {
+    matches: BTreeMap<String, CachedMatch>,
+    dirty: bool,
+}
+
+/// FNV-1a over every entry field, stable across runs and Rust versions.
+pub(crate) fn entry_fingerprint(entry: &MetadataEntry) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let fields = [
+        entry.name.as_str(),
+        entry.artist.as_str(),
+        &entry.bpm.to_bits().to_string(),
+        &entry.key.camelot(),
+        &entry
+            .duration_ms
+            .map(|duration| duration.to_string())
+            .unwrap_or_default(),
+        entry.genre.as_deref().unwrap_or_default(),
+        &entry
+            .bpm_confidence
+            .map(|confidence| confidence.to_bits().to_string())
+            .unwrap_or_default(),
+        entry.source.label(),
+    ];
+    for field in fields {
+        for byte in field.bytes().chain([0]) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    format!("{hash:016x}")
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+impl MatchCache {
+    pub(crate) fn len(&self) -> usize {
+        self.matches.len()
+    }
+
+    pub(crate) fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub(crate) fn from_json(content: &str) -> Result<Self> {
+        let file: MatchCacheFile = serde_json::from_str(content)?;
+        if file.version != MATCH_CACHE_VERSION {
+            bail!(
+                "unsupported match cache version {} (expected {MATCH_CACHE_VERSION})",
+                file.version
+            );
+        }
+        Ok(Self {
+            matches: file.matches,
+            dirty: false,
+        })
+    }
+
+    pub(crate) fn to_json(&self) -> Result<String> {
+        let file = MatchCacheFile {
+            version: MATCH_CACHE_VERSION,
+            matches: self.matches.clone(),
+        };
+        Ok(serde_json::to_string_pretty(&file)? + "\n")
+    }
+
+    /// Returns the cached entry for `track` if it still exists unchanged in
+    /// the index; otherwise drops the stale record so the track is rematched.
+    pub(crate) fn lookup<'a>(
+        &mut self,
+        track: &TrackInfo,
+        metadata_index: &'a MetadataIndex,
+    ) -> Option<&'a MetadataEntry> {
+        let cached = self.matches.get(&track.id)?;
+        let entry = metadata_index
+            .by_title
+            .get(&normalize(&cached.name))
+            .and_then(|bucket| {
+                bucket
+                    .iter()
+                    .find(|entry| entry_fingerprint(entry) == cached.fingerprint)
+            });
+
+        if entry.is_none() {
+            self.matches.remove(&track.id);
+            self.dirty = true;
+        }
+        entry
+    }
+
+    pub(crate) fn forget(&mut self, track: &TrackInfo) {
+        if self.matches.remove(&track.id).is_some() {
+            self.dirty = true;
+        }
+    }
+
+    pub(crate) fn record(&mut self, track: &TrackInfo, entry: &MetadataEntry) {
+        let score = score_candidate(
+            track,
+            &normalize(&track.artist),
+            &normalize(&track.name),
+            entry,
+        )
+        .unwrap_or_default();
+
+        self.matches.insert(
+            track.id.clone(),
+            CachedMatch {
+                name: entry.name.clone(),
+                artist: entry.artist.clone(),
+                source: entry.source.label().to_string(),
+                fingerprint: entry_fingerprint(entry),
+                score,
+                matched_at: unix_now(),
+            },
+        );
+        self.dirty = true;
+    }
+}
+
+pub(crate) fn load_match_cache(path: &str) -> Result<MatchCache> {
+    if !Path::new(path).exists() {
+        return Ok(MatchCache::default());
+    }
+
+    let content = fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+    MatchCache::from_json(&content).with_context(|| format!("failed to parse {path}"))
+}
+
+/// Writes through a temporary file, as the database is saved, so an
+/// interrupted run never leaves a truncated cache for the next one to trip on.
+pub(crate) fn save_match_cache(path: &str, cache: &MatchCache) -> Result<()> {
+    let temp_path = format!("{path}.tmp");
+    fs::write(&temp_path, cache.to_json()?)
+        .with_context(|| format!("failed to write {temp_path}"))?;
+    fs::rename(&temp_path, path).with_context(|| format!("failed to replace {path}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::Key;
+    use crate::{build_metadata_index, MetadataSource};
+
+    fn track() -> TrackInfo {
+        TrackInfo {
+            id: "spotify-id".to_string(),
+            name: "Losing It".to_string(),
+            artist: "FISHER".to_string(),
+            key: None,
+            tempo: None,
+            duration_ms: 248_000,
+        }
+    }
+
+    fn entry(bpm: f32) -> MetadataEntry {
+        MetadataEntry {
+            name: "Losing It".to_string(),
+            artist: "FISHER".to_string(),
+            bpm,
+            bpm_confidence: None,
+            key: Key::parse("10B").unwrap(),
+            duration_ms: Some(248_000),
+            genre: None,
+            source: MetadataSource::LocalDb.into(),
+        }
+    }
+
+    #[test]
+    fn lookup_returns_the_recorded_entry_after_a_round_trip() {
+        let metadata_index = build_metadata_index(vec![entry(125.0), entry(124.0)]);
+        let mut cache = MatchCache::default();
+        cache.record(&track(), &metadata_index.all_entries[1]);
+        assert!(cache.is_dirty());
+
+        let mut reloaded = MatchCache::from_json(&cache.to_json().unwrap()).unwrap();
+        assert!(!reloaded.is_dirty());
+        assert_eq!(reloaded.matches["spotify-id"].score, 170);
+
+        let cached = reloaded.lookup(&track(), &metadata_index).unwrap();
+        assert_eq!(cached.bpm, 124.0);
+        assert!(!reloaded.is_dirty());
+    }
+
+    #[test]
+    fn lookup_invalidates_records_for_changed_entries() {
+        let mut cache = MatchCache::default();
+        cache.record(&track(), &entry(125.0));
+        let edited_index = build_metadata_index(vec![entry(126.0)]);
+
+        assert_eq!(cache.lookup(&track(), &edited_index), None);
+        assert_eq!(cache.len(), 0);
+        assert!(cache.is_dirty());
+    }
+
+    #[test]
+    fn entry_fingerprint_is_stable_and_field_sensitive() {
+        assert_eq!(
+            entry_fingerprint(&entry(125.0)),
+            entry_fingerprint(&entry(125.0))
+        );
+        assert_ne!(
+            entry_fingerprint(&entry(125.0)),
+            entry_fingerprint(&entry(125.5))
+        );
+        let retagged = MetadataEntry {
+            genre: Some("Tech House".to_string()),
+            ..entry(125.0)
+        };
+        assert_ne!(
+            entry_fingerprint(&entry(125.0)),
+            entry_fingerprint(&retagged)
+        );
+        let analysed = MetadataEntry {
+            bpm_confidence: Some(0.8),
+            ..entry(125.0)
+        };
+        assert_ne!(
+            entry_fingerprint(&entry(125.0)),
+            entry_fingerprint(&analysed)
+        );
+        assert_eq!(entry_fingerprint(&entry(125.0)).len(), 16);
+    }
+}
@@ -0,0 +1,351 @@
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+
+use crate::db::{MAX_BPM, MIN_BPM};
+use crate::key::Key;
+use crate::{normalize, MetadataEntry, MetadataIndex, MetadataSource, TrackInfo};
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, PartialEq)]
+struct OverrideRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    spotify_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    track: Option<String>,
+    #[serde(flatten)]
+    action: OverrideAction,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub(crate) enum OverrideAction {
+    Ignore,
+    Entry {
+        name: String,
+        artist: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duration_ms: Option<u32>,
+    },
+    Values {
+        bpm: f32,
+        #[serde(rename = "key_camelot")]
+        key: Key,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct MatchOverrides {
+    rules: Vec<OverrideRule>,
+    by_spotify_id: HashMap<String, OverrideAction>,
+    by_track: HashMap<String, OverrideAction>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum OverrideResolution {
+    Ignore,
+    Matched(MetadataEntry),
+    MissingEntry { name: String, artist: String },
+}
+
+pub(crate) fn override_track_key(artist: &str, title: &str) -> String {
+    format!("{} - {}", normalize(artist), normalize(title))
+}
+
+fn parse_track_key(raw: &str) -> Option<String> {
+    let (artist, title) = raw.split_once(" - ")?;
+    if artist.trim().is_empty() || title.trim().is_empty() {
+        return None;
+    }
+    Some(override_track_key(artist, title))
+}
+
+/// Checks a `values` rule's BPM; its key was checked when it was read.
+fn check_values(index: usize, action: &OverrideAction) -> Result<()> {
+    match action {
+        OverrideAction::Values { bpm, .. } if !(MIN_BPM..=MAX_BPM).contains(bpm) => {
+            bail!("override #{index}: BPM {bpm} is outside {MIN_BPM}-{MAX_BPM}")
+        }
+        _ => Ok(()),
+    }
+}
+
+impl MatchOverrides {
+    pub(crate) fn len(&self) -> usize {
+        self.by_spotify_id.len() + self.by_track.len()
+    }
+
+    fn from_rules(rules: Vec<OverrideRule>) -> Result<Self> {
+        let mut overrides = Self::default();
+
+        for (index, rule) in rules.iter().enumerate() {
+            check_values(index, &rule.action)?;
+            let action = rule.action.clone();
+            let replaced = match (&rule.spotify_id, &rule.track) {
+                (Some(spotify_id), None) => overrides
+                    .by_spotify_id
+                    .insert(spotify_id.trim().to_string(), action),
+                (None, Some(track)) => {
+                    let key = parse_track_key(track).with_context(|| {
+                        format!("override #{index}: 'track' must look like 'Artist - Title', got '{track}'")
+                    })?;
+                    overrides.by_track.insert(key, action)
+                }
+                _ => bail!("override #{index}: set exactly one of 'spotify_id' or 'track'"),
+            };
+            if replaced.is_some() {
+                bail!("override #{index}: an earlier override already covers this track");
+            }
+        }
+
+        overrides.rules = rules;
+        Ok(overrides)
+    }
+
+    /// Adds (or replaces) the rule for a Spotify track ID, e.g. after an
+    /// interactive disambiguation decision.
+    pub(crate) fn record(&mut self, spotify_id: &str, action: OverrideAction) {
+        let spotify_id = spotify_id.trim();
+        self.rules
+            .retain(|rule| rule.spotify_id.as_deref().map(str::trim) != Some(spotify_id));
+        self.rules.push(OverrideRule {
+            spotify_id: Some(spotify_id.to_string()),
+            track: None,
+            action: action.clone(),
+        });
+        self.by_spotify_id.insert(spotify_id.to_string(), action);
+    }
+
+    pub(crate) fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.rules)? + "\n")
+    }
+
+    pub(crate) fn from_json(content: &str) -> Result<Self> {
+        let rules: Vec<OverrideRule> = serde_json::from_str(content)?;
+        Self::from_rules(rules)
+    }
+
+    fn action_for(&self, track: &TrackInfo) -> Option<&OverrideAction> {
+        self.by_spotify_id.get(&track.id).or_else(|| {
+            self.by_track
+                .get(&override_track_key(&track.artist, &track.name))
+        })
+    }
+
+    /// Looks up an override for `track`, returning `None` when matching should
+    /// fall through to the regular scorer.
+    pub(crate) fn resolve(
+        &self,
+        track: &TrackInfo,
+        metadata_index: &MetadataIndex,
+    ) -> Option<OverrideResolution> {
+        let resolution = match self.action_for(track)? {
+            OverrideAction::Ignore => OverrideResolution::Ignore,
+            OverrideAction::Entry {
+                name,
+                artist,
+                duration_ms,
+            } => match find_pinned_entry(metadata_index, name, artist, *duration_ms) {
+                Some(entry) => OverrideResolution::Matched(entry.clone()),
+                None => OverrideResolution::MissingEntry {
+                    name: name.clone(),
+                    artist: artist.clone(),
+                },
+            },
+            OverrideAction::Values { bpm, key } => OverrideResolution::Matched(MetadataEntry {
+                name: track.name.clone(),
+                artist: track.artist.clone(),
+                bpm: *bpm,
+                bpm_confidence: None,
+                key: *key,
+                duration_ms: Some(track.duration_ms),
+                genre: None,
+                source: MetadataSource::Override.into(),
+            }),
+        };
+
+        Some(resolution)
+    }
+}
+
+fn find_pinned_entry<'a>(
+    metadata_index: &'a MetadataIndex,
+    name: &str,
+    artist: &str,
+    duration_ms: Option<u32>,
+) -> Option<&'a MetadataEntry> {
+    let artist_norm = normalize(artist);
+
+    metadata_index
+        .by_title
+        .get(&normalize(name))?
+        .iter()
+        .filter(|entry| normalize(&entry.artist) == artist_norm)
+        .filter(|entry| duration_ms.is_none() || entry.duration_ms == duration_ms)
+        .max_by_key(|entry| entry.source.priority())
+}
+
+pub(crate) fn load_overrides(path: &str) -> Result<MatchOverrides> {
+    let content = fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+    MatchOverrides::from_json(&content).with_context(|| format!("failed to parse {path}"))
+}
+
+pub(crate) fn save_overrides(path: &str, overrides: &MatchOverrides) -> Result<()> {
+    fs::write(path, overrides.to_json()?).with_context(|| format!("failed to write {path}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_metadata_index;
+
+    fn track(id: &str, name: &str, artist: &str) -> TrackInfo {
+        TrackInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            artist: artist.to_string(),
+            key: None,
+            tempo: None,
+            duration_ms: 248_000,
+        }
+    }
+
+    fn entry(name: &str, artist: &str, bpm: f32, duration_ms: Option<u32>) -> MetadataEntry {
+        MetadataEntry {
+            name: name.to_string(),
+            artist: artist.to_string(),
+            bpm,
+            bpm_confidence: None,
+            key: Key::parse("10B").unwrap(),
+            duration_ms,
+            genre: None,
+            source: MetadataSource::LocalDb.into(),
+        }
+    }
+
+    #[test]
+    fn from_json_reads_all_actions_and_keys() {
+        let overrides = MatchOverrides::from_json(
+            r#"[
+                {"spotify_id": "abc", "action": "ignore"},
+                {"track": "FISHER - Losing It", "action": "values", "bpm": 125, "key_camelot": "10B"},
+                {"track": "Beach House - Space Song", "action": "entry", "name": "Space Song", "artist": "Beach House"}
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(overrides.len(), 3);
+        assert_eq!(overrides.by_spotify_id["abc"], OverrideAction::Ignore);
+        assert!(overrides.by_track.contains_key("fisher - losing it"));
+    }
+
+    #[test]
+    fn from_json_rejects_rules_without_a_single_key() {
+        let err = MatchOverrides::from_json(r#"[{"action": "ignore"}]"#).unwrap_err();
+        assert!(err.to_string().contains("exactly one"));
+
+        let err = MatchOverrides::from_json(r#"[{"track": "no separator", "action": "ignore"}]"#)
+            .unwrap_err();
+        assert!(err.to_string().contains("Artist - Title"));
+    }
+
+    #[test]
+    fn from_json_rejects_duplicates_and_invalid_values() {
+        let err = MatchOverrides::from_json(
+            r#"[
+                {"spotify_id": "abc", "action": "ignore"},
+                {"spotify_id": " abc ", "action": "values", "bpm": 125, "key_camelot": "10B"}
+            ]"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("override #1: an earlier override"));
+
+        let err = MatchOverrides::from_json(
+            r#"[
+                {"track": "FISHER - Losing It", "action": "ignore"},
+                {"track": "fisher -  Losing  It", "action": "ignore"}
+            ]"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("override #1: an earlier override"));
+
+        let err = MatchOverrides::from_json(
+            r#"[{"spotify_id": "abc", "action": "values", "bpm": 125, "key_camelot": "13A"}]"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("'13A' is not a Camelot"));
+
+        let err = MatchOverrides::from_json(
+            r#"[{"spotify_id": "abc", "action": "values", "bpm": 0, "key_camelot": "8A"}]"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("BPM 0 is outside"));
+
+        let overrides = MatchOverrides::from_json(
+            r#"[{"spotify_id": "abc", "action": "values", "bpm": 125, "key_camelot": "Am"}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            overrides.by_spotify_id["abc"],
+            OverrideAction::Values {
+                bpm: 125.0,
+                key: Key::parse("8A").unwrap()
+            }
+        );
+    }
+
+    #[test]
+    fn resolve_prefers_spotify_id_and_pins_entries_by_duration() {
+        let metadata_index = build_metadata_index(vec![
+            entry("Losing It", "FISHER", 125.0, Some(248_000)),
+            entry("Losing It", "FISHER", 124.0, Some(370_000)),
+        ]);
+        let overrides = MatchOverrides::from_json(
+            r#"[
+                {"spotify_id": "pinned", "action": "entry", "name": "Losing It", "artist": "fisher", "duration_ms": 370000},
+                {"track": "FISHER - Losing It", "action": "ignore"}
+            ]"#,
+        )
+        .unwrap();
+
+        let pinned = overrides.resolve(&track("pinned", "Losing It", "FISHER"), &metadata_index);
+        match pinned {
+            Some(OverrideResolution::Matched(entry)) => assert_eq!(entry.bpm, 124.0),
+            other => panic!("unexpected resolution: {other:?}"),
+        }
+
+        let ignored = overrides.resolve(&track("other", "Losing It", "FISHER"), &metadata_index);
+        assert_eq!(ignored, Some(OverrideResolution::Ignore));
+
+        let untouched = overrides.resolve(&track("x", "Dior", "Pop Smoke"), &metadata_index);
+        assert_eq!(untouched, None);
+    }
+
+    #[test]
+    fn record_replaces_existing_rule_and_round_trips() {
+        let mut overrides =
+            MatchOverrides::from_json(r#"[{"spotify_id": "abc", "action": "ignore"}]"#).unwrap();
+        overrides.record(
+            "abc",
+            OverrideAction::Values {
+                bpm: 125.0,
+                key: Key::parse("10B").unwrap(),
+            },
+        );
+
+        let reloaded = MatchOverrides::from_json(&overrides.to_json().unwrap()).unwrap();
+        assert_eq!(reloaded.len(), 1);
+        assert_eq!(reloaded, overrides);
+    }
+
+    #[test]
+    fn record_replaces_rules_whose_spotify_id_differs_only_by_whitespace() {
+        let mut overrides =
+            MatchOverrides::from_json(r#"[{"spotify_id": " abc ", "action": "ignore"}]"#).unwrap();
+        overrides.record("abc", OverrideAction::Ignore);
+        overrides.record(" abc\n", OverrideAction::Ignore);
+
+        assert_eq!(overrides.rules.len(), 1);
+        assert_eq!(overrides.rules[0].spotify_id.as_deref(), Some("abc"));
+        MatchOverrides::from_json(&overrides.to_json().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,161 @@
+use crate::harmonic::{HarmonicRules, Move};
+use crate::key::Key;
+
+/// Beyond two semitones, about 12% of tempo, the tempo change itself is
+/// too obvious to be worth suggesting.
+pub(crate) const MAX_SUGGESTED_SEMITONES: i32 = 2;
+
+/// The widest tempo change, as a fraction, a DJ rides without key lock.
+/// Tracks further from the target, after half- or double-time folding,
+/// are assumed to be played at their own tempo.
+pub(crate) const MAX_PITCH_RANGE: f32 = 0.08;
+
+/// How `sort` treats tracks played without key lock, where changing the
+/// tempo also changes the key.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct PitchOptions {
+    /// Check compatibility with the key each track sounds in at the
+    /// target tempo rather than its written key.
+    pub(crate) shift: bool,
+    /// The tempo the set is mixed at; the median BPM of the matched
+    /// tracks when not given.
+    pub(crate) target_bpm: Option<f32>,
+    /// Suggest a pitch adjustment for each clashing transition.
+    pub(crate) suggest: bool,
+}
+
+/// Semitones a track at `bpm` moves when played at `target_bpm` without key
+/// lock, to the nearest semitone. Speeding up by about 6% is one semitone.
+///
+/// The tempo ratio is first folded to the nearest octave, so a 64 BPM track
+/// in a 120 BPM set is mixed at double time (128 down to 120, one semitone
+/// down) rather than sped up by nearly 90%.
+///
+/// `None` when even the folded change is beyond [`MAX_PITCH_RANGE`], as for
+/// an 85 BPM track in a 128 BPM set (170 down to 128 is a quarter slower).
+pub(crate) fn semitone_shift(bpm: f32, target_bpm: f32) -> Option<i32> {
+    let octaves = (target_bpm / bpm).log2();
+    let ratio = 2f32.powf(octaves - octaves.round());
+    ((ratio - 1.0).abs() <= MAX_PITCH_RANGE).then(|| (12.0 * ratio.log2()).round() as i32)
+}
+
+/// The key a track at `bpm` sounds in when played at `target_bpm`; its
+/// written key when [`semitone_shift`] leaves it unshifted.
+pub(crate) fn effective_key(key: Key, bpm: f32, target_bpm: f32) -> Key {
+    semitone_shift(bpm, target_bpm).map_or(key, |semitones| key.transpose(semitones))
+}
+
+/// Tempo change, in percent, that shifts the pitch by `semitones`.
+pub(crate) fn tempo_change_percent(semitones: i32) -> f32 {
+    (2f32.powf(semitones as f32 / 12.0) - 1.0) * 100.0
+}
+
+pub(crate) fn median_bpm(mut bpms: Vec<f32>) -> Option<f32> {
+    if bpms.is_empty() {
+        return None;
+    }
+    bpms.sort_by(f32::total_cmp);
+    let middle = bpms.len() / 2;
+    Some(if bpms.len().is_multiple_of(2) {
+        (bpms[middle - 1] + bpms[middle]) / 2.0
+    } else {
+        bpms[middle]
+    })
+}
+
+/// A pitch adjustment of the incoming track that turns a clash into an
+/// allowed move.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PitchSuggestion {
+    pub(crate) semitones: i32,
+    pub(crate) key: Key,
+    pub(crate) harmonic_move: Move,
+}
+
+/// The smallest nudge of `to`, up to [`MAX_SUGGESTED_SEMITONES`] either way, after which
+/// `from` mixes into it. Between nudges of the same size the one reaching
+/// the higher-weighted move wins, then the upward one.
+pub(crate) fn suggest_pitch(from: Key, to: Key, rules: &HarmonicRules) -> Option<PitchSuggestion> {
+    (1..=MAX_SUGGESTED_SEMITONES).find_map(|size| {
+        [size, -size]
+            .into_iter()
+            .filter_map(|semitones| {
+                let key = to.transpose(semitones);
+                let (harmonic_move, weight) = rules.score(from, key)?;
+                Some((
+                    weight,
+                    PitchSuggestion {
+                        semitones,
+                        key,
+                        harmonic_move,
+                    },
+                ))
+            })
+            // `max_by` keeps the last of equals, so try downward first.
+            .rev()
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+            .map(|(_, suggestion)| suggestion)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(raw: &str) -> Key {
+        Key::parse(raw).unwrap()
+    }
+
+    #[test]
+    fn effective_key_follows_the_tempo_change() {
+        assert_eq!(semitone_shift(120.0, 127.2), Some(1));
+        assert_eq!(semitone_shift(128.0, 120.0), Some(-1));
+        assert_eq!(semitone_shift(124.0, 126.0), Some(0));
+        // A minor sped up a semitone is B-flat minor.
+        assert_eq!(effective_key(key("8A"), 120.0, 127.2), key("3A"));
+        assert!((tempo_change_percent(1) - 5.95).abs() < 0.01);
+        assert!((tempo_change_percent(-2) + 10.91).abs() < 0.01);
+
+        assert_eq!(median_bpm(vec![128.0, 120.0, 124.0]), Some(124.0));
+        assert_eq!(median_bpm(vec![128.0, 120.0]), Some(124.0));
+        assert_eq!(median_bpm(Vec::new()), None);
+    }
+
+    #[test]
+    fn semitone_shift_folds_half_and_double_time_and_limits_the_range() {
+        // 85 BPM mixed at double time is 170 down to 128, well beyond 8%.
+        assert_eq!(semitone_shift(85.0, 128.0), None);
+        assert_eq!(effective_key(key("8A"), 85.0, 128.0), key("8A"));
+        // Half and double time fold to the same small change.
+        assert_eq!(semitone_shift(64.0, 120.0), Some(-1));
+        assert_eq!(semitone_shift(256.0, 135.0), Some(1));
+        assert_eq!(semitone_shift(87.0, 174.0), Some(0));
+        // 110 to 124 BPM is nearly 13% faster.
+        assert_eq!(semitone_shift(110.0, 124.0), None);
+        assert_eq!(semitone_shift(120.0, 129.0), Some(1));
+        assert_eq!(semitone_shift(120.0, 130.0), None);
+    }
+
+    #[test]
+    fn suggest_pitch_prefers_the_smallest_heaviest_nudge() {
+        let rules = HarmonicRules::default();
+        // 8A to 4A clashes; a semitone down reaches 9A.
+        assert_eq!(
+            suggest_pitch(key("8A"), key("4A"), &rules),
+            Some(PitchSuggestion {
+                semitones: -1,
+                key: key("9A"),
+                harmonic_move: Move::Adjacent,
+            })
+        );
+        // 8A to 6A: two semitones up is the same key, down is 4A.
+        assert_eq!(
+            suggest_pitch(key("8A"), key("6A"), &rules).map(|s| (s.semitones, s.harmonic_move)),
+            Some((2, Move::SameKey))
+        );
+        assert_eq!(
+            suggest_pitch(key("8A"), key("2B"), &rules).map(|s| s.semitones),
+            None
+        );
+    }
+}
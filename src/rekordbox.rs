@@ -0,0 +1,132 @@
+use anyhow::{Context, Result};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::fs;
+
+use crate::key::Key;
+use crate::library::{LibraryImport, LibraryKey, LibraryTrack};
+use crate::MetadataSource;
+
+fn track_from_element(element: &BytesStart<'_>) -> Result<LibraryTrack> {
+    let mut track = LibraryTrack::default();
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        let value = attribute.unescape_value()?.into_owned();
+        match attribute.key.as_ref() {
+            b"Name" => track.name = value,
+            b"Artist" => track.artist = value,
+            b"AverageBpm" => track.bpm = value,
+            b"Tonality" => track.key = LibraryKey::read(&value, Key::parse),
+            // Whole seconds.
+            b"TotalTime" => {
+                track.duration_ms = value
+                    .trim()
+                    .parse::<u32>()
+                    .ok()
+                    .and_then(|seconds| seconds.checked_mul(1_000))
+            }
+            b"Genre" => track.genre = value,
+            _ => {}
+        }
+    }
+    Ok(track)
+}
+
+/// Reads every `TRACK` in the `COLLECTION` of a Rekordbox `collection.xml`.
+/// Playlist `TRACK` references are ignored.
+pub(crate) fn parse_rekordbox_xml(content: &str) -> Result<LibraryImport> {
+    let mut reader = Reader::from_str(content);
+    let mut import = LibraryImport::default();
+    let mut in_collection = false;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) if element.name().as_ref() == b"COLLECTION" => {
+                in_collection = true;
+            }
+            Event::End(element) if element.name().as_ref() == b"COLLECTION" => {
+                in_collection = false;
+            }
+            Event::Start(element) | Event::Empty(element)
+                if in_collection && element.name().as_ref() == b"TRACK" =>
+            {
+                import.push(track_from_element(&element)?, MetadataSource::Rekordbox);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(import)
+}
+
+pub(crate) fn load_rekordbox_xml(path: &str) -> Result<LibraryImport> {
+    let content = fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+    parse_rekordbox_xml(&content)
+        .with_context(|| format!("failed to parse Rekordbox XML at {path}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLLECTION: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<DJ_PLAYLISTS Version="1.0.0">
+  <PRODUCT Name="rekordbox" Version="6.8.5" Company="AlphaTheta"/>
+  <COLLECTION Entries="5">
+    <TRACK TrackID="1" Name="Losing It" Artist="FISHER" Genre="Tech House" TotalTime="248" AverageBpm="125.00" Tonality="10B">
+      <TEMPO Inizio="0.025" Bpm="125.00" Metro="4/4" Battito="1"/>
+    </TRACK>
+    <TRACK TrackID="2" Name="Glue" Artist="Bicep" Genre="" TotalTime="269" AverageBpm="129.98" Tonality="Am"/>
+    <TRACK TrackID="3" Name="Rock &amp; Roll" Artist="Unknown" TotalTime="200" AverageBpm="0.00" Tonality="Am"/>
+    <TRACK TrackID="4" Name="No Key" Artist="Unknown" TotalTime="200" AverageBpm="120.00" Tonality=""/>
+    <TRACK TrackID="5" Name="Odd Key" Artist="Unknown" TotalTime="200" AverageBpm="120.00" Tonality="X#"/>
+  </COLLECTION>
+  <PLAYLISTS>
+    <NODE Type="0" Name="ROOT" Count="1">
+      <NODE Name="Set" Type="1" KeyType="0" Entries="1">
+        <TRACK Key="1"/>
+      </NODE>
+    </NODE>
+  </PLAYLISTS>
+</DJ_PLAYLISTS>
+"#;
+
+    #[test]
+    fn parse_rekordbox_xml_reads_collection_tracks() {
+        let import = parse_rekordbox_xml(COLLECTION).unwrap();
+
+        let rows: Vec<String> = import
+            .entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{} | {} | {} | {:?} | {:?}",
+                    entry.name,
+                    entry.bpm,
+                    entry.key.camelot(),
+                    entry.duration_ms,
+                    entry.genre
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                "Losing It | 125 | 10B | Some(248000) | Some(\"Tech House\")",
+                "Glue | 129.98 | 8A | Some(269000) | None",
+            ]
+        );
+        assert_eq!(import.entries[0].source.kind, MetadataSource::Rekordbox);
+        assert_eq!(
+            (import.missing_bpm, import.missing_key, import.invalid_key),
+            (1, 1, 1)
+        );
+
+        let overlong = parse_rekordbox_xml(
+            &COLLECTION.replace(r#"TotalTime="269""#, r#"TotalTime="4294968""#),
+        )
+        .unwrap();
+        assert_eq!(overlong.entries[1].duration_ms, None);
+    }
+}
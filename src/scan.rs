@@ -0,0 +1,317 @@
+use anyhow::{Context, Result};
+use lofty::prelude::*;
+use lofty::tag::Tag;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::analysis::{decode_audio, describe_key, describe_tempo};
+use crate::cli::PromoteMode;
+use crate::db::promote_entries;
+use crate::key::{Key, Notation};
+use crate::key_detection::{estimate_key, KeyEstimate};
+use crate::library::{LibraryImport, LibraryKey, LibraryTrack};
+use crate::tempo::{estimate_tempo, TempoEstimate};
+use crate::MetadataSource;
+
+/// Extensions read by `scan`, compared case-insensitively.
+const AUDIO_EXTENSIONS: [&str; 7] = ["mp3", "flac", "wav", "ogg", "opus", "m4a", "mp4"];
+
+/// Every audio file under `folder`, sorted by path. Symlinked folders are
+/// not followed.
+pub(crate) fn audio_files(folder: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![folder.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries =
+            fs::read_dir(&dir).with_context(|| format!("failed to read {}", dir.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+            } else if path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+            {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Reads a key tag in Camelot (`8A`, `08A`), Open Key (`1m`) or musical
+/// (`Am`, `A minor`) notation. Tools that write two notations at once, such
+/// as `8A/Am` or `8A - Am`, are read from whichever part parses.
+fn tag_key(raw: &str) -> Option<Key> {
+    Key::parse(raw).or_else(|| {
+        raw.split(['/', '|', ',', '-'])
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .find_map(Key::parse)
+    })
+}
+
+/// Builds a track from `tag`. The title falls back to the file name.
+fn track_from_tag(tag: Option<&Tag>, duration_ms: u32, path: &Path) -> LibraryTrack {
+    let text = |key: ItemKey| {
+        tag.and_then(|tag| tag.get_string(key))
+            .unwrap_or_default()
+            .trim()
+            .to_string()
+    };
+    let title = text(ItemKey::TrackTitle);
+    let bpm = Some(text(ItemKey::Bpm))
+        .filter(|bpm| !bpm.is_empty())
+        .unwrap_or_else(|| text(ItemKey::IntegerBpm));
+
+    LibraryTrack {
+        name: if title.is_empty() {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        } else {
+            title
+        },
+        artist: text(ItemKey::TrackArtist),
+        bpm,
+        bpm_confidence: None,
+        key: LibraryKey::read(&text(ItemKey::InitialKey), tag_key),
+        duration_ms: Some(duration_ms),
+        genre: text(ItemKey::Genre),
+    }
+}
+
+/// What `--analyze` estimated for a tag a file was missing; `None` inside
+/// when the audio gave no answer.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Estimate {
+    Tempo(Option<TempoEstimate>),
+    Key(Option<KeyEstimate>),
+}
+
+impl Estimate {
+    fn describe(&self, notation: Notation) -> String {
+        match self {
+            Self::Tempo(tempo) => describe_tempo(tempo.as_ref()),
+            Self::Key(key) => describe_key(key.as_ref(), notation),
+        }
+    }
+}
+
+/// Fills in a missing BPM or key from the decoded audio, returning what was
+/// estimated, or `None` when the tags were complete.
+fn analyze_missing_fields(track: &mut LibraryTrack, path: &Path) -> Result<Option<Vec<Estimate>>> {
+    let needs_bpm = !track.bpm.trim().parse::<f32>().is_ok_and(|bpm| bpm > 0.0);
+    let needs_key = track.key == LibraryKey::Missing;
+    if !needs_bpm && !needs_key {
+        return Ok(None);
+    }
+
+    let audio = decode_audio(path)?;
+    let mut found = Vec::new();
+    if needs_bpm {
+        let estimate = estimate_tempo(&audio);
+        if let Some(estimate) = &estimate {
+            track.bpm = format!("{:.2}", estimate.bpm);
+            track.bpm_confidence = Some(estimate.confidence);
+        }
+        found.push(Estimate::Tempo(estimate));
+    }
+    if needs_key {
+        let estimate = estimate_key(&audio);
+        if let Some(estimate) = &estimate {
+            track.key = LibraryKey::Parsed(estimate.key);
+        }
+        found.push(Estimate::Key(estimate));
+    }
+    Ok(Some(found))
+}
+
+/// The tracks read from a folder, and the files that could not be parsed.
+#[derive(Debug, Default)]
+pub(crate) struct ScanReport {
+    pub(crate) files: usize,
+    pub(crate) import: LibraryImport,
+    pub(crate) unreadable: Vec<(PathBuf, String)>,
+    /// Files analysed by `--analyze`, with what was estimated.
+    pub(crate) analyzed: Vec<(PathBuf, Vec<Estimate>)>,
+    /// Files whose tags were read but whose audio `--analyze` could not
+    /// decode; their tags are still imported.
+    pub(crate) analysis_failed: Vec<(PathBuf, String)>,
+}
+
+/// Reads the tags of every audio file under `folder`. With `analyze`, files
+/// without a BPM or key tag are decoded and the missing values estimated.
+pub(crate) fn scan_folder(folder: &str, kind: MetadataSource, analyze: bool) -> Result<ScanReport> {
+    let files = audio_files(Path::new(folder))?;
+    let mut report = ScanReport {
+        files: files.len(),
+        ..ScanReport::default()
+    };
+    for path in files {
+        match lofty::read_from_path(&path) {
+            Ok(tagged) => {
+                let tag = tagged.primary_tag().or_else(|| tagged.first_tag());
+                let duration_ms = tagged.properties().duration().as_millis();
+                let mut track =
+                    track_from_tag(tag, u32::try_from(duration_ms).unwrap_or(u32::MAX), &path);
+                if analyze {
+                    match analyze_missing_fields(&mut track, &path) {
+                        Ok(Some(found)) => report.analyzed.push((path, found)),
+                        Ok(None) => {}
+                        Err(err) => report.analysis_failed.push((path, format!("{err:#}"))),
+                    }
+                }
+                report.import.push(track, kind);
+            }
+            Err(err) => report
+                .unreadable
+                .push((path, format!("{:#}", anyhow::Error::new(err)))),
+        }
+    }
+    Ok(report)
+}
+
+/// `scan`: reads the tags under `folder` and merges them into the database
+/// like `db promote`.
+pub(crate) fn run_scan(
+    db_path: &str,
+    folder: &str,
+    mode: PromoteMode,
+    analyze: bool,
+    notation: Notation,
+) -> Result<()> {
+    let report = scan_folder(folder, MetadataSource::FileTags, analyze)?;
+    for (path, found) in &report.analyzed {
+        let found: Vec<String> = found
+            .iter()
+            .map(|estimate| estimate.describe(notation))
+            .collect();
+        println!("[ANALYZED] {}: {}", path.display(), found.join(", "));
+    }
+    for (path, reason) in &report.analysis_failed {
+        println!("[ANALYSIS FAILED] {} ({reason})", path.display());
+    }
+    for (path, reason) in &report.unreadable {
+        println!("[UNREADABLE] {} ({reason})", path.display());
+    }
+    println!(
+        "Scanned {} audio files in {folder}: {} tagged with BPM and key, {} unreadable.",
+        report.files,
+        report.import.entries.len(),
+        report.unreadable.len()
+    );
+    if !report.analysis_failed.is_empty() {
+        println!(
+            "Could not analyse the audio of {} files; their tags were used as they are.",
+            report.analysis_failed.len()
+        );
+    }
+    if report.import.skipped() > 0 {
+        println!(
+            "Skipped {} files without usable tags ({}).",
+            report.import.skipped(),
+            report.import.skip_summary()
+        );
+    }
+    promote_entries(db_path, report.import.entries, mode, notation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lofty::tag::TagType;
+
+    #[test]
+    fn tag_key_reads_common_notations() {
+        let keys = [
+            "8A", "08a", "Am", "A minor", "1m", "3d", "F#m/11A", "10B - D", "o",
+        ];
+        let camelot: Vec<Option<String>> = keys
+            .iter()
+            .map(|key| tag_key(key).map(Key::camelot))
+            .collect();
+        assert_eq!(
+            camelot,
+            vec![
+                Some("8A".to_string()),
+                Some("8A".to_string()),
+                Some("8A".to_string()),
+                Some("8A".to_string()),
+                Some("8A".to_string()),
+                Some("10B".to_string()),
+                Some("11A".to_string()),
+                Some("10B".to_string()),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn track_from_tag_reads_vorbis_and_id3_style_fields() {
+        let mut vorbis = Tag::new(TagType::VorbisComments);
+        vorbis.insert_text(ItemKey::TrackTitle, "Losing It".to_string());
+        vorbis.insert_text(ItemKey::TrackArtist, "FISHER".to_string());
+        vorbis.insert_text(ItemKey::Bpm, "125.00".to_string());
+        vorbis.insert_text(ItemKey::InitialKey, "D".to_string());
+        let entry = track_from_tag(Some(&vorbis), 248_071, Path::new("a.flac"))
+            .into_entry(MetadataSource::FileTags)
+            .unwrap();
+        assert_eq!(
+            (entry.name.as_str(), entry.bpm, entry.key.camelot()),
+            ("Losing It", 125.0, "10B".to_string())
+        );
+        assert_eq!(entry.duration_ms, Some(248_071));
+
+        let mut id3 = Tag::new(TagType::Id3v2);
+        id3.insert_text(ItemKey::IntegerBpm, "130".to_string());
+        id3.insert_text(ItemKey::InitialKey, "Am".to_string());
+        let track = track_from_tag(Some(&id3), 269_000, Path::new("music/Bicep - Glue.mp3"));
+        assert_eq!(
+            (track.name.as_str(), track.bpm.as_str()),
+            ("Bicep - Glue", "130")
+        );
+        assert_eq!(track.key, LibraryKey::read("8A", Key::parse));
+    }
+
+    #[test]
+    fn scan_folder_walks_subfolders_and_reports_unreadable_files() {
+        let dir = std::env::temp_dir().join(format!("mix_sorter_scan_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("Albums/Nested")).unwrap();
+        fs::write(dir.join("Albums/Nested/broken.MP3"), b"not audio").unwrap();
+        fs::write(dir.join("cover.jpg"), b"not audio either").unwrap();
+
+        let report = scan_folder(dir.to_str().unwrap(), MetadataSource::FileTags, true).unwrap();
+        assert_eq!(report.files, 1);
+        assert!(report.import.entries.is_empty());
+        assert_eq!(report.unreadable.len(), 1);
+        assert!(report.unreadable[0].0.ends_with("broken.MP3"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn scan_folder_keeps_analysis_failures_apart_from_unreadable_files() {
+        let dir = std::env::temp_dir().join(format!(
+            "mix_sorter_scan_analysis_test_{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        // A 16-bit PCM WAV whose one data byte is less than a sample to analyse.
+        let mut wav = b"RIFF\x26\0\0\0WAVEfmt \x10\0\0\0\x01\0\x01\0".to_vec();
+        wav.extend(44_100u32.to_le_bytes());
+        wav.extend(88_200u32.to_le_bytes());
+        wav.extend(b"\x02\0\x10\0data\x01\0\0\0\0\0");
+        fs::write(dir.join("untagged.wav"), wav).unwrap();
+
+        let report = scan_folder(dir.to_str().unwrap(), MetadataSource::FileTags, true).unwrap();
+        assert_eq!(report.files, 1);
+        assert!(report.unreadable.is_empty());
+        assert_eq!(report.analysis_failed.len(), 1);
+        assert_eq!(report.import.skipped(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,319 @@
+use anyhow::{bail, Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::key::Key;
+use crate::library::{LibraryImport, LibraryKey, LibraryTrack};
+use crate::MetadataSource;
+
+/// One tag-length-value record of a Serato `database V2` or `.crate` file.
+struct Field<'a> {
+    tag: &'a [u8],
+    data: &'a [u8],
+}
+
+/// Splits `data` into records: a 4-byte ASCII tag, a big-endian `u32`
+/// length, then that many bytes of payload.
+fn fields(mut data: &[u8]) -> Result<Vec<Field<'_>>> {
+    let mut fields = Vec::new();
+    while !data.is_empty() {
+        if data.len() < 8 {
+            bail!("truncated field header");
+        }
+        let (tag, rest) = data.split_at(4);
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let rest = &rest[4..];
+        if rest.len() < length {
+            bail!(
+                "field {} claims {length} bytes but only {} remain",
+                String::from_utf8_lossy(tag),
+                rest.len()
+            );
+        }
+        let (payload, rest) = rest.split_at(length);
+        fields.push(Field { tag, data: payload });
+        data = rest;
+    }
+    Ok(fields)
+}
+
+/// Serato stores text as UTF-16 big-endian.
+fn utf16_text(data: &[u8]) -> String {
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16_lossy(&units)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+/// Parses Serato's `mm:ss.cc` track length.
+fn parse_length(raw: &str) -> Option<u32> {
+    let (minutes, seconds) = raw.trim().split_once(':')?;
+    let minutes: u32 = minutes.parse().ok()?;
+    let seconds: f32 = seconds.parse().ok()?;
+    if !(0.0..60.0).contains(&seconds) {
+        return None;
+    }
+    minutes
+        .checked_mul(60_000)?
+        .checked_add((seconds * 1_000.0).round() as u32)
+}
+
+/// Every `otrk` record of a `database V2` file, with its file path.
+fn database_tracks(data: &[u8]) -> Result<Vec<(String, LibraryTrack)>> {
+    let mut tracks = Vec::new();
+    for record in fields(data)? {
+        if record.tag != b"otrk" {
+            continue;
+        }
+        let mut path = String::new();
+        let mut track = LibraryTrack::default();
+        for field in fields(record.data)? {
+            match field.tag {
+                b"pfil" => path = utf16_text(field.data),
+                b"tsng" => track.name = utf16_text(field.data),
+                b"tart" => track.artist = utf16_text(field.data),
+                b"tbpm" => track.bpm = utf16_text(field.data),
+                b"tkey" => track.key = LibraryKey::read(&utf16_text(field.data), Key::parse),
+                b"tgen" => track.genre = utf16_text(field.data),
+                b"tlen" => track.duration_ms = parse_length(&utf16_text(field.data)),
+                _ => {}
+            }
+        }
+        tracks.push((path, track));
+    }
+    Ok(tracks)
+}
+
+/// The track paths listed in a `.crate` file.
+fn crate_paths(data: &[u8]) -> Result<HashSet<String>> {
+    let mut paths = HashSet::new();
+    for record in fields(data)? {
+        if record.tag != b"otrk" {
+            continue;
+        }
+        for field in fields(record.data)? {
+            if field.tag == b"ptrk" {
+                paths.insert(utf16_text(field.data));
+            }
+        }
+    }
+    Ok(paths)
+}
+
+fn import_tracks(tracks: impl IntoIterator<Item = LibraryTrack>) -> LibraryImport {
+    let mut import = LibraryImport::default();
+    for track in tracks {
+        import.push(track, MetadataSource::Serato);
+    }
+    import
+}
+
+pub(crate) fn parse_serato_database(data: &[u8]) -> Result<LibraryImport> {
+    Ok(import_tracks(
+        database_tracks(data)?.into_iter().map(|(_, track)| track),
+    ))
+}
+
+/// Imports the tracks of `database` that are listed in `crate_data`.
+pub(crate) fn parse_serato_crate(database: &[u8], crate_data: &[u8]) -> Result<LibraryImport> {
+    let paths = crate_paths(crate_data)?;
+    Ok(import_tracks(
+        database_tracks(database)?
+            .into_iter()
+            .filter(|(path, _)| paths.contains(path))
+            .map(|(_, track)| track),
+    ))
+}
+
+/// Reads a CSV exported from Serato's History panel. The session summary
+/// row has no BPM and is counted as skipped.
+pub(crate) fn parse_serato_csv(content: &str) -> Result<LibraryImport> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let headers = reader.headers()?.clone();
+    let column = |names: &[&str]| {
+        headers.iter().position(|header| {
+            let header = header.trim().trim_start_matches('\u{feff}');
+            names.iter().any(|name| header.eq_ignore_ascii_case(name))
+        })
+    };
+    let (Some(name), Some(artist)) = (column(&["name", "title", "song"]), column(&["artist"]))
+    else {
+        bail!("missing name/artist columns");
+    };
+    let bpm = column(&["bpm"]).context("missing bpm column")?;
+    let key = column(&["key"]).context("missing key column")?;
+    let length = column(&["length"]);
+    let genre = column(&["genre"]);
+
+    let mut tracks = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let field = |index: Option<usize>| {
+            index
+                .and_then(|index| record.get(index))
+                .unwrap_or_default()
+                .to_string()
+        };
+        tracks.push(LibraryTrack {
+            name: field(Some(name)),
+            artist: field(Some(artist)),
+            bpm: field(Some(bpm)),
+            bpm_confidence: None,
+            key: LibraryKey::read(&field(Some(key)), Key::parse),
+            duration_ms: parse_length(&field(length)),
+            genre: field(genre),
+        });
+    }
+    Ok(import_tracks(tracks))
+}
+
+/// Loads a Serato source by extension: a history `.csv`, a `.crate` (read
+/// with the `database V2` two directories up, as in `_Serato_/Subcrates/`),
+/// or a `database V2` file.
+pub(crate) fn load_serato(path: &str) -> Result<LibraryImport> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    let read =
+        |path: &Path| fs::read(path).with_context(|| format!("failed to read {}", path.display()));
+
+    match extension.as_deref() {
+        Some("csv") => {
+            let content =
+                fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+            parse_serato_csv(&content)
+        }
+        Some("crate") => {
+            let database_path = Path::new(path)
+                .parent()
+                .and_then(Path::parent)
+                .map(|serato_dir| serato_dir.join("database V2"))
+                .with_context(|| format!("cannot locate the database V2 for {path}"))?;
+            parse_serato_crate(&read(&database_path)?, &read(Path::new(path))?)
+        }
+        _ => parse_serato_database(&read(Path::new(path))?),
+    }
+    .with_context(|| format!("failed to parse Serato library at {path}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::describe_entries;
+
+    fn field(tag: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = tag.as_bytes().to_vec();
+        bytes.extend((data.len() as u32).to_be_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    fn text(tag: &str, value: &str) -> Vec<u8> {
+        let data: Vec<u8> = value.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        field(tag, &data)
+    }
+
+    fn track(path: &str, fields: &[(&str, &str)]) -> Vec<u8> {
+        let mut data = text("pfil", path);
+        for (tag, value) in fields {
+            data.extend(text(tag, value));
+        }
+        field("otrk", &data)
+    }
+
+    fn database() -> Vec<u8> {
+        let mut data = text("vrsn", "2.0/Serato Scratch LIVE Database");
+        data.extend(track(
+            "Music/losing_it.mp3",
+            &[
+                ("tsng", "Losing It"),
+                ("tart", "FISHER"),
+                ("tbpm", "125.00"),
+                ("tkey", "10B"),
+                ("tlen", "04:08.00"),
+                ("tgen", "Tech House"),
+                ("uadd", "x"),
+            ],
+        ));
+        data.extend(track(
+            "Music/glue.mp3",
+            &[
+                ("tsng", "Glue"),
+                ("tart", "Bicep"),
+                ("tbpm", "130"),
+                ("tkey", "Am"),
+            ],
+        ));
+        data.extend(track(
+            "Music/new.mp3",
+            &[("tsng", "New"), ("tart", "Someone")],
+        ));
+        data
+    }
+
+    #[test]
+    fn parse_serato_database_reads_tracks_and_skips_unanalysed_ones() {
+        let import = parse_serato_database(&database()).unwrap();
+
+        assert_eq!(
+            describe_entries(&import),
+            vec![
+                "FISHER - Losing It | 125 | 10B | Some(248000) | Some(\"Tech House\")",
+                "Bicep - Glue | 130 | 8A | None | None",
+            ]
+        );
+        assert_eq!(import.entries[0].source.kind, MetadataSource::Serato);
+        assert_eq!(import.missing_bpm, 1);
+
+        let mut truncated = database();
+        truncated.truncate(truncated.len() - 3);
+        assert!(parse_serato_database(&truncated).is_err());
+    }
+
+    #[test]
+    fn parse_length_reads_minutes_and_rejects_overflow() {
+        assert_eq!(parse_length("04:08.50"), Some(248_500));
+        assert_eq!(parse_length("04:60.00"), None);
+        assert_eq!(parse_length("71583:00.00"), None);
+    }
+
+    #[test]
+    fn parse_serato_crate_keeps_only_listed_tracks() {
+        let mut crate_data = text("vrsn", "1.0/Serato ScratchLive Crate");
+        crate_data.extend(field("otrk", &text("ptrk", "Music/glue.mp3")));
+
+        let import = parse_serato_crate(&database(), &crate_data).unwrap();
+        let names: Vec<&str> = import
+            .entries
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["Glue"]);
+    }
+
+    #[test]
+    fn parse_serato_csv_reads_history_exports() {
+        let import = parse_serato_csv(
+            "name,artist,start time,end time,playtime,deck,bpm,key,genre\n\
+             Friday Session,,21:00:00,23:00:00,02:00:00,,,,\n\
+             Losing It,FISHER,21:00:00,21:04:00,00:04:00,1,125,10B,Tech House\n\
+             Glue,Bicep,21:04:00,21:08:00,00:04:00,2,130,F#m,\n",
+        )
+        .unwrap();
+
+        let keys: Vec<String> = import
+            .entries
+            .iter()
+            .map(|entry| entry.key.camelot())
+            .collect();
+        assert_eq!(keys, vec!["10B", "11A"]);
+        assert_eq!(import.missing_bpm, 1);
+    }
+}
@@ -0,0 +1,281 @@
+use anyhow::{bail, Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::cli::MetadataArgs;
+use crate::{local_db_path, MetadataSource, SourceLayer, SOURCES_PATH};
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SourceEntry {
+    name: String,
+    #[serde(rename = "type")]
+    kind: MetadataSource,
+    path: String,
+    #[serde(default)]
+    priority: Option<i32>,
+    /// CSV preset name or mapping file; `csv` sources only.
+    #[serde(default)]
+    mapping: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SourcesFile {
+    sources: Vec<SourceEntry>,
+}
+
+/// One metadata layer to load: the file it comes from and how it ranks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SourceSpec {
+    pub(crate) layer: SourceLayer,
+    pub(crate) path: String,
+    /// `--csv-mapping` value for `csv` layers; `None` detects a preset.
+    pub(crate) csv_mapping: Option<String>,
+}
+
+/// Parses a sources file. A layer without a `priority` ranks by list
+/// position, the first listed layer highest.
+pub(crate) fn parse_sources(content: &str) -> Result<Vec<SourceSpec>> {
+    let file: SourcesFile = serde_json::from_str(content)?;
+    let count = file.sources.len() as i32;
+    let mut names = HashSet::new();
+
+    file.sources
+        .into_iter()
+        .enumerate()
+        .map(|(position, entry)| {
+            let name = entry.name.trim().to_string();
+            if name.is_empty() {
+                bail!("source #{} has an empty name", position + 1);
+            }
+            if !names.insert(name.to_lowercase()) {
+                bail!("source name '{name}' is used more than once");
+            }
+            if entry.kind == MetadataSource::Override {
+                bail!("source '{name}' cannot have type override; use overrides.json");
+            }
+            if entry.mapping.is_some() && entry.kind != MetadataSource::Csv {
+                bail!("source '{name}' has a mapping, but only csv sources take one");
+            }
+            Ok(SourceSpec {
+                layer: SourceLayer {
+                    kind: entry.kind,
+                    name,
+                    priority: entry.priority.unwrap_or(count - position as i32),
+                    modified: None,
+                },
+                path: entry.path,
+                csv_mapping: entry.mapping,
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn load_sources(path: &str) -> Result<Vec<SourceSpec>> {
+    let content = fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+    parse_sources(&content).with_context(|| format!("failed to parse {path}"))
+}
+
+/// The layers `sort` and `explain` load, highest priority first. Layers come
+/// from the sources file when one is in use, otherwise from `--local-db`.
+/// Each overlay file given on the command line is added below every other
+/// layer.
+pub(crate) fn metadata_sources(metadata: &MetadataArgs) -> Result<Vec<SourceSpec>> {
+    let sources_path = match &metadata.sources_path {
+        Some(path) => Some(path.as_str()),
+        None => Path::new(SOURCES_PATH).exists().then_some(SOURCES_PATH),
+    };
+
+    let mut sources = match sources_path {
+        Some(path) => {
+            if metadata.local_db_path.is_some() {
+                bail!("--local-db cannot be combined with {path}; list the database as a layer there instead");
+            }
+            println!("Loading metadata sources from {path}...");
+            load_sources(path)?
+        }
+        None => vec![SourceSpec {
+            layer: MetadataSource::LocalDb.into(),
+            path: local_db_path(metadata).to_string(),
+            csv_mapping: None,
+        }],
+    };
+
+    let lowest = sources
+        .iter()
+        .map(|source| source.layer.priority)
+        .min()
+        .unwrap_or(0);
+    let overlay_priority = if sources_path.is_some() {
+        lowest - 1
+    } else {
+        MetadataSource::Exportify.default_priority()
+    };
+    sources.extend(overlay_sources(metadata, overlay_priority));
+
+    sources.sort_by_key(|source| std::cmp::Reverse(source.layer.priority));
+    Ok(sources)
+}
+
+/// `EXPORTIFY:liked` for `exports/liked.csv`. Files of one kind that share a
+/// stem are named by their whole path instead.
+fn overlay_name(kind: MetadataSource, path: &str, paths: &[String]) -> String {
+    let stem = |path: &str| {
+        Path::new(path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+    };
+    let own = stem(path);
+    let shared = paths
+        .iter()
+        .filter(|other| stem(other.as_str()) == own)
+        .count()
+        > 1;
+    match own {
+        Some(own) if !shared => format!("{}:{own}", kind.label()),
+        _ => format!("{}:{path}", kind.label()),
+    }
+}
+
+/// Layers given with `--exportify-csv`, `--rekordbox-xml`, `--traktor-nml`,
+/// `--serato`, `--engine-db`, `--virtualdj-xml` and `--csv`, all at
+/// `priority`, each named after its kind and file so that overlays which
+/// disagree are told apart.
+/// `--csv-mapping` applies to every `--csv` file.
+pub(crate) fn overlay_sources(metadata: &MetadataArgs, priority: i32) -> Vec<SourceSpec> {
+    let overlays = [
+        (MetadataSource::Exportify, &metadata.exportify_csv_paths),
+        (MetadataSource::Rekordbox, &metadata.rekordbox_xml_paths),
+        (MetadataSource::Traktor, &metadata.traktor_nml_paths),
+        (MetadataSource::Serato, &metadata.serato_paths),
+        (MetadataSource::EngineDj, &metadata.engine_db_paths),
+        (MetadataSource::VirtualDj, &metadata.virtualdj_xml_paths),
+        (MetadataSource::Csv, &metadata.csv_paths),
+    ];
+    overlays
+        .into_iter()
+        .flat_map(|(kind, paths)| {
+            paths.iter().map(move |path| SourceSpec {
+                layer: SourceLayer {
+                    name: overlay_name(kind, path, paths),
+                    priority,
+                    ..kind.into()
+                },
+                path: path.clone(),
+                csv_mapping: (kind == MetadataSource::Csv)
+                    .then(|| metadata.csv_mapping.clone())
+                    .flatten(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sources_defaults_priority_to_list_order() {
+        let sources = parse_sources(
+            r#"{"sources": [
+                {"name": "personal", "type": "local_db", "path": "mine.json"},
+                {"name": "team", "type": "local_db", "path": "team.sqlite", "priority": 10},
+                {"name": "exports", "type": "exportify", "path": "exports/a.csv"}
+            ]}"#,
+        )
+        .unwrap();
+
+        let layers: Vec<(&str, MetadataSource, i32)> = sources
+            .iter()
+            .map(|source| {
+                (
+                    source.layer.name.as_str(),
+                    source.layer.kind,
+                    source.layer.priority,
+                )
+            })
+            .collect();
+        assert_eq!(
+            layers,
+            vec![
+                ("personal", MetadataSource::LocalDb, 3),
+                ("team", MetadataSource::LocalDb, 10),
+                ("exports", MetadataSource::Exportify, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_sources_rejects_duplicate_names_override_layers_and_stray_mappings() {
+        let err = parse_sources(
+            r#"{"sources": [
+                {"name": "team", "type": "local_db", "path": "a.json"},
+                {"name": "Team", "type": "local_db", "path": "b.json"}
+            ]}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("used more than once"));
+
+        let err = parse_sources(
+            r#"{"sources": [{"name": "pins", "type": "override", "path": "o.json"}]}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("cannot have type override"));
+
+        let err = parse_sources(
+            r#"{"sources": [{"name": "rb", "type": "rekordbox", "path": "c.xml", "mapping": "tunebat"}]}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("only csv sources"));
+        let csv = parse_sources(
+            r#"{"sources": [{"name": "mik", "type": "csv", "path": "mik.csv", "mapping": "mixed-in-key"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(csv[0].csv_mapping.as_deref(), Some("mixed-in-key"));
+    }
+
+    #[test]
+    fn metadata_sources_ranks_csv_layers_below_configured_ones() {
+        let dir =
+            std::env::temp_dir().join(format!("mix_sorter_sources_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sources.json");
+        fs::write(
+            &path,
+            r#"{"sources": [
+                {"name": "team", "type": "local_db", "path": "team.json", "priority": 5},
+                {"name": "personal", "type": "local_db", "path": "mine.json", "priority": 20}
+            ]}"#,
+        )
+        .unwrap();
+
+        let metadata = MetadataArgs {
+            sources_path: Some(path.to_str().unwrap().to_string()),
+            exportify_csv_paths: vec!["exports/a.csv".to_string()],
+            ..MetadataArgs::default()
+        };
+        let layers: Vec<(String, i32)> = metadata_sources(&metadata)
+            .unwrap()
+            .into_iter()
+            .map(|source| (source.layer.name, source.layer.priority))
+            .collect();
+        assert_eq!(
+            layers,
+            vec![
+                ("personal".to_string(), 20),
+                ("team".to_string(), 5),
+                ("EXPORTIFY:a".to_string(), 4),
+            ]
+        );
+
+        let err = metadata_sources(&MetadataArgs {
+            local_db_path: Some("x.json".to_string()),
+            ..metadata
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("--local-db cannot be combined"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
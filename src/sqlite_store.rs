@@ -0,0 +1,469 @@
+use anyhow::{anyhow, bail, Context, Result};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Row};
+use serde_json::{Map, Value};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::db::{
+    load_local_db_file, normalize_isrc, save_local_db_file, LocalDbFile, Lookup, TrackStore,
+};
+use crate::{normalize, LocalTrackData};
+
+/// Stored in `PRAGMA user_version`; bump with a migration when the table changes.
+const SQLITE_SCHEMA_VERSION: i64 = 1;
+/// How long to wait for another writer before giving up.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const SCHEMA: &str = "
+CREATE TABLE tracks (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    artist TEXT NOT NULL,
+    bpm REAL NOT NULL,
+    key_camelot TEXT NOT NULL,
+    duration_ms INTEGER,
+    genre TEXT,
+    energy INTEGER,
+    tags TEXT NOT NULL DEFAULT '[]',
+    rating INTEGER,
+    isrc TEXT,
+    spotify_id TEXT,
+    notes TEXT,
+    bpm_confidence REAL,
+    verified INTEGER NOT NULL DEFAULT 0,
+    extra TEXT NOT NULL DEFAULT '{}',
+    name_norm TEXT NOT NULL,
+    artist_norm TEXT NOT NULL,
+    isrc_norm TEXT
+);
+CREATE INDEX tracks_by_title ON tracks (name_norm, artist_norm);
+CREATE INDEX tracks_by_artist ON tracks (artist_norm);
+CREATE INDEX tracks_by_isrc ON tracks (isrc_norm);
+CREATE INDEX tracks_by_spotify_id ON tracks (spotify_id);
+";
+
+const TRACK_COLUMNS: &str = "name, artist, bpm, key_camelot, duration_ms, genre, energy, tags, \
+     rating, isrc, spotify_id, notes, bpm_confidence, verified";
+
+/// Selects the row at a 0-based position in insertion order.
+const ROW_AT: &str = "(SELECT id FROM tracks ORDER BY id LIMIT 1 OFFSET ?1)";
+
+pub(crate) fn is_sqlite_path(path: &str) -> bool {
+    matches!(
+        Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase)
+            .as_deref(),
+        Some("sqlite" | "sqlite3" | "db")
+    )
+}
+
+/// Metadata pool in a SQLite file, with indexes on normalized title and
+/// artist, ISRC and Spotify ID. Edits run inside one transaction that is
+/// committed by [`TrackStore::save`] and rolled back if the store is dropped.
+pub(crate) struct SqliteStore {
+    connection: Connection,
+}
+
+fn track_from_row(row: &Row<'_>) -> rusqlite::Result<LocalTrackData> {
+    let tags_index = row.as_ref().column_index("tags")?;
+    let tags: String = row.get(tags_index)?;
+    let tags = serde_json::from_str(&tags).map_err(|error| {
+        rusqlite::Error::FromSqlConversionFailure(tags_index, Type::Text, Box::new(error))
+    })?;
+    Ok(LocalTrackData {
+        name: row.get("name")?,
+        artist: row.get("artist")?,
+        bpm: row.get::<_, f64>("bpm")? as f32,
+        key_camelot: row.get("key_camelot")?,
+        duration_ms: row.get("duration_ms")?,
+        genre: row.get("genre")?,
+        energy: row.get("energy")?,
+        tags,
+        rating: row.get("rating")?,
+        isrc: row.get("isrc")?,
+        spotify_id: row.get("spotify_id")?,
+        notes: row.get("notes")?,
+        bpm_confidence: row
+            .get::<_, Option<f64>>("bpm_confidence")?
+            .map(|c| c as f32),
+        verified: row.get("verified")?,
+    })
+}
+
+impl SqliteStore {
+    fn connect(path: &str, flags: OpenFlags) -> Result<Self> {
+        let connection = Connection::open_with_flags(path, flags)
+            .with_context(|| format!("failed to open SQLite store {path}"))?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        Ok(Self { connection })
+    }
+
+    /// Opens an existing store created by [`SqliteStore::create`].
+    pub(crate) fn open(path: &str) -> Result<Self> {
+        let store = Self::connect(path, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+        let version: i64 = store
+            .connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version != SQLITE_SCHEMA_VERSION {
+            bail!("{path} has SQLite schema version {version}, expected {SQLITE_SCHEMA_VERSION}");
+        }
+        Ok(store)
+    }
+
+    /// Opens an existing store and takes the write lock up front, so
+    /// concurrent editors queue instead of overwriting each other.
+    pub(crate) fn open_for_edit(path: &str) -> Result<Self> {
+        let store = Self::open(path)?;
+        store.connection.execute_batch("BEGIN IMMEDIATE")?;
+        Ok(store)
+    }
+
+    /// Creates a new, empty store in an open transaction.
+    pub(crate) fn create(path: &str) -> Result<Self> {
+        if Path::new(path).exists() {
+            bail!("{path} already exists; move it away first");
+        }
+        let store = Self::connect(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE,
+        )?;
+        store.connection.execute_batch("BEGIN IMMEDIATE")?;
+        store.connection.execute_batch(SCHEMA)?;
+        store
+            .connection
+            .pragma_update(None, "user_version", SQLITE_SCHEMA_VERSION)?;
+        Ok(store)
+    }
+
+    fn track_params(track: &LocalTrackData) -> Result<[rusqlite::types::Value; 17]> {
+        use rusqlite::types::Value as Sql;
+
+        let text = |value: &Option<String>| value.clone().map_or(Sql::Null, Sql::Text);
+        let integer = |value: Option<i64>| value.map_or(Sql::Null, Sql::Integer);
+        Ok([
+            Sql::Text(track.name.clone()),
+            Sql::Text(track.artist.clone()),
+            Sql::Real(f64::from(track.bpm)),
+            Sql::Text(track.key_camelot.clone()),
+            integer(track.duration_ms.map(i64::from)),
+            text(&track.genre),
+            integer(track.energy.map(i64::from)),
+            Sql::Text(serde_json::to_string(&track.tags)?),
+            integer(track.rating.map(i64::from)),
+            text(&track.isrc),
+            text(&track.spotify_id),
+            text(&track.notes),
+            track
+                .bpm_confidence
+                .map_or(Sql::Null, |confidence| Sql::Real(f64::from(confidence))),
+            Sql::Integer(i64::from(track.verified)),
+            Sql::Text(normalize(&track.name)),
+            Sql::Text(normalize(&track.artist)),
+            text(&track.isrc.as_deref().map(normalize_isrc)),
+        ])
+    }
+
+    fn insert_with_extra(
+        &mut self,
+        track: &LocalTrackData,
+        extra: &Map<String, Value>,
+    ) -> Result<()> {
+        let mut values = Self::track_params(track)?.to_vec();
+        values.push(rusqlite::types::Value::Text(serde_json::to_string(extra)?));
+        self.connection.execute(
+            &format!(
+                "INSERT INTO tracks ({TRACK_COLUMNS}, name_norm, artist_norm, isrc_norm, extra) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)"
+            ),
+            rusqlite::params_from_iter(values),
+        )?;
+        Ok(())
+    }
+
+    /// Every track with the keys it carried over from a JSON import.
+    pub(crate) fn tracks_with_extra(&self) -> Result<Vec<(LocalTrackData, Map<String, Value>)>> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT {TRACK_COLUMNS}, extra FROM tracks ORDER BY id"
+        ))?;
+        let rows = statement.query_map([], |row| {
+            let extra: String = row.get("extra")?;
+            Ok((track_from_row(row)?, extra))
+        })?;
+        rows.map(|row| {
+            let (track, extra) = row?;
+            Ok((track, serde_json::from_str(&extra)?))
+        })
+        .collect()
+    }
+
+    /// Imports `tracks` in one transaction; nothing is written if any fails.
+    pub(crate) fn import(&mut self, tracks: &[(LocalTrackData, Map<String, Value>)]) -> Result<()> {
+        for (position, (track, extra)) in tracks.iter().enumerate() {
+            self.insert_with_extra(track, extra)
+                .with_context(|| format!("failed to import entry #{}", position + 1))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SqliteStore {
+    fn drop(&mut self) {
+        if !self.connection.is_autocommit() {
+            let _ = self.connection.execute_batch("ROLLBACK");
+        }
+    }
+}
+
+impl TrackStore for SqliteStore {
+    fn parsed_tracks(&self) -> Result<Vec<Result<LocalTrackData>>> {
+        let mut statement = self
+            .connection
+            .prepare(&format!("SELECT {TRACK_COLUMNS} FROM tracks ORDER BY id"))?;
+        let rows = statement.query_map([], track_from_row)?;
+        Ok(rows.map(|row| row.map_err(Into::into)).collect())
+    }
+
+    fn len(&self) -> Result<usize> {
+        let count: i64 = self
+            .connection
+            .query_row("SELECT COUNT(*) FROM tracks", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    fn track_at(&self, position: usize) -> Result<LocalTrackData> {
+        self.connection
+            .query_row(
+                &format!("SELECT {TRACK_COLUMNS} FROM tracks WHERE id = {ROW_AT}"),
+                params![position as i64],
+                track_from_row,
+            )
+            .optional()?
+            .ok_or_else(|| anyhow!("no entry #{}", position + 1))
+    }
+
+    fn insert(&mut self, track: &LocalTrackData) -> Result<()> {
+        self.insert_with_extra(track, &Map::new())
+    }
+
+    fn update(&mut self, position: usize, track: &LocalTrackData) -> Result<()> {
+        let mut values = Self::track_params(track)?.to_vec();
+        values.push(rusqlite::types::Value::Integer(position as i64));
+        self.connection.execute(
+            "UPDATE tracks SET name = ?1, artist = ?2, bpm = ?3, key_camelot = ?4, \
+             duration_ms = ?5, genre = ?6, energy = ?7, tags = ?8, rating = ?9, isrc = ?10, \
+             spotify_id = ?11, notes = ?12, bpm_confidence = ?13, verified = ?14, \
+             name_norm = ?15, artist_norm = ?16, isrc_norm = ?17 \
+             WHERE id = (SELECT id FROM tracks ORDER BY id LIMIT 1 OFFSET ?18)",
+            rusqlite::params_from_iter(values),
+        )?;
+        Ok(())
+    }
+
+    fn delete(&mut self, position: usize) -> Result<()> {
+        self.connection.execute(
+            &format!("DELETE FROM tracks WHERE id = {ROW_AT}"),
+            params![position as i64],
+        )?;
+        Ok(())
+    }
+
+    fn save(&mut self) -> Result<()> {
+        if !self.connection.is_autocommit() {
+            self.connection.execute_batch("COMMIT")?;
+        }
+        Ok(())
+    }
+
+    fn lookup(&self, lookup: Lookup<'_>) -> Result<Vec<usize>> {
+        let (condition, values) = match lookup {
+            Lookup::Track { title, artist } => {
+                ("name_norm = ?1 AND artist_norm = ?2", vec![title, artist])
+            }
+            Lookup::Isrc(isrc) => ("isrc_norm = ?1", vec![isrc]),
+            Lookup::SpotifyId(id) => ("spotify_id = ?1", vec![id]),
+        };
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT (SELECT COUNT(*) FROM tracks AS earlier WHERE earlier.id < found.id) \
+             FROM tracks AS found WHERE {condition} ORDER BY found.id"
+        ))?;
+        let positions = statement.query_map(rusqlite::params_from_iter(values), |row| {
+            row.get::<_, i64>(0)
+        })?;
+        positions.map(|position| Ok(position? as usize)).collect()
+    }
+}
+
+/// Copies the database at `source_path` into a new file at `target_path`,
+/// converting between `local_db.json` and SQLite by file extension.
+pub(crate) fn run_convert(source_path: &str, target_path: &str) -> Result<()> {
+    let tracks = match (is_sqlite_path(source_path), is_sqlite_path(target_path)) {
+        (false, true) => {
+            let tracks = load_local_db_file(source_path)?.tracks_with_extra()?;
+            let mut store = SqliteStore::create(target_path)?;
+            store.import(&tracks)?;
+            store.save()?;
+            tracks
+        }
+        (true, false) => {
+            if Path::new(target_path).exists() {
+                bail!("{target_path} already exists; move it away first");
+            }
+            let tracks = SqliteStore::open(source_path)?.tracks_with_extra()?;
+            save_local_db_file(target_path, &LocalDbFile::from_tracks(tracks.clone())?)?;
+            tracks
+        }
+        _ => {
+            bail!("db convert copies between JSON and SQLite; pick a target with the other format")
+        }
+    };
+
+    println!(
+        "Copied {} tracks from {source_path} to {target_path}.",
+        tracks.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{EntrySelector, TrackFields};
+
+    fn temp_path(name: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("mix_sorter_{name}_{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path.to_str().unwrap().to_string()
+    }
+
+    const DB: &str = r#"{
+  "version": 2,
+  "tracks": [
+    {
+      "name": "Losing It",
+      "artist": "FISHER",
+      "bpm": 125,
+      "key_camelot": "10B",
+      "duration_ms": 248000,
+      "isrc": "AU-ABC-18-00001",
+      "comment": "festival edit"
+    },
+    {
+      "name": "Space Song",
+      "artist": "Beach House",
+      "bpm": 147.3,
+      "key_camelot": "5A",
+      "tags": [
+        "dreamy"
+      ],
+      "spotify_id": "7H0ya83CMmgFcOhw0UB6ow",
+      "verified": true
+    }
+  ]
+}
+"#;
+
+    #[test]
+    fn json_round_trips_through_sqlite() {
+        let path = temp_path("round_trip");
+        let tracks = LocalDbFile::from_json(DB)
+            .unwrap()
+            .tracks_with_extra()
+            .unwrap();
+        let mut store = SqliteStore::create(&path).unwrap();
+        store.import(&tracks).unwrap();
+        store.save().unwrap();
+
+        let exported = SqliteStore::open(&path)
+            .unwrap()
+            .tracks_with_extra()
+            .unwrap();
+        let json = LocalDbFile::from_tracks(exported)
+            .unwrap()
+            .to_json()
+            .unwrap();
+        assert_eq!(json, DB);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn edits_use_indexes_and_commit_only_on_save() {
+        let path = temp_path("edits");
+        let tracks = LocalDbFile::from_json(DB)
+            .unwrap()
+            .tracks_with_extra()
+            .unwrap();
+        let mut store = SqliteStore::create(&path).unwrap();
+        store.import(&tracks).unwrap();
+        store.save().unwrap();
+
+        let mut store = SqliteStore::open_for_edit(&path).unwrap();
+        assert_eq!(store.lookup(Lookup::Isrc("AUABC1800001")).unwrap(), vec![0]);
+        let found = store
+            .search("spotify:track:7H0ya83CMmgFcOhw0UB6ow")
+            .unwrap();
+        assert_eq!(found[0].0, 2);
+
+        let number = store
+            .edit(
+                &EntrySelector::Track("beach house - space song".to_string()),
+                &TrackFields {
+                    bpm: Some(146.0),
+                    ..TrackFields::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(number, 2);
+        let err = store
+            .add(LocalTrackData {
+                name: "Losing It".to_string(),
+                artist: "Fisher".to_string(),
+                bpm: 125.0,
+                key_camelot: "10B".to_string(),
+                duration_ms: Some(248_000),
+                ..LocalTrackData::default()
+            })
+            .unwrap_err();
+        assert!(err.to_string().contains("duplicate of #1"));
+        drop(store);
+
+        let mut store = SqliteStore::open_for_edit(&path).unwrap();
+        assert_eq!(store.track_at(1).unwrap().bpm, 147.3);
+        store.remove(&EntrySelector::Index(1)).unwrap();
+        store.save().unwrap();
+        drop(store);
+
+        let store = SqliteStore::open(&path).unwrap();
+        assert_eq!(store.len().unwrap(), 1);
+        assert_eq!(store.track_at(0).unwrap().name, "Space Song");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn malformed_tags_cell_is_an_error() {
+        let path = temp_path("bad_tags");
+        let tracks = LocalDbFile::from_json(DB)
+            .unwrap()
+            .tracks_with_extra()
+            .unwrap();
+        let mut store = SqliteStore::create(&path).unwrap();
+        store.import(&tracks).unwrap();
+        store.save().unwrap();
+        drop(store);
+
+        Connection::open(&path)
+            .unwrap()
+            .execute(
+                "UPDATE tracks SET tags = '[\"dreamy\"' WHERE name = 'Space Song'",
+                [],
+            )
+            .unwrap();
+        let store = SqliteStore::open(&path).unwrap();
+        assert!(store.track_at(1).is_err());
+        assert!(store.tracks_with_extra().is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,207 @@
+use crate::analysis::{for_each_spectrum, Audio, ANALYSIS_RATE};
+
+const FRAME_SIZE: usize = 1024;
+const HOP: usize = 128;
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 200.0;
+/// Tempos are pulled towards this, an octave either side halving the
+/// weight, so clear half- and double-time readings can still win.
+const PRIOR_BPM: f32 = 120.0;
+const REFINE_BEATS: usize = 4;
+
+fn frames_per_second() -> f32 {
+    ANALYSIS_RATE as f32 / HOP as f32
+}
+
+fn lag_to_bpm(lag: f32) -> f32 {
+    60.0 * frames_per_second() / lag
+}
+
+fn bpm_to_lag(bpm: f32) -> f32 {
+    60.0 * frames_per_second() / bpm
+}
+
+/// One tempo reading and how strongly the audio repeats at it (0-1).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TempoCandidate {
+    pub(crate) bpm: f32,
+    pub(crate) strength: f32,
+}
+
+/// The chosen tempo, with the readings a DJ might count instead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct TempoEstimate {
+    pub(crate) bpm: f32,
+    /// 0-1, stored as the local DB's `bpm_confidence`.
+    pub(crate) confidence: f32,
+    pub(crate) half_time: TempoCandidate,
+    pub(crate) double_time: TempoCandidate,
+}
+
+/// Spectral flux of the log-compressed spectrum, minus its moving average
+/// over about a second, so only sudden rises in energy remain.
+fn onset_envelope(audio: &Audio) -> Vec<f32> {
+    let mut flux = Vec::new();
+    let mut previous: Option<Vec<f32>> = None;
+    for_each_spectrum(audio, FRAME_SIZE, HOP, |magnitudes| {
+        let compressed: Vec<f32> = magnitudes.iter().map(|m| (1.0 + 100.0 * m).ln()).collect();
+        if let Some(previous) = &previous {
+            flux.push(
+                compressed
+                    .iter()
+                    .zip(previous)
+                    .map(|(now, before)| (now - before).max(0.0))
+                    .sum(),
+            );
+        }
+        previous = Some(compressed);
+    });
+
+    let radius = frames_per_second() as usize / 2;
+    (0..flux.len())
+        .map(|frame| {
+            let window = &flux[frame.saturating_sub(radius)..(frame + radius + 1).min(flux.len())];
+            let mean = window.iter().sum::<f32>() / window.len() as f32;
+            (flux[frame] - mean).max(0.0)
+        })
+        .collect()
+}
+
+/// Autocorrelation for lags `0..=max_lag`, each divided by the number of
+/// frame pairs it covers.
+fn autocorrelation(envelope: &[f32], max_lag: usize) -> Vec<f32> {
+    (0..=max_lag)
+        .map(|lag| {
+            let pairs = envelope.len() - lag;
+            envelope[..pairs]
+                .iter()
+                .zip(&envelope[lag..])
+                .map(|(a, b)| a * b)
+                .sum::<f32>()
+                / pairs as f32
+        })
+        .collect()
+}
+
+/// Linear interpolation between neighbouring lags.
+fn correlation_at(correlation: &[f32], lag: f32) -> f32 {
+    let below = lag.floor() as usize;
+    if below + 1 >= correlation.len() {
+        return correlation.last().copied().unwrap_or(0.0);
+    }
+    let fraction = lag - below as f32;
+    correlation[below] * (1.0 - fraction) + correlation[below + 1] * fraction
+}
+
+/// Reads the beat period from the peak [`REFINE_BEATS`] beats out, where a
+/// one-frame error is that many times smaller, with parabolic interpolation
+/// for sub-frame precision.
+fn refine_lag(correlation: &[f32], lag: usize) -> f32 {
+    let centre = lag * REFINE_BEATS;
+    let window = centre - REFINE_BEATS..=(centre + REFINE_BEATS).min(correlation.len() - 2);
+    let Some(peak) = window.max_by(|a, b| correlation[*a].total_cmp(&correlation[*b])) else {
+        return lag as f32;
+    };
+    let (left, middle, right) = (
+        correlation[peak - 1],
+        correlation[peak],
+        correlation[peak + 1],
+    );
+    let curvature = left - 2.0 * middle + right;
+    let offset = if curvature < 0.0 {
+        0.5 * (left - right) / curvature
+    } else {
+        0.0
+    };
+    (peak as f32 + offset) / REFINE_BEATS as f32
+}
+
+/// Estimates the tempo between 60 and 200 BPM from the periodicity of the
+/// onset envelope. A beat that also repeats at twice its period scores
+/// higher, which favours the bar-level pulse over off-beat hi-hats.
+/// Returns `None` for audio too short or too flat to have a tempo.
+pub(crate) fn estimate_tempo(audio: &Audio) -> Option<TempoEstimate> {
+    let envelope = onset_envelope(audio);
+    let max_lag = REFINE_BEATS * (bpm_to_lag(MIN_BPM).ceil() as usize + 1) + 1;
+    if envelope.len() < 2 * max_lag {
+        return None;
+    }
+    let correlation = autocorrelation(&envelope, max_lag);
+    let energy = correlation[0];
+    if energy <= f32::EPSILON {
+        return None;
+    }
+
+    let score = |lag: usize| {
+        let octaves = (lag_to_bpm(lag as f32) / PRIOR_BPM).log2();
+        let prior = (-0.5 * octaves * octaves / 0.72).exp();
+        prior * (correlation[lag] + 0.5 * correlation[2 * lag])
+    };
+    let lags = bpm_to_lag(MAX_BPM).floor() as usize..=bpm_to_lag(MIN_BPM).ceil() as usize;
+    let best = lags
+        .clone()
+        .max_by(|a, b| score(*a).total_cmp(&score(*b)))?;
+    if score(best) <= 0.0 {
+        return None;
+    }
+
+    let lag = refine_lag(&correlation, best);
+
+    let strength = |lag: f32| (correlation_at(&correlation, lag) / energy).clamp(0.0, 1.0);
+    Some(TempoEstimate {
+        bpm: lag_to_bpm(lag),
+        confidence: strength(lag),
+        half_time: TempoCandidate {
+            bpm: lag_to_bpm(2.0 * lag),
+            strength: strength(2.0 * lag),
+        },
+        double_time: TempoCandidate {
+            bpm: lag_to_bpm(lag / 2.0),
+            strength: strength(lag / 2.0),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 20 ms, 1 kHz blip on every beat.
+    fn click_track(bpm: f32, seconds: f32) -> Audio {
+        let rate = ANALYSIS_RATE as f32;
+        let beat = (60.0 / bpm * rate) as f64;
+        let mut samples = vec![0.0; (seconds * rate) as usize];
+        let mut onset = 0.0f64;
+        while (onset as usize) < samples.len() {
+            for (offset, sample) in samples[onset as usize..].iter_mut().take(220).enumerate() {
+                let t = offset as f32 / rate;
+                *sample = (2.0 * std::f32::consts::PI * 1_000.0 * t).sin() * (-t * 150.0).exp();
+            }
+            onset += beat;
+        }
+        Audio { samples }
+    }
+
+    #[test]
+    fn estimate_tempo_finds_click_track_tempo_and_octave_alternatives() {
+        for bpm in [90.0, 125.0, 174.0] {
+            let estimate = estimate_tempo(&click_track(bpm, 30.0)).unwrap();
+            assert!(
+                (estimate.bpm - bpm).abs() < 0.3,
+                "expected {bpm}, got {estimate:?}"
+            );
+            assert!(estimate.confidence > 0.5, "{estimate:?}");
+            assert!((estimate.half_time.bpm - bpm / 2.0).abs() < 0.5);
+            assert!((estimate.double_time.bpm - bpm * 2.0).abs() < 2.0);
+            assert!(estimate.double_time.strength < estimate.confidence);
+        }
+    }
+
+    #[test]
+    fn estimate_tempo_gives_up_on_silence() {
+        let silence = Audio {
+            samples: vec![0.0; ANALYSIS_RATE as usize * 10],
+        };
+        assert_eq!(estimate_tempo(&silence), None);
+    }
+}
@@ -0,0 +1,168 @@
+use anyhow::{Context, Result};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::fs;
+
+use crate::key::{Key, Mode};
+use crate::library::{LibraryImport, LibraryKey, LibraryTrack};
+use crate::MetadataSource;
+
+/// Traktor numbers keys 0-11 for C..B major and 12-23 for C..B minor.
+fn traktor_key(value: &str) -> Option<Key> {
+    let value: i32 = value.trim().parse().ok()?;
+    let mode = match value {
+        0..=11 => Mode::Major,
+        12..=23 => Mode::Minor,
+        _ => return None,
+    };
+    Key::new(value % 12, mode)
+}
+
+fn read_attributes(element: &BytesStart<'_>, mut read: impl FnMut(&[u8], String)) -> Result<()> {
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        read(
+            attribute.key.as_ref(),
+            attribute.unescape_value()?.into_owned(),
+        );
+    }
+    Ok(())
+}
+
+fn read_entry_child(element: &BytesStart<'_>, track: &mut LibraryTrack) -> Result<()> {
+    read_attributes(element, |key, value| {
+        match (element.name().as_ref(), key) {
+            (b"TEMPO", b"BPM") => track.bpm = value,
+            (b"MUSICAL_KEY", b"VALUE") => track.key = LibraryKey::read(&value, traktor_key),
+            // Whole seconds.
+            (b"INFO", b"PLAYTIME") => {
+                track.duration_ms = value
+                    .trim()
+                    .parse::<u32>()
+                    .ok()
+                    .and_then(|seconds| seconds.checked_mul(1_000))
+            }
+            (b"INFO", b"GENRE") => track.genre = value,
+            _ => {}
+        }
+    })
+}
+
+/// Reads every `ENTRY` in the `COLLECTION` of a Traktor `collection.nml`.
+/// Playlist entries, which only reference collection tracks, are ignored.
+pub(crate) fn parse_traktor_nml(content: &str) -> Result<LibraryImport> {
+    let mut reader = Reader::from_str(content);
+    let mut import = LibraryImport::default();
+    let mut in_collection = false;
+    let mut current: Option<LibraryTrack> = None;
+
+    loop {
+        let event = reader.read_event()?;
+        match &event {
+            Event::Start(element) if element.name().as_ref() == b"COLLECTION" => {
+                in_collection = true;
+            }
+            Event::End(element) if element.name().as_ref() == b"COLLECTION" => {
+                in_collection = false;
+            }
+            Event::Start(element) | Event::Empty(element)
+                if in_collection && element.name().as_ref() == b"ENTRY" =>
+            {
+                let mut track = LibraryTrack::default();
+                read_attributes(element, |key, value| match key {
+                    b"TITLE" => track.name = value,
+                    b"ARTIST" => track.artist = value,
+                    _ => {}
+                })?;
+                if matches!(event, Event::Empty(_)) {
+                    import.push(track, MetadataSource::Traktor);
+                } else {
+                    current = Some(track);
+                }
+            }
+            Event::End(element) if element.name().as_ref() == b"ENTRY" => {
+                if let Some(track) = current.take() {
+                    import.push(track, MetadataSource::Traktor);
+                }
+            }
+            Event::Start(element) | Event::Empty(element) => {
+                if let Some(track) = current.as_mut() {
+                    read_entry_child(element, track)?;
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(import)
+}
+
+pub(crate) fn load_traktor_nml(path: &str) -> Result<LibraryImport> {
+    let content = fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+    parse_traktor_nml(&content).with_context(|| format!("failed to parse Traktor NML at {path}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::describe_entries;
+
+    const COLLECTION: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="no" ?>
+<NML VERSION="19"><HEAD COMPANY="www.native-instruments.com" PROGRAM="Traktor"></HEAD>
+<COLLECTION ENTRIES="3">
+<ENTRY MODIFIED_DATE="2024/5/1" TITLE="Losing It" ARTIST="FISHER">
+<LOCATION DIR="/:Music/:" FILE="losing_it.mp3" VOLUME="Macintosh HD"></LOCATION>
+<INFO BITRATE="320000" GENRE="Tech House" KEY="3d" PLAYTIME="248" PLAYTIME_FLOAT="248.071"></INFO>
+<TEMPO BPM="125.000061" BPM_QUALITY="100.000000"></TEMPO>
+<MUSICAL_KEY VALUE="2"></MUSICAL_KEY>
+</ENTRY>
+<ENTRY TITLE="Glue" ARTIST="Bicep">
+<INFO PLAYTIME="269"></INFO>
+<TEMPO BPM="129.980000"></TEMPO>
+<MUSICAL_KEY VALUE="21"></MUSICAL_KEY>
+</ENTRY>
+<ENTRY TITLE="Unanalysed" ARTIST="Someone"><INFO PLAYTIME="200"></INFO></ENTRY>
+</COLLECTION>
+<PLAYLISTS><NODE TYPE="FOLDER" NAME="$ROOT"><SUBNODES COUNT="1">
+<NODE TYPE="PLAYLIST" NAME="Set"><PLAYLIST ENTRIES="1" TYPE="LIST">
+<ENTRY><PRIMARYKEY TYPE="TRACK" KEY="Macintosh HD/:Music/:losing_it.mp3"></PRIMARYKEY></ENTRY>
+</PLAYLIST></NODE></SUBNODES></NODE></PLAYLISTS>
+</NML>
+"#;
+
+    #[test]
+    fn traktor_key_maps_all_24_values() {
+        let camelot = |value: &str| traktor_key(value).map(Key::camelot);
+        assert_eq!(camelot("0").as_deref(), Some("8B"));
+        assert_eq!(camelot("2").as_deref(), Some("10B"));
+        assert_eq!(camelot("12").as_deref(), Some("5A"));
+        assert_eq!(camelot("21").as_deref(), Some("8A"));
+        assert_eq!(camelot("23").as_deref(), Some("10A"));
+        assert_eq!(camelot("24"), None);
+        let distinct: std::collections::HashSet<Key> = (0..24)
+            .filter_map(|value| traktor_key(&value.to_string()))
+            .collect();
+        assert_eq!(distinct.len(), 24);
+    }
+
+    #[test]
+    fn parse_traktor_nml_reads_collection_entries() {
+        let import = parse_traktor_nml(COLLECTION).unwrap();
+
+        assert_eq!(
+            describe_entries(&import),
+            vec![
+                "FISHER - Losing It | 125 | 10B | Some(248000) | Some(\"Tech House\")",
+                "Bicep - Glue | 129.98 | 8A | Some(269000) | None",
+            ]
+        );
+        assert_eq!(import.entries[0].source.kind, MetadataSource::Traktor);
+        assert_eq!(import.missing_bpm, 1);
+
+        let overlong =
+            parse_traktor_nml(&COLLECTION.replace(r#"PLAYTIME="269""#, r#"PLAYTIME="4294968""#))
+                .unwrap();
+        assert_eq!(overlong.entries[1].duration_ms, None);
+    }
+}
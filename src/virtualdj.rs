@@ -0,0 +1,135 @@
+use anyhow::{Context, Result};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::fs;
+
+use crate::key::Key;
+use crate::library::{LibraryImport, LibraryKey, LibraryTrack};
+use crate::MetadataSource;
+
+/// Fields of one `Song`, before choosing between user tags and analysis.
+#[derive(Debug, Default)]
+struct SongFields {
+    track: LibraryTrack,
+    /// `Scan Bpm`, in seconds per beat.
+    scan_beat_seconds: String,
+    scan_key: String,
+}
+
+impl SongFields {
+    /// User-edited `Tags` values win over `Scan` analysis.
+    fn into_track(self) -> LibraryTrack {
+        let mut track = self.track;
+        if track.bpm.trim().is_empty() {
+            track.bpm = match self.scan_beat_seconds.trim().parse::<f32>() {
+                Ok(seconds) if seconds > 0.0 => (60.0 / seconds).to_string(),
+                _ => self.scan_beat_seconds,
+            };
+        }
+        if track.key == LibraryKey::Missing {
+            track.key = LibraryKey::read(&self.scan_key, Key::parse);
+        }
+        track
+    }
+}
+
+fn read_song_child(element: &BytesStart<'_>, song: &mut SongFields) -> Result<()> {
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        let value = attribute.unescape_value()?.into_owned();
+        match (element.name().as_ref(), attribute.key.as_ref()) {
+            (b"Tags", b"Title") => song.track.name = value,
+            (b"Tags", b"Author") => song.track.artist = value,
+            (b"Tags", b"Genre") => song.track.genre = value,
+            (b"Tags", b"Bpm") => song.track.bpm = value,
+            (b"Tags", b"Key") => song.track.key = LibraryKey::read(&value, Key::parse),
+            (b"Scan", b"Bpm") => song.scan_beat_seconds = value,
+            (b"Scan", b"Key") => song.scan_key = value,
+            // Fractional seconds.
+            (b"Infos", b"SongLength") => {
+                song.track.duration_ms = value
+                    .trim()
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+                    .map(|seconds| (seconds * 1_000.0).round() as u32)
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Reads every `Song` of a VirtualDJ `database.xml`.
+pub(crate) fn parse_virtualdj_xml(content: &str) -> Result<LibraryImport> {
+    let mut reader = Reader::from_str(content);
+    let mut import = LibraryImport::default();
+    let mut current: Option<SongFields> = None;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) if element.name().as_ref() == b"Song" => {
+                current = Some(SongFields::default());
+            }
+            Event::End(element) if element.name().as_ref() == b"Song" => {
+                if let Some(song) = current.take() {
+                    import.push(song.into_track(), MetadataSource::VirtualDj);
+                }
+            }
+            Event::Start(element) | Event::Empty(element) => {
+                if let Some(song) = current.as_mut() {
+                    read_song_child(&element, song)?;
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(import)
+}
+
+pub(crate) fn load_virtualdj_xml(path: &str) -> Result<LibraryImport> {
+    let content = fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+    parse_virtualdj_xml(&content)
+        .with_context(|| format!("failed to parse VirtualDJ database at {path}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::describe_entries;
+
+    const DATABASE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<VirtualDJ_Database Version="2024">
+ <Song FilePath="C:\Music\losing_it.mp3" FileSize="9923456">
+  <Tags Author="FISHER" Title="Losing It" Genre="Tech House" Flag="1" />
+  <Infos SongLength="248.071000" Bitrate="320" />
+  <Scan Version="801" Bpm="0.480000" AltBpm="0.960000" Volume="1.0" Key="D" Flag="32768" />
+ </Song>
+ <Song FilePath="C:\Music\glue.mp3" FileSize="10832123">
+  <Tags Author="Bicep" Title="Glue" Bpm="130" Key="8A" />
+  <Infos SongLength="269.0" />
+  <Scan Bpm="0.461538" Key="Am" />
+ </Song>
+ <Song FilePath="C:\Music\new.mp3">
+  <Tags Author="Someone" Title="Not Scanned" />
+ </Song>
+</VirtualDJ_Database>
+"#;
+
+    #[test]
+    fn parse_virtualdj_xml_prefers_tags_over_scan_values() {
+        let import = parse_virtualdj_xml(DATABASE).unwrap();
+
+        assert_eq!(
+            describe_entries(&import),
+            vec![
+                "FISHER - Losing It | 125 | 10B | Some(248071) | Some(\"Tech House\")",
+                "Bicep - Glue | 130 | 8A | Some(269000) | None",
+            ]
+        );
+        assert_eq!(import.entries[0].source.kind, MetadataSource::VirtualDj);
+        assert_eq!(import.missing_bpm, 1);
+    }
+}